@@ -1,30 +1,635 @@
 //! Command-line interface definitions.
 
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf, time::Duration};
 
-use clap::Parser;
+use aws_mfa::{
+    credentials::StsEndpoint,
+    expiry, history, output,
+    remote::Remote,
+    session_store::CredentialStore,
+    token::{Providers, SecretReference, TokenLength},
+};
+use clap::{Parser, Subcommand};
 
 /// AWS MFA credential updater.
 ///
 /// Refreshes AWS credentials by obtaining temporary session tokens using MFA.
-/// Reads long-term credentials from `[default-long-term]` profile and writes
-/// temporary credentials to `[default]` profile.
-#[derive(Parser)]
+/// Reads long-term credentials from the `[<profile>-long-term]` section and writes
+/// temporary credentials to the `[<profile>]` section.
+#[derive(Clone, Parser)]
 #[command(author, version, about)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to AWS credentials file [default: ~/.aws/credentials]
-    #[arg(short, long, env = "AWS_SHARED_CREDENTIALS_FILE")]
+    #[arg(short, long, env = "AWS_SHARED_CREDENTIALS_FILE", global = true)]
     pub credentials_path: Option<PathBuf>,
 
-    /// Session duration in seconds (900-129600)
-    #[arg(short, long, env = "AWS_SESSION_DURATION", default_value = "43200")]
-    pub duration: u32,
+    /// Profile to refresh [default: $AWS_PROFILE, or a choice among the profiles with long-term
+    /// credentials, or default]
+    #[arg(short, long, global = true)]
+    pub profile: Option<String>,
+
+    /// Section to write the session credentials to [default: aws_mfa_target_profile of the
+    /// profile, or the profile name]
+    #[arg(long, env = "AWS_MFA_TARGET_PROFILE", global = true)]
+    pub target_profile: Option<String>,
+
+    /// Suffix of the section holding the long-term credentials, or `none` to use the profile itself
+    #[arg(
+        long,
+        env = "AWS_MFA_LONG_TERM_SUFFIX",
+        default_value = "-long-term",
+        allow_hyphen_values = true,
+        global = true
+    )]
+    pub long_term_suffix: String,
+
+    /// Suffix of the section the session credentials are written to
+    #[arg(
+        long,
+        env = "AWS_MFA_SHORT_TERM_SUFFIX",
+        default_value = "",
+        allow_hyphen_values = true,
+        global = true
+    )]
+    pub short_term_suffix: String,
+
+    /// Session duration in seconds (900-129600; up to 43200 with a role, 3600 with a role chain)
+    /// [default: aws_mfa_duration of the profile, or 43200]
+    #[arg(short, long, env = "AWS_SESSION_DURATION", global = true)]
+    pub duration: Option<u32>,
+
+    /// Duration in seconds of the MFA session roles are assumed with (900-129600), see
+    /// --via-session [default: aws_mfa_session_duration of the profile, or --duration]
+    #[arg(long, env = "AWS_MFA_SESSION_DURATION", global = true)]
+    pub session_duration: Option<u32>,
+
+    /// Duration in seconds of the role sessions (900-43200, 3600 with a role chain)
+    /// [default: aws_mfa_role_duration of the profile, or --duration]
+    #[arg(long, env = "AWS_MFA_ROLE_DURATION", global = true)]
+    pub role_duration: Option<u32>,
+
+    /// Fail when the duration exceeds what the mode allows, instead of lowering it
+    #[arg(long, global = true)]
+    pub strict_duration: bool,
+
+    /// Retry with the longest duration allowed when STS refuses the one requested, e.g. beyond
+    /// the maximum session duration of the role, sending the MFA code again while it is valid
+    #[arg(long, env = "AWS_MFA_AUTO_DOWNGRADE_DURATION", global = true)]
+    pub auto_downgrade_duration: bool,
+
+    /// Skip a refresh while the session is valid for at least this long, e.g. 10m or 1h, and
+    /// reuse it; the same goes for exec and server
+    #[arg(
+        long,
+        alias = "refresh-before",
+        env = "AWS_MFA_MIN_REMAINING",
+        value_name = "DURATION",
+        default_value = "10m",
+        value_parser = expiry::parse_min_remaining,
+        global = true
+    )]
+    pub min_remaining: Duration,
+
+    /// Give up on an STS request after this many seconds, retries included
+    #[arg(long, env = "AWS_MFA_STS_TIMEOUT", default_value = "15", global = true)]
+    pub sts_timeout: u64,
+
+    /// Region of the STS and IAM endpoints, and of the console page to land on [default:
+    /// AWS_REGION, AWS_DEFAULT_REGION, region of the profile, or of the MFA device's partition]
+    #[arg(long, global = true)]
+    pub region: Option<String>,
+
+    /// STS endpoint to use: regional (the default), or global for sts.amazonaws.com, as in the
+    /// standard partition only
+    #[arg(long, env = "AWS_STS_REGIONAL_ENDPOINTS", default_value = "regional", global = true)]
+    pub sts_endpoint: StsEndpoint,
+
+    /// PEM file of root certificates to trust in addition to the system's, e.g. of a proxy that
+    /// intercepts TLS
+    #[arg(long, env = "AWS_CA_BUNDLE", global = true)]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Use the MFA device whose name contains this, when the profile has several
+    #[arg(long, env = "AWS_MFA_DEVICE_NAME", global = true)]
+    pub mfa_device_name: Option<String>,
+
+    /// Use this MFA device, an ARN or a hardware token serial number, instead of the configured
+    /// ones for this run
+    #[arg(long, value_name = "ARN_OR_SERIAL", global = true, conflicts_with = "mfa_device_name")]
+    pub mfa_device: Option<String>,
 
     /// 1Password account for automatic MFA token retrieval
-    #[arg(long, env = "AWS_MFA_UPDATER_OP_ACCOUNT")]
+    #[arg(long, env = "AWS_MFA_UPDATER_OP_ACCOUNT", global = true)]
     pub op_account: Option<String>,
 
+    /// 1Password CLI to run, instead of looking for op (and on WSL, op.exe) on the PATH
+    #[arg(long, env = "AWS_MFA_UPDATER_OP_PATH", value_name = "PATH", global = true)]
+    pub op_path: Option<PathBuf>,
+
     /// 1Password item name containing the TOTP
-    #[arg(long, env = "AWS_MFA_UPDATER_OP_ITEM_NAME")]
+    #[arg(long, env = "AWS_MFA_UPDATER_OP_ITEM_NAME", global = true)]
     pub op_item_name: Option<String>,
+
+    /// 1Password secret reference to the one-time password, read with `op read`, e.g.
+    /// "op://Private/AWS/one-time password"; replaces --op-account and --op-item-name
+    #[arg(
+        long,
+        env = "AWS_MFA_UPDATER_OP_SECRET_REF",
+        value_name = "URI",
+        global = true,
+        conflicts_with_all = ["op_account", "op_item_name"]
+    )]
+    pub op_secret_reference: Option<SecretReference>,
+
+    /// Bitwarden item, by name or ID, whose TOTP is read with `bw get totp`; the vault has to be
+    /// unlocked, with BW_SESSION set [default: aws_mfa_bw_item of the profile]
+    #[arg(long, env = "AWS_MFA_BW_ITEM", value_name = "ITEM", global = true)]
+    pub bw_item: Option<String>,
+
+    /// Entry of the password store whose one-time password is read with `pass otp`, from the
+    /// pass-otp extension [default: aws_mfa_pass_entry of the profile]
+    #[arg(long, env = "AWS_MFA_PASS_ENTRY", value_name = "ENTRY", global = true)]
+    pub pass_entry: Option<String>,
+
+    /// KeePassXC database holding the TOTP, read with keepassxc-cli
+    #[arg(long, env = "AWS_MFA_KEEPASS_DB", requires = "keepass_entry", global = true)]
+    pub keepass_db: Option<PathBuf>,
+
+    /// Entry of the KeePassXC database with the TOTP
+    #[arg(long, env = "AWS_MFA_KEEPASS_ENTRY", requires = "keepass_db", global = true)]
+    pub keepass_entry: Option<String>,
+
+    /// Key file unlocking the KeePassXC database, with or without a password
+    #[arg(long, env = "AWS_MFA_KEEPASS_KEYFILE", requires = "keepass_db", global = true)]
+    pub keepass_keyfile: Option<PathBuf>,
+
+    /// OATH account of a YubiKey whose code is read with `ykman oath accounts code`, e.g.
+    /// "AWS:work" [default: aws_mfa_yubikey_account of the profile]
+    #[arg(long, env = "AWS_MFA_YUBIKEY_ACCOUNT", value_name = "NAME", global = true)]
+    pub yubikey_account: Option<String>,
+
+    /// Seconds the YubiKey is given to be touched, for accounts that require it
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 20,
+        env = "AWS_MFA_YUBIKEY_TIMEOUT",
+        global = true
+    )]
+    pub yubikey_timeout: u64,
+
+    /// Shell command printing the current MFA code, e.g. `gopass otp -o aws/work`
+    /// [default: aws_mfa_token_command of the profile]
+    #[arg(long, value_name = "COMMAND", env = "AWS_MFA_TOKEN_COMMAND", global = true)]
+    pub token_command: Option<String>,
+
+    /// MFA code to use as is, for CI and scripts, skipping the password managers and the
+    /// prompt; a code of the wrong format fails the run
+    #[arg(long, value_name = "CODE", env = "AWS_MFA_TOKEN", global = true)]
+    pub token: Option<String>,
+
+    /// Read the long-term keys from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, and the MFA
+    /// device from AWS_MFA_DEVICE, even if the long-term section exists
+    #[arg(long, global = true)]
+    pub from_env: bool,
+
+    /// Store long-term keys entered with --fix or the setup wizard in the system keyring instead
+    /// of the credentials file; keys already there stay there
+    #[arg(long, env = "AWS_MFA_USE_KEYRING", global = true)]
+    pub use_keyring: bool,
+
+    /// 1Password item holding the long-term keys, read with `op item get` on each run instead of
+    /// the long-term section, so that they are never stored on disk
+    #[arg(
+        long,
+        env = "AWS_MFA_UPDATER_OP_CREDENTIALS_ITEM",
+        value_name = "ITEM",
+        global = true,
+        conflicts_with = "from_env"
+    )]
+    pub op_credentials_item: Option<String>,
+
+    /// Label of the field of --op-credentials-item holding the access key ID
+    #[arg(long, value_name = "LABEL", default_value = "access key id", global = true)]
+    pub op_access_key_field: String,
+
+    /// Label of the field of --op-credentials-item holding the secret access key
+    #[arg(long, value_name = "LABEL", default_value = "secret access key", global = true)]
+    pub op_secret_key_field: String,
+
+    /// Label of the field of --op-credentials-item holding the MFA device, which may be missing
+    #[arg(long, value_name = "LABEL", default_value = "mfa serial", global = true)]
+    pub op_mfa_device_field: String,
+
+    /// age identity file the long-term keys are encrypted to, instead of a passphrase
+    #[arg(long, env = "AWS_MFA_AGE_IDENTITY", global = true)]
+    pub age_identity: Option<PathBuf>,
+
+    /// Give up when no MFA code is entered within this many seconds
+    #[arg(long, env = "AWS_MFA_PROMPT_TIMEOUT", global = true)]
+    pub prompt_timeout: Option<u64>,
+
+    /// Number of digits of MFA codes, 6 to 8, or auto for any of them; codes of another length
+    /// are refused, whether typed or fetched from a password manager
+    #[arg(
+        long,
+        env = "AWS_MFA_TOKEN_LENGTH",
+        value_name = "DIGITS",
+        default_value = "6",
+        global = true
+    )]
+    pub token_length: TokenLength,
+
+    /// Wait for the next code when the one from the keyring or a password manager is valid for
+    /// fewer than this many seconds, so that it doesn't expire before STS checks it; 0 takes any
+    #[arg(
+        long,
+        env = "AWS_MFA_MIN_CODE_VALIDITY",
+        value_name = "SECONDS",
+        default_value = "5",
+        value_parser = clap::value_parser!(u64).range(0..30),
+        global = true
+    )]
+    pub min_code_validity: u64,
+
+    /// Seconds the local clock is ahead of AWS, or negative if behind, as `doctor` reports it;
+    /// codes generated from the keyring secret are computed for the corrected time
+    #[arg(
+        long,
+        env = "AWS_MFA_TOTP_SKEW",
+        value_name = "SECONDS",
+        default_value = "0",
+        allow_hyphen_values = true,
+        global = true
+    )]
+    pub totp_skew: i64,
+
+    /// Sources of MFA codes to ask in order, comma-separated: keyring, op, bw, pass, keepass,
+    /// yubikey, command and prompt; leave out prompt to never ask for a code [default:
+    /// aws_mfa_providers of the profile, or keyring,op,bw,pass,keepass,yubikey,command,prompt]
+    #[arg(long, value_name = "LIST", env = "AWS_MFA_PROVIDERS", global = true)]
+    pub providers: Option<Providers>,
+
+    /// Ask for the MFA code in a dialog instead of on the terminal; without a terminal, a dialog
+    /// is shown anyway where a desktop is available
+    #[arg(long, env = "AWS_MFA_GUI_PROMPT", global = true, conflicts_with = "non_interactive")]
+    pub gui_prompt: bool,
+
+    /// Fail instead of asking for the missing settings when the profile isn't set up yet
+    #[arg(long, env = "AWS_MFA_NO_WIZARD", global = true)]
+    pub no_wizard: bool,
+
+    /// Never ask for anything: fail with the choices instead of showing a menu, e.g. when several
+    /// profiles have long-term credentials, and with exit code 8 when no password manager
+    /// provides the MFA code, without prompting or reading stdin; the default when neither stdin
+    /// nor stderr is a terminal
+    #[arg(long, env = "AWS_MFA_NON_INTERACTIVE", global = true)]
+    pub non_interactive: bool,
+
+    /// Ask as usual even when neither stdin nor stderr is a terminal, e.g. to pipe the MFA code
+    /// to stdin from a script
+    #[arg(long, env = "AWS_MFA_INTERACTIVE", global = true, conflicts_with = "non_interactive")]
+    pub interactive: bool,
+
+    /// Show desktop notifications for refresh results and upcoming expiry
+    #[arg(long, env = "AWS_MFA_NOTIFY", global = true)]
+    pub notify: bool,
+
+    /// Assume this role with the MFA code instead of getting a plain session token; a
+    /// comma-separated list is assumed as a chain
+    #[arg(long, env = "AWS_MFA_ROLE_ARN", value_delimiter = ',', global = true)]
+    pub role_arn: Vec<String>,
+
+    /// Get an MFA session token first and assume the role with it
+    #[arg(long, requires = "role_arn", global = true)]
+    pub via_session: bool,
+
+    /// Also write the intermediate session to [<profile>-session], and assume the role with it
+    /// instead of asking for an MFA code while it is valid
+    #[arg(long, requires = "via_session", global = true)]
+    pub write_session: bool,
+
+    /// External ID required by the role [default: external_id of the profile]
+    #[arg(long, global = true)]
+    pub external_id: Option<String>,
+
+    /// Role session name [default: role_session_name of the profile, or aws-mfa-<username>]
+    #[arg(long, global = true)]
+    pub role_session_name: Option<String>,
+
+    /// Session tag as key=value (repeatable) [default: session_tags of the profile]
+    #[arg(long = "session-tag", global = true)]
+    pub session_tags: Vec<String>,
+
+    /// Key of a session tag that persists through role chaining (repeatable)
+    #[arg(long = "transitive-tag-key", global = true)]
+    pub transitive_tag_keys: Vec<String>,
+
+    /// Inline session policy (JSON) that scopes the role credentials down
+    #[arg(long, global = true)]
+    pub session_policy_file: Option<PathBuf>,
+
+    /// Managed policy that scopes the role credentials down (repeatable)
+    #[arg(long = "session-policy-arn", global = true)]
+    pub session_policy_arns: Vec<String>,
+
+    /// Get federated user credentials with GetFederationToken under this name instead
+    #[arg(long, conflicts_with = "role_arn")]
+    pub federation_name: Option<String>,
+
+    /// Inline session policy (JSON) for the federated user
+    #[arg(long, requires = "federation_name")]
+    pub policy_file: Option<PathBuf>,
+
+    /// Managed session policy for the federated user (repeatable)
+    #[arg(long = "policy-arn", requires = "federation_name")]
+    pub policy_arns: Vec<String>,
+
+    /// Also write the role credentials to the AWS CLI cache (~/.aws/cli/cache) for the profile,
+    /// so that `aws` doesn't ask for another MFA code
+    #[arg(long, requires = "role_arn", global = true)]
+    pub write_cli_cache: bool,
+
+    /// Also write the session to the credentials file of this host over ssh (repeatable); the
+    /// path defaults to the remote ~/.aws/credentials
+    #[arg(long = "push-remote", value_name = "USER@HOST[:PATH]", global = true)]
+    pub push_remotes: Vec<Remote>,
+
+    /// Don't write the session token a second time as aws_security_token, which only old tools
+    /// read
+    #[arg(long, global = true)]
+    pub no_legacy_token: bool,
+
+    /// Key the session expiration is written under (repeatable), e.g. aws_session_expiration or
+    /// x_security_token_expires [default: expiration]
+    #[arg(long = "expiration-key", value_name = "NAME", global = true)]
+    pub expiration_keys: Vec<String>,
+
+    /// Non-secret keys of the long-term section to copy to the session section on every
+    /// refresh, comma-separated, e.g. region,output [default: aws_mfa_copy_keys of the profile]
+    #[arg(
+        long,
+        value_name = "KEYS",
+        env = "AWS_MFA_COPY_KEYS",
+        value_delimiter = ',',
+        global = true
+    )]
+    pub copy_keys: Vec<String>,
+
+    /// Where sessions are kept: file (the credentials file), keyring (the system keyring, never
+    /// touching the disk) or both; reading and clearing them go to the same place [default:
+    /// aws_mfa_credential_store of the profile, or file]
+    #[arg(long, value_name = "STORE", env = "AWS_MFA_CREDENTIAL_STORE", global = true)]
+    pub credential_store: Option<CredentialStore>,
+
+    /// Also write the session to this dotenv file (repeatable), e.g. the env_file of a compose
+    /// project, updating the AWS_* variables in place
+    #[arg(long = "write-dotenv", value_name = "PATH", global = true)]
+    pub dotenv_paths: Vec<PathBuf>,
+
+    /// Write the session to this file when the credentials file can't be written [default: a
+    /// new file in ~/.cache/aws-mfa]
+    #[arg(long, value_name = "PATH", env = "AWS_MFA_FALLBACK_PATH", global = true)]
+    pub fallback_path: Option<PathBuf>,
+
+    /// Print the session to stderr, without asking first, when neither the credentials file nor
+    /// the fallback can be written
+    #[arg(long, env = "AWS_MFA_PRINT_ON_WRITE_FAILURE", global = true)]
+    pub print_on_write_failure: bool,
+
+    /// Command to run through the shell after every refresh, with the AWS_MFA_EVENT,
+    /// AWS_MFA_PROFILE, AWS_MFA_EXPIRATION and AWS_MFA_MODE environment variables set [default:
+    /// aws_mfa_on_refresh_hook of the profile]
+    #[arg(long, value_name = "CMD", env = "AWS_MFA_ON_REFRESH_HOOK", global = true)]
+    pub on_refresh_hook: Option<String>,
+
+    /// Command to run through the shell in watch mode a few minutes before the session expires,
+    /// with the same environment as --on-refresh-hook [default: aws_mfa_on_expiring_hook of the
+    /// profile]
+    #[arg(long, value_name = "CMD", env = "AWS_MFA_ON_EXPIRING_HOOK", global = true)]
+    pub on_expiring_hook: Option<String>,
+
+    /// Seconds after which a hook still running is killed
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 10,
+        env = "AWS_MFA_HOOK_TIMEOUT",
+        global = true
+    )]
+    pub hook_timeout: u64,
+
+    /// Print the new credentials to stdout instead of writing them to the credentials file; with
+    /// --write-dotenv, only the dotenv files are written
+    #[arg(long, visible_alias = "stdout", conflicts_with = "write_session")]
+    pub no_write: bool,
+
+    /// Format of the credentials printed with --no-write: ini (the default), json (as for
+    /// credential_process) or env. Without --no-write, json prints a summary of the refresh
+    #[arg(long)]
+    pub output: Option<output::Format>,
+
+    /// Interactively re-enter the long-term credentials before refreshing
+    #[arg(long)]
+    pub fix: bool,
+
+    /// Refresh even if the session is valid for at least --min-remaining
+    #[arg(long, conflicts_with = "dry_run")]
+    pub force: bool,
+
+    /// Move the long-term keys `aws configure` left in the profile's own section to its
+    /// long-term section before refreshing, without asking
+    #[arg(long, conflicts_with_all = ["fix", "dry_run"])]
+    pub migrate: bool,
+
+    /// Check the configuration and show the changes a refresh would make to the credentials
+    /// file, without asking for a code or writing anything
+    #[arg(long, conflicts_with_all = ["no_write", "fix"])]
+    pub dry_run: bool,
+
+    /// Refresh the context of this name, an [aws-mfa-context <name>] section of the AWS config
+    /// file with its own credentials file, profile and settings (repeatable)
+    #[arg(long = "context", value_name = "NAME", conflicts_with = "all_contexts")]
+    pub contexts: Vec<String>,
+
+    /// Refresh every context of the AWS config file, in the order of the file
+    #[arg(long)]
+    pub all_contexts: bool,
+
+    /// Stop at the first context that fails, instead of refreshing the others
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Fail when the credentials file is accessible by other users or its directory is
+    /// world-writable, instead of warning
+    #[arg(long, global = true, conflicts_with = "fix_permissions")]
+    pub strict_permissions: bool,
+
+    /// Restrict the credentials file to 0600 and a world-writable directory to 0700
+    #[arg(long, global = true)]
+    pub fix_permissions: bool,
+
+    /// Verify the new credentials with STS GetCallerIdentity after writing them, or with
+    /// --dry-run, the long-term credentials
+    #[arg(long, global = true)]
+    pub verify: bool,
+
+    /// Show expirations in UTC instead of local time
+    #[arg(long, global = true)]
+    pub utc: bool,
+
+    /// Log only warnings and errors, and show no progress spinner
+    #[arg(short, long, env = "AWS_MFA_QUIET", global = true)]
+    pub quiet: bool,
+}
+
+/// Subcommands. Without one, the credentials are refreshed once.
+#[derive(Clone, Subcommand)]
+pub enum Command {
+    /// Commands run with the logger and the async runtime.
+    #[command(flatten)]
+    Act(Action),
+
+    /// Commands only reading the session, fast enough for shell prompts.
+    #[command(flatten)]
+    Peek(Peek),
+}
+
+/// Subcommands acting on the profile.
+#[derive(Clone, Subcommand)]
+pub enum Action {
+    /// Keep running and refresh the credentials shortly before they expire
+    Watch {
+        /// Refresh this many seconds before the recorded expiration
+        #[arg(long, env = "AWS_MFA_WATCH_LEAD_TIME", default_value = "600")]
+        lead_time: u64,
+    },
+
+    /// Serve the credentials on localhost like the EC2 instance metadata service
+    Server {
+        /// Port to listen on (127.0.0.1 only)
+        #[arg(long, env = "AWS_MFA_SERVER_PORT", default_value = "9911")]
+        port: u16,
+
+        /// Secret path prefix clients must use [default: random per start]
+        #[arg(long, env = "AWS_MFA_SERVER_SECRET")]
+        secret: Option<String>,
+    },
+
+    /// Replace the long-term access key with a new one and delete the old key
+    RotateKey {
+        /// Keep the old access key instead of deleting it
+        #[arg(long)]
+        keep_old: bool,
+    },
+
+    /// Run a command with the session credentials in its environment, refreshing them first if
+    /// they expire soon; a new session is handed to the command only, never written to disk
+    Exec {
+        /// Store a new session where a refresh would, for the next run to reuse
+        #[arg(long, env = "AWS_MFA_EXEC_WRITE")]
+        write: bool,
+
+        /// Accepted for scripts written when exec stored new sessions by default
+        #[arg(long, hide = true, conflicts_with = "write")]
+        no_write: bool,
+
+        /// Command to run, after `--`
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<OsString>,
+    },
+
+    /// Print the session as the JSON document credential_process expects, for `credential_process
+    /// = aws-mfa credential-process` in ~/.aws/config: a valid session is reused, and a new one is
+    /// stored so that the next call needs no MFA code
+    CredentialProcess,
+
+    /// Refresh the role or federated user credentials and print a URL signing in to the AWS
+    /// console with them, valid for 15 minutes
+    Console {
+        /// Open the URL in the default browser instead of printing it
+        #[arg(long, overrides_with = "no_open")]
+        open: bool,
+
+        /// Print the URL (the default)
+        #[arg(long, overrides_with = "open")]
+        no_open: bool,
+    },
+
+    /// Remove the session credentials of the profile, keeping the long-term section
+    Clear {
+        /// Clear every section with session credentials instead of only the profile's
+        #[arg(long)]
+        all: bool,
+
+        /// Only clear sessions that have expired
+        #[arg(long)]
+        expired_only: bool,
+    },
+
+    /// Set up the profile without refreshing: move the long-term keys `aws configure` left in its
+    /// section to the long-term section, ask for the MFA device or whatever else is missing, and
+    /// make the credentials file readable by its owner only
+    Init,
+
+    /// Check the setup of the profile and print what to fix, without refreshing anything
+    Doctor {
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the sessions obtained so far, newest last, of the profile if --profile is given
+    History {
+        /// Only show sessions obtained within this long, e.g. 12h, 7d or 4w
+        #[arg(long, value_parser = history::parse_age)]
+        since: Option<Duration>,
+
+        /// Print the records as JSON lines, as they are stored
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Store the TOTP secret of the MFA device in the system keyring, as base32 or an otpauth://
+    /// URI, so that refreshes generate the codes from it
+    Enroll,
+
+    /// Delete the TOTP secret of the profile from the system keyring
+    Unenroll,
+
+    /// Print the current code of the TOTP secret stored with enroll, without refreshing
+    Code {
+        /// Also print how many seconds the code stays valid, after it
+        #[arg(long)]
+        window: bool,
+    },
+
+    /// Encrypt the long-term access keys with age, to a passphrase or --age-identity
+    EncryptLongTerm,
+
+    /// Write the encrypted long-term access keys back in plaintext
+    DecryptLongTerm,
+
+    /// Move the long-term access keys from the credentials file to the system keyring
+    ImportKeyring,
+}
+
+/// Subcommands only reading the session of the profile.
+#[derive(Clone, Subcommand)]
+pub enum Peek {
+    /// Print how long the session of the profile is valid, e.g. 3h12m, 47m or EXPIRED, for shell
+    /// prompts: only the credentials file is read. Exits with 1 once expired, and with 2 and
+    /// nothing printed without a session
+    Remaining {
+        /// Print the number of seconds left instead
+        #[arg(long)]
+        porcelain: bool,
+    },
+
+    /// List the sessions in the credentials file with their access key, expiration and validity
+    /// left, marking the profile's with *. Exits like remaining does for the profile's session
+    Status,
 }
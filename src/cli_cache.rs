@@ -0,0 +1,232 @@
+//! Entries in the AWS CLI's credential cache (`~/.aws/cli/cache`).
+//!
+//! For a profile with `role_arn`, the AWS CLI (through botocore) caches the AssumeRole response
+//! in a JSON file named after a hash of the profile's AssumeRole parameters, and only asks for an
+//! MFA code when that entry is missing or expired. Writing the role credentials aws-mfa obtained
+//! under the same name spares that prompt.
+
+use std::{
+    fmt::Write as _,
+    fs::{create_dir_all, remove_file, rename},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use aws_sdk_sts::types;
+use aws_smithy_types::date_time::Format;
+use dirs::home_dir;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{config::AwsConfig, credentials_file::create_private};
+
+/// AssumeRole parameters of a profile in the AWS config file, which botocore hashes into the
+/// name of the cache entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheKey {
+    /// `role_arn` of the profile.
+    pub role_arn: String,
+    /// `role_session_name`, if set.
+    pub role_session_name: Option<String>,
+    /// `external_id`, if set.
+    pub external_id: Option<String>,
+    /// `mfa_serial`, if set.
+    pub mfa_serial: Option<String>,
+    /// `duration_seconds`, if set.
+    pub duration_seconds: Option<u32>,
+}
+
+impl CacheKey {
+    /// Reads the parameters of `profile` from `config`; `None` if the profile has no `role_arn`.
+    pub fn from_config(config: &AwsConfig, profile: &str) -> Result<Option<Self>> {
+        let Some(role_arn) = config.get(profile, "role_arn") else {
+            return Ok(None);
+        };
+        let get = |key| config.get(profile, key).map(str::to_string);
+        let duration_seconds = get("duration_seconds")
+            .map(|d| d.parse())
+            .transpose()
+            .with_context(|| format!("Invalid duration_seconds in profile {profile}"))?;
+
+        Ok(Some(Self {
+            role_arn: role_arn.to_string(),
+            role_session_name: get("role_session_name"),
+            external_id: get("external_id"),
+            mfa_serial: get("mfa_serial"),
+            duration_seconds,
+        }))
+    }
+
+    /// Returns the name botocore gives the cache entry, without `.json`.
+    ///
+    /// botocore hashes the parameters as Python's `json.dumps(args, sort_keys=True)` renders them,
+    /// so that form is reproduced exactly:
+    ///
+    /// ```
+    /// use aws_mfa::cli_cache::CacheKey;
+    ///
+    /// let key = CacheKey { role_arn: "arn:aws:iam::foo-role".into(), ..Default::default() };
+    /// assert_eq!(key.hash(), "3f8e35c8dca6211d496e830a2de723b2387921e3");
+    ///
+    /// let key = CacheKey {
+    ///     role_arn: "arn:aws:iam::123456789012:role/admin".into(),
+    ///     mfa_serial: Some("arn:aws:iam::123456789012:mfa/me".into()),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(key.hash(), "b26e45a5efba11b34b676c5657f44efc9db2add0");
+    ///
+    /// let key = CacheKey {
+    ///     role_session_name: Some("me".into()),
+    ///     external_id: Some("ext-ü".into()),
+    ///     duration_seconds: Some(3600),
+    ///     ..key
+    /// };
+    /// assert_eq!(key.hash(), "0368cd059ca63919e01ea453003672c4a9b7fa9b");
+    /// ```
+    pub fn hash(&self) -> String {
+        // Sorted by key, as `sort_keys=True` does.
+        let mut fields = Vec::new();
+        if let Some(duration) = self.duration_seconds {
+            fields.push(("DurationSeconds", duration.to_string()));
+        }
+        if let Some(external_id) = &self.external_id {
+            fields.push(("ExternalId", python_json_string(external_id)));
+        }
+        fields.push(("RoleArn", python_json_string(&self.role_arn)));
+        if let Some(name) = &self.role_session_name {
+            fields.push(("RoleSessionName", python_json_string(name)));
+        }
+        if let Some(serial) = &self.mfa_serial {
+            fields.push(("SerialNumber", python_json_string(serial)));
+        }
+
+        let json = fields
+            .iter()
+            .map(|(key, value)| format!("\"{key}\": {value}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let digest = Sha1::digest(format!("{{{json}}}").as_bytes());
+
+        // botocore also replaces characters unsafe in file names, which a hex digest never has.
+        digest.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    }
+}
+
+/// A cache entry, in the shape of the AssumeRole response botocore stores.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Entry<'a> {
+    credentials: EntryCredentials<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EntryCredentials<'a> {
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    session_token: &'a str,
+    expiration: String,
+}
+
+/// Returns the AWS CLI's cache directory, `~/.aws/cli/cache`.
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(home_dir()
+        .context("Could not locate the AWS CLI cache: the home directory is unknown")?
+        .join(".aws/cli/cache"))
+}
+
+/// Writes `session` to the entry for `key` in `dir`, replacing any previous one, and returns its
+/// path. The file is readable by the owner only.
+///
+/// ```
+/// use aws_mfa::cli_cache::{CacheKey, write};
+/// use aws_sdk_sts::types::Credentials;
+/// use aws_smithy_types::DateTime;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-cli-cache-{}", std::process::id()));
+/// let key = CacheKey { role_arn: "arn:aws:iam::foo-role".into(), ..Default::default() };
+/// let session = |token: &str| {
+///     Credentials::builder()
+///         .access_key_id("ASIAEXAMPLE")
+///         .secret_access_key("secret")
+///         .session_token(token)
+///         .expiration(DateTime::from_secs(1_714_764_064))
+///         .build()
+/// };
+///
+/// write(&dir, &key, &session("stale")?)?;
+/// let path = write(&dir, &key, &session("fresh")?)?;
+/// assert_eq!(path, dir.join("3f8e35c8dca6211d496e830a2de723b2387921e3.json"));
+/// assert_eq!(
+///     std::fs::read_to_string(&path)?,
+///     r#"{"Credentials":{"AccessKeyId":"ASIAEXAMPLE","SecretAccessKey":"secret","#.to_owned()
+///         + r#""SessionToken":"fresh","Expiration":"2024-05-03T19:21:04Z"}}"#
+/// );
+/// # #[cfg(unix)]
+/// # {
+/// use std::os::unix::fs::PermissionsExt;
+/// assert_eq!(std::fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+/// # }
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn write(dir: &Path, key: &CacheKey, session: &types::Credentials) -> Result<PathBuf> {
+    let entry = Entry {
+        credentials: EntryCredentials {
+            access_key_id: session.access_key_id(),
+            secret_access_key: session.secret_access_key(),
+            session_token: session.session_token(),
+            expiration: session.expiration().fmt(Format::DateTime)?,
+        },
+    };
+    let content = serde_json::to_string(&entry)?;
+
+    let path = dir.join(format!("{}.json", key.hash()));
+    let tmp = dir.join(format!("{}.json.tmp", key.hash()));
+    let result = create_dir_all(dir).and_then(|()| {
+        let mut file = create_private(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        rename(&tmp, &path)
+    });
+    if result.is_err() {
+        let _ = remove_file(&tmp);
+    }
+    result.with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Renders `s` as a JSON string the way Python's `json.dumps` does by default, with everything
+/// outside ASCII escaped.
+fn python_json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(out, "\\u{unit:04x}");
+                }
+            }
+        }
+    }
+    out.push('"');
+    out
+}
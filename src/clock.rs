@@ -0,0 +1,35 @@
+//! Local clock skew detection.
+//!
+//! TOTP codes and request signatures both depend on accurate time, so a drifted clock shows up as
+//! a rejected MFA code or signature. Servers report their time in the HTTP `Date` header, which is
+//! compared against the local clock here.
+
+use std::time::SystemTime;
+
+use aws_smithy_types::{DateTime, date_time::Format};
+
+/// Skew beyond which the local clock is considered wrong.
+pub const MAX_SKEW_SECS: i64 = 30;
+
+/// Returns how many seconds the local clock `now` is ahead of the server time in `date`, an
+/// HTTP `Date` header value. Negative values mean the local clock is behind.
+pub fn skew_secs(date: &str, now: SystemTime) -> Option<i64> {
+    let server = DateTime::from_str(date, Format::HttpDate).ok()?;
+    Some(DateTime::from(now).secs() - server.secs())
+}
+
+/// Returns a warning if the local clock is off by more than [`MAX_SKEW_SECS`] compared to the
+/// server time in `date`.
+pub fn skew_warning(date: &str, now: SystemTime) -> Option<String> {
+    let skew = skew_secs(date, now)?;
+    if skew.abs() <= MAX_SKEW_SECS {
+        return None;
+    }
+
+    let direction = if skew > 0 { "ahead of" } else { "behind" };
+    Some(format!(
+        "Your local clock is {} seconds {direction} AWS. MFA codes depend on accurate time; \
+         sync your clock (e.g. enable NTP) and try again",
+        skew.abs()
+    ))
+}
@@ -0,0 +1,1137 @@
+//! What each command does with the arguments of a run for one profile.
+//!
+//! A [`Run`] checks the arguments and selects the profile. Commands that need the long-term
+//! credentials or STS open a [`Refresh`] on it, which sets up what is missing first.
+
+use std::{
+    cell::OnceCell,
+    env::var,
+    ffi::OsString,
+    fs::read_to_string,
+    io::{IsTerminal, stderr, stdin},
+    path::Path,
+    process::{ExitCode, exit},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use aws_mfa::{
+    clear::{self, Scope},
+    config::AwsConfig,
+    console::{self, SignIn},
+    credentials::{self, AssumeRole, Federation, MfaDevice},
+    dialog::ManualPrompt,
+    doctor::{self, Status},
+    error::{Failure, Report, ResultExt},
+    exec,
+    history::{self, History},
+    notify,
+    op_keys::{Fields, OpKeys},
+    output::{self, Summary},
+    path,
+    permissions::Policy,
+    picker::{self, Candidate, Menu},
+    plan,
+    questions::Questions,
+    redact::Secret,
+    remaining::Remaining,
+    rotate, server,
+    session_keys::SessionKeys,
+    session_store::{CredentialStore, KeyringSession},
+    settings::{self, ProfileSettings},
+    setup, status,
+    tls::{self, CaBundle},
+    token::{Bitwarden, KeePass, Pass, Provider, Sources, YubiKey},
+    totp::Totp,
+    totp_seed::SeedStore,
+    updater::{AwsMfaUpdater, KeySource, Mode, Profile},
+    vault::Key,
+    watch, wizard,
+};
+use aws_sdk_sts::types;
+use aws_smithy_types::date_time::Format;
+use log::{debug, info, warn};
+use tokio::task::spawn_blocking;
+
+use crate::cli::{Action, Args};
+
+/// Shortest remaining validity of the existing session for rotate-key to use it.
+const ROTATE_MIN_REMAINING: Duration = Duration::from_secs(5 * 60);
+
+/// Prints the validity left of the session of the profile for the `remaining` command, and
+/// returns its exit code. Nothing is logged and nothing fails: a prompt shows nothing instead.
+pub fn remaining(args: &Args, porcelain: bool) -> ExitCode {
+    let (_, remaining) = selected_session(args);
+    let shown = match porcelain {
+        true => remaining.porcelain(),
+        false => remaining.to_string(),
+    };
+    if !shown.is_empty() {
+        println!("{shown}");
+    }
+    remaining.exit_code()
+}
+
+/// Lists the sessions of the credentials file for the `status` command, and returns the exit code
+/// `remaining` has for the session of the profile.
+pub fn status(args: &Args) -> ExitCode {
+    let (section, remaining) = selected_session(args);
+    let keys = SessionKeys::default().with_expiration_keys(args.expiration_keys.clone());
+    let sessions =
+        keys.and_then(|keys| status::read(args.credentials_path.clone(), &keys, SystemTime::now()));
+    match sessions {
+        Ok(sessions) if sessions.is_empty() => eprintln!("No sessions in the credentials file"),
+        Ok(sessions) => print!("{}", status::render(&sessions, &section, args.utc)),
+        Err(e) => {
+            eprintln!("Error: {}", Report(&e));
+            return Failure::exit_code(&Failure::Config.wrap(e));
+        }
+    }
+    remaining.exit_code()
+}
+
+/// Returns the section of the session of the profile `args` select, and its validity left: in
+/// the credentials file, or in the keyring with `--credential-store`. Nothing fails; a session
+/// that can't be read is [`Remaining::NoSession`].
+fn selected_session(args: &Args) -> (String, Remaining) {
+    let env = var("AWS_PROFILE").ok().filter(|p| !p.is_empty());
+    let keys = SessionKeys::default().with_expiration_keys(args.expiration_keys.clone());
+    let now = SystemTime::now();
+    let name = args.profile.as_ref().or(env.as_ref());
+    let suffix = Some(args.long_term_suffix.clone()).filter(|suffix| suffix != "none");
+    let profile = Profile::new(name.map_or("default", String::as_str))
+        .with_suffixes(suffix, &args.short_term_suffix);
+    let path = args.credentials_path.clone();
+    let settings = ProfileSettings::of(path, &profile).unwrap_or_default();
+    let store = args
+        .credential_store
+        .or(settings.credential_store)
+        .unwrap_or_default();
+    let target = args.target_profile.clone().or(settings.target_profile);
+    // The section a refresh writes to, see `Profile::session_section`.
+    let section = match target {
+        Some(target) => profile.with_target(target),
+        None => profile,
+    }
+    .session_section();
+    let mut remaining = match keys {
+        Ok(keys) if store.file() => {
+            Remaining::read(args.credentials_path.clone(), &section, &keys, now)
+        }
+        _ => Remaining::NoSession,
+    };
+    if matches!(remaining, Remaining::NoSession) && store.keyring() {
+        let session = KeyringSession::new(&section).and_then(|entry| entry.load());
+        if let Ok(Some(session)) = session {
+            let expiration = session.expiration.fmt(Format::DateTime).ok();
+            remaining = Remaining::at(now, expiration.as_deref());
+        }
+    }
+    (section, remaining)
+}
+
+/// A run of aws-mfa for one profile.
+pub struct Run {
+    args: Args,
+    /// Nobody is there to answer, as under cron or in CI.
+    unattended: bool,
+    /// Questions can be asked on stdin.
+    interactive: bool,
+    /// Suffix of the long-term section, or `None` for the profile itself.
+    long_term_suffix: Option<String>,
+    /// The profile, once selected.
+    selected: OnceCell<Selected>,
+}
+
+/// The profile a run is for.
+struct Selected {
+    name: String,
+    profile: Profile,
+    /// Whether the user picked it from a menu.
+    picked: bool,
+}
+
+impl Run {
+    /// Checks `args` for running `action`, and applies their settings of the TLS and the STS
+    /// endpoint.
+    pub fn new(args: Args, action: Option<&Action>) -> Result<Self> {
+        ensure!(!args.fail_fast, "--fail-fast needs --context or --all-contexts");
+        if let Some(token) = &args.token {
+            if let Err(problem) = args.token_length.check(token) {
+                let error = anyhow!("Invalid MFA code in --token or AWS_MFA_TOKEN: {problem}");
+                return Err(Failure::Token.wrap(error));
+            }
+            ensure!(
+                !matches!(action, Some(Action::Watch { .. } | Action::Server { .. })),
+                "--token can't be combined with watch or server, which need a new MFA code for \
+                 every refresh"
+            );
+        }
+        if let Some(path) = &args.ca_bundle {
+            let bundle = path::expand(path)
+                .and_then(|path| CaBundle::load(&path))
+                .failure(Failure::Config)?;
+            debug!("Trusting {} certificates of {}", bundle.count(), bundle.path().display());
+            tls::set_ca_bundle(Some(bundle));
+        }
+        credentials::set_region(args.region.clone());
+        credentials::set_sts_endpoint(args.sts_endpoint);
+        ensure!(!args.no_write || action.is_none(), "--no-write can't be combined with a command");
+        ensure!(!args.dry_run || action.is_none(), "--dry-run can't be combined with a command");
+        // Global arguments can't declare conflicts with --no-write, which only exists at the top
+        // level.
+        ensure!(
+            !args.no_write || !args.write_cli_cache && args.push_remotes.is_empty(),
+            "--no-write can't be combined with --write-cli-cache or --push-remote"
+        );
+        ensure!(
+            args.no_write || args.output.is_none() || summary(&args) && action.is_none(),
+            "--output needs --no-write, except --output json for a summary of a refresh"
+        );
+
+        // Without a terminal on either end, as under cron or in CI, nobody is there to answer.
+        let attended = stdin().is_terminal() || stderr().is_terminal();
+        let unattended = args.non_interactive || !(args.interactive || args.gui_prompt || attended);
+        let long_term_suffix = Some(args.long_term_suffix.clone()).filter(|s| s != "none");
+        Ok(Self {
+            interactive: !unattended && stdin().is_terminal(),
+            unattended,
+            long_term_suffix,
+            args,
+            selected: OnceCell::new(),
+        })
+    }
+
+    /// Returns the name of the profile if the user picked it from a menu.
+    pub fn picked(&self) -> Option<&str> {
+        self.selected.get().filter(|s| s.picked).map(|s| s.name.as_str())
+    }
+
+    /// Prints the sessions obtained so far, of the profile if `--profile` is given; the history
+    /// is about all profiles.
+    pub fn history(&self, since: Option<Duration>, json: bool) -> Result<()> {
+        let history = History::user().context("No state directory to keep the history in")?;
+        let since = since.map(|age| SystemTime::now() - age);
+        let records: Vec<_> = history
+            .read()?
+            .into_iter()
+            .filter(|r| r.matches(self.args.profile.as_deref(), since))
+            .collect();
+        if json {
+            for record in &records {
+                println!("{}", serde_json::to_string(record)?);
+            }
+        } else if records.is_empty() {
+            info!("No sessions recorded in {}", history.path().display());
+        } else {
+            print!("{}", history::render(&records, self.args.utc));
+        }
+        Ok(())
+    }
+
+    /// Stores the TOTP secret of the profile in the keyring, apart from the credentials.
+    pub fn enroll(&self) -> Result<()> {
+        let name = &self.select(false)?.name;
+        if self.unattended {
+            let error =
+                anyhow!("enroll asks for the TOTP secret, which a non-interactive run can't");
+            return Err(Failure::Unattended.wrap(error));
+        }
+        let secret = Questions::stdio()
+            .secret(&format!(
+                "TOTP secret or otpauth:// URI of the MFA device of {name} (hidden): "
+            ))
+            .context("Failed to read the TOTP secret from the terminal")?;
+        let totp = Totp::parse(Secret::new(secret).expose()).failure(Failure::Config)?;
+        SeedStore::new(name)?.save(&totp)?;
+        let totp = totp.with_skew(self.args.totp_skew);
+        info!("Stored the TOTP secret of {name} in the keyring; refreshes generate the codes now");
+        info!("The current code is {}; check it against your authenticator app", totp.now());
+        Ok(())
+    }
+
+    /// Prints the current code of the TOTP secret of the profile, with its validity if `window`.
+    pub fn code(&self, window: bool) -> Result<()> {
+        let name = &self.select(false)?.name;
+        let totp = SeedStore::new(name)?.load()?.with_context(|| {
+            format!("No TOTP secret of {name} in the keyring; run `aws-mfa enroll` first")
+        });
+        let totp = totp.failure(Failure::Config)?.with_skew(self.args.totp_skew);
+        match window {
+            true => println!("{} {}", totp.now(), totp.valid_for().as_millis().div_ceil(1000)),
+            false => println!("{}", totp.now()),
+        }
+        Ok(())
+    }
+
+    /// Deletes the TOTP secret of the profile from the keyring.
+    pub fn unenroll(&self) -> Result<()> {
+        let name = &self.select(false)?.name;
+        match SeedStore::new(name)?.delete()? {
+            true => info!("Deleted the TOTP secret of {name} from the keyring"),
+            false => info!("No TOTP secret of {name} in the keyring"),
+        }
+        Ok(())
+    }
+
+    /// Clears the session of the profile, or every session with `all`, and only expired ones with
+    /// `expired_only`. Needs neither the long-term credentials nor STS.
+    pub async fn clear(&self, all: bool, expired_only: bool) -> Result<()> {
+        ensure!(
+            !all || self.args.profile.is_none() && self.args.target_profile.is_none(),
+            "clear --all can't be combined with --profile or --target-profile"
+        );
+        let profile = &self.select(all)?.profile;
+        let path = &self.args.credentials_path;
+        let store = CredentialStore::of(self.args.credential_store, path.clone(), profile)?;
+        let mut cleared = Vec::new();
+        if store.keyring() {
+            // The keyring can't be searched, so --all goes by the profiles of the file.
+            let profiles = match (all, &self.long_term_suffix) {
+                (true, Some(suffix)) => self
+                    .candidates(suffix)?
+                    .into_iter()
+                    .map(|c| self.profile(c.name))
+                    .collect(),
+                _ => vec![profile.clone()],
+            };
+            let mut entries = Vec::new();
+            for profile in profiles {
+                entries.push(KeyringSession::new(&profile.session_section())?);
+                entries.push(KeyringSession::new(&format!("{}-session", profile.name()))?);
+            }
+            cleared.extend(clear::clear_keyring(&entries, expired_only)?);
+        }
+        if store.file() {
+            let scope = match all {
+                true => Scope::All,
+                false => Scope::Section(profile.session_section()),
+            };
+            let suffix = self.long_term_suffix.as_deref();
+            cleared.extend(clear::clear(path.clone(), &scope, suffix, expired_only).await?);
+        }
+        if cleared.is_empty() {
+            info!("Nothing to clear");
+        }
+        Ok(())
+    }
+
+    /// Reports what a refresh would stumble over instead of running into it.
+    pub async fn doctor(&self, json: bool) -> Result<()> {
+        ensure!(!self.args.fix, "--fix can't be combined with doctor");
+        let args = &self.args;
+        let profile = &self.select(false)?.profile;
+        let config = AwsConfig::load().failure(Failure::Config)?;
+        let setting = |key| config.get(profile.name(), key).map(str::to_string);
+        let providers = match args.providers.clone() {
+            Some(providers) => providers,
+            None => setting(settings::PROVIDERS)
+                .map(|providers| providers.parse())
+                .transpose()
+                .failure(Failure::Config)?
+                .unwrap_or_default(),
+        };
+        let mut sources = Sources::default()
+            .with_providers(providers)
+            .with_op_program(args.op_path.clone())
+            .with_op_reference(args.op_secret_reference.clone())
+            .with_one_password(
+                args.op_account.clone().or_else(|| setting(setup::OP_ACCOUNT)),
+                args.op_item_name.clone().or_else(|| setting(setup::OP_ITEM_NAME)),
+            )
+            .with_bitwarden(
+                args.bw_item
+                    .clone()
+                    .or_else(|| setting(settings::BW_ITEM))
+                    .map(Bitwarden::new),
+            )
+            .with_pass(
+                args.pass_entry
+                    .clone()
+                    .or_else(|| setting(settings::PASS_ENTRY))
+                    .map(Pass::new),
+            )
+            .with_yubikey(
+                args.yubikey_account
+                    .clone()
+                    .or_else(|| setting(settings::YUBIKEY_ACCOUNT))
+                    .map(YubiKey::new),
+            )
+            .with_command(
+                args.token_command
+                    .clone()
+                    .or_else(|| setting(settings::TOKEN_COMMAND)),
+            );
+        if let Some(keepass) = self.keepass()? {
+            sources = sources.with_keepass(keepass);
+        }
+        let timeout = Duration::from_secs(args.sts_timeout);
+        let checks = doctor::run(args.credentials_path.clone(), profile, &sources, timeout)
+            .await
+            .failure(Failure::Config)?;
+
+        match json {
+            true => print!("{}", doctor::to_json(profile.name(), &checks)?),
+            false => print!("{}", doctor::render(&checks)),
+        }
+        let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+        if failed > 0 {
+            let error = anyhow!("{failed} of {} checks failed", checks.len());
+            return Err(Failure::Config.wrap(error));
+        }
+        Ok(())
+    }
+
+    /// Sets up the profile without refreshing, see [`wizard::init`].
+    pub async fn init(&self) -> Result<()> {
+        let profile = &self.select(false)?.profile;
+        if !self.interactive {
+            let error = anyhow!("init asks questions, which a non-interactive run can't");
+            return Err(Failure::Unattended.wrap(error));
+        }
+        let (path, timeout) = (self.args.credentials_path.clone(), self.sts_timeout());
+        wizard::init(&mut Questions::stdio(), path, profile, self.args.use_keyring, timeout)
+            .await
+            .failure(Failure::Config)
+    }
+
+    /// Opens the updater of the profile, setting up what is missing first. Only a refresh and the
+    /// console are `federated` with `--federation-name`. `context_region` is that of the context
+    /// the run is for, if any.
+    pub async fn open(
+        &self,
+        federated: bool,
+        context_region: Option<String>,
+    ) -> Result<Refresh<'_>> {
+        let args = &self.args;
+        let federation = args
+            .federation_name
+            .clone()
+            .map(|name| {
+                ensure!(
+                    federated,
+                    "--federation-name can only be combined with the console command"
+                );
+                let policy = args.policy_file.as_deref().map(read_policy).transpose()?;
+                Federation::new(name, policy, args.policy_arns.clone())
+            })
+            .transpose()
+            .failure(Failure::Config)?;
+        let Selected { name, profile, .. } = self.select(false)?;
+        self.set_up(profile).await?;
+
+        let updater = self.updater(profile.clone())?;
+        let mut settings = updater.settings().clone();
+        if context_region.is_some() {
+            settings.region = context_region;
+        }
+        credentials::set_fallback_region(settings.region.clone());
+        // GovCloud and China users without a region would otherwise be sent to us-east-1.
+        let device = MfaDevice::parse(updater.credentials().mfa_device()).ok();
+        credentials::set_partition(device.as_ref().and_then(MfaDevice::partition));
+        let updater = self.with_roles(updater, &settings, federation.is_some())?;
+
+        let timeout = args.prompt_timeout.map(Duration::from_secs);
+        let prompt =
+            ManualPrompt::pick(args.gui_prompt, self.unattended, timeout, args.token_length)?;
+        let sources = Arc::new(self.sources(name, &settings)?);
+        let get_token: GetToken = {
+            let sources = sources.clone();
+            Arc::new(move || sources.get_token(&prompt))
+        };
+        let updater = match args.auto_downgrade_duration {
+            true => {
+                let again = get_token.clone();
+                let min_validity = Duration::from_secs(args.min_code_validity);
+                updater.with_duration_downgrade(move || again(), min_validity)
+            }
+            false => updater,
+        };
+        Ok(Refresh {
+            run: self,
+            updater,
+            settings,
+            federation,
+            sources,
+            get_token,
+        })
+    }
+
+    /// Selects the profile, from a menu of the profiles with long-term credentials if several
+    /// have them and neither `--profile` nor `AWS_PROFILE` names one, unless for `clear_all`.
+    fn select(&self, clear_all: bool) -> Result<&Selected> {
+        if let Some(selected) = self.selected.get() {
+            return Ok(selected);
+        }
+        let args = &self.args;
+        let (name, picked) = select_profile(args.profile.clone(), || {
+            // Without a suffix, every section would be a candidate.
+            let Some(suffix) = self.long_term_suffix.as_ref().filter(|_| !clear_all) else {
+                return Ok(None);
+            };
+            let candidates = self.candidates(suffix)?;
+            let menu = self.interactive.then_some(&choose_profile as &dyn Menu);
+            Ok(picker::pick(&candidates, menu)?.map(|c| c.name.clone()))
+        })?;
+        let mut profile = self.profile(name.clone());
+        if args.from_env {
+            profile = profile.with_keys_from_env();
+        }
+        if let Some(item) = args.op_credentials_item.clone() {
+            // The long-term section may be gone, so the account can only come from the config.
+            let account = args.op_account.clone().or_else(|| {
+                let config = AwsConfig::load().ok()?;
+                config.get(&name, setup::OP_ACCOUNT).map(str::to_string)
+            });
+            let fields = Fields {
+                access_key_id: args.op_access_key_field.clone(),
+                secret_access_key: args.op_secret_key_field.clone(),
+                mfa_device: args.op_mfa_device_field.clone(),
+            };
+            let mut keys = OpKeys::new(item)
+                .with_account(account)
+                .with_fields(fields)
+                .with_sign_in(self.interactive);
+            if let Some(path) = &args.op_path {
+                keys = keys.with_program(path);
+            }
+            profile = profile.with_op_keys(keys);
+        }
+        if let Some(device) = &args.mfa_device {
+            profile = profile.with_mfa_device(device).failure(Failure::Config)?;
+        }
+        let target_profile = match args.target_profile.clone() {
+            None if !clear_all => {
+                let settings = ProfileSettings::of(args.credentials_path.clone(), &profile);
+                settings.failure(Failure::Config)?.target_profile
+            }
+            target_profile => target_profile,
+        };
+        if let Some(target) = target_profile {
+            info!("Writing session credentials to [{target}]");
+            profile = profile.with_target(target);
+        }
+        Ok(self.selected.get_or_init(|| Selected { name, profile, picked }))
+    }
+
+    /// Returns the profile `name` with the suffixes of the run.
+    fn profile(&self, name: String) -> Profile {
+        Profile::new(name)
+            .with_suffixes(self.long_term_suffix.clone(), &self.args.short_term_suffix)
+    }
+
+    /// Lists the profiles with a long-term section ending in `suffix`.
+    fn candidates(&self, suffix: &str) -> Result<Vec<Candidate>> {
+        let (path, short_term_suffix) = (&self.args.credentials_path, &self.args.short_term_suffix);
+        picker::discover(path.clone(), suffix, short_term_suffix, self.args.utc)
+            .failure(Failure::Config)
+    }
+
+    /// Enters new long-term keys with `--fix`, or else sets up what `profile` is missing: moves
+    /// the keys `aws configure` left in its section, or runs the wizard.
+    async fn set_up(&self, profile: &Profile) -> Result<()> {
+        let args = &self.args;
+        let path = &args.credentials_path;
+        ensure!(!args.fix || !args.from_env, "--fix can't be combined with --from-env");
+        ensure!(
+            !args.fix || profile.op_keys().is_none(),
+            "--fix can't be combined with --op-credentials-item"
+        );
+        if args.fix && !self.interactive {
+            let error =
+                anyhow!("--fix asks for the long-term keys, which a non-interactive run can't");
+            return Err(Failure::Unattended.wrap(error));
+        }
+        if args.fix {
+            let ask = |name: &str| Questions::stdio().ask(name);
+            return AwsMfaUpdater::fix_long_term(path.clone(), profile, args.use_keyring, ask)
+                .failure(Failure::Config);
+        }
+        let Some(missing) = setup::missing(path.clone(), profile).failure(Failure::Config)? else {
+            return Ok(());
+        };
+        let stray = setup::stray_keys(path.clone(), profile).failure(Failure::Config)?;
+        let timeout = self.sts_timeout();
+        if stray.is_some() {
+            // Keys left by `aws configure` are moved over rather than asked for again. Like the
+            // wizard, this writes the file, which a dry run must not.
+            let mut questions = Questions::stdio();
+            let offered = !args.no_wizard && self.interactive;
+            let accepted = args.migrate
+                || offered && questions.confirm(&format!("{missing}. Move them there?"))?;
+            if args.dry_run || !accepted {
+                return Err(setup::unmigrated(&missing, profile, path.as_deref()));
+            }
+            wizard::migrate_keys(&mut questions, path.clone(), profile, self.interactive, timeout)
+                .await
+                .failure(Failure::Config)
+        } else if args.no_wizard || !self.interactive || args.dry_run {
+            // The wizard writes the file, which a dry run must not.
+            Err(setup::unattended(&missing))
+        } else {
+            let mut questions = Questions::stdio();
+            let in_keyring = args.use_keyring;
+            wizard::wizard(&mut questions, path.clone(), profile, &missing, in_keyring, timeout)
+                .await
+                .failure(Failure::Config)
+        }
+    }
+
+    /// Opens the updater of `profile` with the destinations and session keys of the run.
+    fn updater(&self, profile: Profile) -> Result<AwsMfaUpdater> {
+        let args = &self.args;
+        let (unattended, interactive) = (self.unattended, self.interactive);
+        let session_keys = SessionKeys::default()
+            .with_legacy_token(!args.no_legacy_token)
+            .with_expiration_keys(args.expiration_keys.clone())
+            .failure(Failure::Config)?;
+        let permissions = match (args.strict_permissions, args.fix_permissions) {
+            (true, _) => Policy::Strict,
+            // A dry run changes nothing, permissions included.
+            (_, true) if !args.dry_run => Policy::Fix,
+            _ => Policy::Warn,
+        };
+        let fallback_path = args
+            .fallback_path
+            .as_deref()
+            .map(path::expand)
+            .transpose()
+            .failure(Failure::Config)?;
+        // Pasting the session into the file saves an MFA code, but it shouldn't land in a log
+        // unasked.
+        let print_on_write_failure = args.print_on_write_failure;
+        let print_session = move |ini: &str| {
+            let question = "Print the session to paste it into the credentials file yourself?";
+            let print = print_on_write_failure
+                || interactive && Questions::stdio().confirm(question).unwrap_or(false);
+            if print {
+                eprint!("{ini}");
+            }
+            print
+        };
+        let age_identity = self.age_identity()?;
+        let key = || match &age_identity {
+            Some(identity) => Ok(Key::Identity(identity.clone())),
+            None if unattended => Err(Failure::Unattended.wrap(anyhow!(
+                "The long-term keys are encrypted to a passphrase, which isn't asked for in a \
+                 non-interactive run; encrypt them to --age-identity instead"
+            ))),
+            None => Questions::stdio().passphrase(false).map(Key::Passphrase),
+        };
+        let path = args.credentials_path.clone();
+        let hook_timeout = Duration::from_secs(args.hook_timeout);
+        let updater = AwsMfaUpdater::open(path, profile, args.duration, permissions, key)
+            .failure(Failure::Config)?
+            .with_mfa_device(args.mfa_device_name.as_deref(), |names| {
+                choose_device(names, interactive)
+            })
+            .failure(Failure::Config)?
+            .with_sts_timeout(self.sts_timeout())
+            .with_cli_cache(args.write_cli_cache)
+            .with_remotes(args.push_remotes.clone())
+            .with_dotenv(self.dotenv_paths()?)
+            .with_fallback_path(fallback_path)
+            .with_last_resort(print_session)
+            .with_hooks(args.on_refresh_hook.clone(), args.on_expiring_hook.clone(), hook_timeout)
+            .with_history(History::user())
+            .with_utc(args.utc);
+        let copy_keys = match args.copy_keys.is_empty() {
+            true => updater.settings().copy_keys.clone(),
+            false => args.copy_keys.clone(),
+        };
+        let session_keys = session_keys.with_copied_keys(copy_keys).failure(Failure::Config)?;
+        updater
+            .with_session_keys(session_keys)
+            .with_credential_store(args.credential_store)
+            .failure(Failure::Config)
+    }
+
+    /// Adds the roles of the run, or else of the profile's `settings`, to `updater`. Federated
+    /// users can't assume roles, so the profile's role_arn doesn't apply to a `federation`.
+    fn with_roles(
+        &self,
+        mut updater: AwsMfaUpdater,
+        settings: &ProfileSettings,
+        federation: bool,
+    ) -> Result<AwsMfaUpdater> {
+        let args = &self.args;
+        let role_arn = match args.role_arn.is_empty() && !federation {
+            true => settings.role_arns.clone(),
+            false => args.role_arn.clone(),
+        };
+        // The profile's role_arn counts too, so this can't be left to clap.
+        if let Some(flag) = role_arn.is_empty().then(|| self.role_flag()).flatten() {
+            let error = anyhow!(
+                "{flag} only applies to assuming a role, not to GetSessionToken; add --role-arn, \
+                 or set role_arn in [{}]",
+                updater.long_term_section()
+            );
+            return Err(Failure::Config.wrap(error));
+        }
+        if !role_arn.is_empty() {
+            let external_id = args.external_id.clone().or(settings.external_id.clone());
+            let session_name =
+                args.role_session_name.clone().or(settings.role_session_name.clone());
+            let session_tags = match args.session_tags.is_empty() {
+                true => settings.session_tags.clone(),
+                false => args.session_tags.clone(),
+            };
+            let policy = args
+                .session_policy_file
+                .as_deref()
+                .map(read_policy)
+                .transpose()
+                .failure(Failure::Config)?;
+            let last = role_arn.len() - 1;
+            for (i, arn) in role_arn.into_iter().enumerate() {
+                let transitive = args.transitive_tag_keys.clone();
+                let mut role = AssumeRole::new(arn, external_id.clone(), session_name.clone())
+                    .and_then(|role| role.with_tags(&session_tags, transitive))
+                    .failure(Failure::Config)?;
+                // Only the credentials that are written get scoped down, so that a chain still
+                // works.
+                if i == last {
+                    role = role
+                        .with_policies(policy.clone(), args.session_policy_arns.clone())
+                        .failure(Failure::Config)?;
+                }
+                updater = updater.with_role(role);
+            }
+            if args.via_session {
+                updater = updater.via_session(args.write_session);
+            }
+        }
+        updater
+            .with_phase_durations(args.session_duration, args.role_duration)
+            .with_checked_duration(federation, args.strict_duration)
+            .failure(Failure::Config)
+    }
+
+    /// Returns the first flag of the run that only applies to AssumeRole, if any.
+    fn role_flag(&self) -> Option<&'static str> {
+        let args = &self.args;
+        [
+            (args.external_id.is_some(), "--external-id"),
+            (args.role_session_name.is_some(), "--role-session-name"),
+            (!args.session_tags.is_empty(), "--session-tag"),
+            (!args.transitive_tag_keys.is_empty(), "--transitive-tag-key"),
+            (args.session_policy_file.is_some(), "--session-policy-file"),
+            (!args.session_policy_arns.is_empty(), "--session-policy-arn"),
+        ]
+        .into_iter()
+        .find_map(|(given, flag)| given.then_some(flag))
+    }
+
+    /// Returns where the MFA codes of the profile `name` come from, with the password managers of
+    /// the run, or else of the profile's `settings`.
+    fn sources(&self, name: &str, settings: &ProfileSettings) -> Result<Sources> {
+        let args = &self.args;
+        // The wizard saves the 1Password item with the profile.
+        let op_account = args.op_account.clone().or(settings.op_account.clone());
+        let op_item_name = args.op_item_name.clone().or(settings.op_item_name.clone());
+        let providers = args
+            .providers
+            .clone()
+            .or(settings.providers.clone())
+            .unwrap_or_default();
+        // Most profiles aren't enrolled, and many machines have no keyring to ask at all.
+        let totp = match providers.contains(Provider::Keyring) {
+            true => SeedStore::new(name)
+                .and_then(|store| store.load())
+                .unwrap_or_else(|e| {
+                    match e.downcast_ref::<keyring::Error>() {
+                        Some(_) => debug!("{}", Report(&e)),
+                        None => warn!("{}", Report(&e)),
+                    }
+                    None
+                }),
+            false => None,
+        };
+        let yubikey_timeout = Duration::from_secs(args.yubikey_timeout);
+        let mut sources = Sources::default()
+            .with_providers(providers)
+            .with_totp(totp.map(|totp| totp.with_skew(args.totp_skew)))
+            .with_op_program(args.op_path.clone())
+            .with_op_reference(args.op_secret_reference.clone())
+            .with_one_password(op_account, op_item_name)
+            .with_bitwarden(args.bw_item.clone().or(settings.bw_item.clone()).map(Bitwarden::new))
+            .with_pass(args.pass_entry.clone().or(settings.pass_entry.clone()).map(Pass::new))
+            .with_yubikey(
+                args.yubikey_account
+                    .clone()
+                    .or(settings.yubikey_account.clone())
+                    .map(|account| YubiKey::new(account).with_timeout(yubikey_timeout)),
+            )
+            .with_command(args.token_command.clone().or(settings.token_command.clone()))
+            .with_token(args.token.clone())
+            .with_op_sign_in(self.interactive)
+            .with_token_length(args.token_length)
+            .with_min_validity(Duration::from_secs(args.min_code_validity))
+            .with_unattended(self.unattended);
+        if let Some(keepass) = self.keepass()? {
+            sources = sources.with_keepass(keepass);
+        }
+        Ok(sources)
+    }
+
+    /// Returns the KeePass database of `--keepass-db` and `--keepass-entry`, if both are given.
+    fn keepass(&self) -> Result<Option<KeePass>> {
+        let args = &self.args;
+        let (Some(db), Some(entry)) = (&args.keepass_db, &args.keepass_entry) else {
+            return Ok(None);
+        };
+        let key_file = args.keepass_keyfile.as_deref().map(path::expand).transpose();
+        let key_file = key_file.failure(Failure::Config)?;
+        let unlocked_by_file = key_file.is_some();
+        let mut keepass = KeePass::new(path::expand(db).failure(Failure::Config)?, entry.clone())
+            .with_key_file(key_file);
+        if self.unattended {
+            keepass = keepass.with_password_prompt(move || match unlocked_by_file {
+                true => Ok(String::new()),
+                false => bail!(
+                    "The database password isn't asked for in a non-interactive run; unlock it \
+                     with --keepass-keyfile"
+                ),
+            });
+        }
+        Ok(Some(keepass))
+    }
+
+    /// Returns the files of `--dotenv`, expanded.
+    fn dotenv_paths(&self) -> Result<Vec<std::path::PathBuf>> {
+        let paths = self.args.dotenv_paths.iter().map(|p| path::expand(p));
+        paths.collect::<Result<_>>().failure(Failure::Config)
+    }
+
+    /// Returns the file of `--age-identity`, expanded.
+    fn age_identity(&self) -> Result<Option<std::path::PathBuf>> {
+        let identity = self.args.age_identity.as_deref().map(path::expand);
+        identity.transpose().failure(Failure::Config)
+    }
+
+    /// Returns how long STS and IAM calls may take.
+    fn sts_timeout(&self) -> Duration {
+        Duration::from_secs(self.args.sts_timeout)
+    }
+}
+
+/// Gets an MFA code.
+type GetToken = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// An updater opened by a [`Run`], and where it gets MFA codes from.
+pub struct Refresh<'a> {
+    run: &'a Run,
+    updater: AwsMfaUpdater,
+    settings: ProfileSettings,
+    federation: Option<Federation>,
+    sources: Arc<Sources>,
+    get_token: GetToken,
+}
+
+impl Refresh<'_> {
+    /// Keeps refreshing the credentials `lead_time` seconds before they expire.
+    pub async fn watch(self, lead_time: u64) -> Result<()> {
+        let (args, automated) = (&self.run.args, self.sources.is_automated());
+        let lead_time = Duration::from_secs(lead_time);
+        watch::run(&self.updater, lead_time, args.notify, !automated, self.get_token()).await?;
+        // A pending manual prompt runs on a blocking thread that the runtime would otherwise wait
+        // for on shutdown.
+        exit(0);
+    }
+
+    /// Serves the credentials on localhost `port`, under the path prefix `secret`.
+    pub async fn serve(self, port: u16, secret: Option<String>) -> Result<()> {
+        let min_remaining = self.run.args.min_remaining;
+        server::run(&self.updater, port, secret, min_remaining, self.get_token()).await?;
+        exit(0);
+    }
+
+    /// Replaces the long-term access key with a new one, deleting the old one unless `keep_old`.
+    pub async fn rotate_key(self, keep_old: bool) -> Result<()> {
+        let updater = &self.updater;
+        ensure!(
+            !updater.has_roles(),
+            "rotate-key acts on the IAM user and can't be combined with --role-arn"
+        );
+        ensure!(
+            matches!(updater.key_source(), KeySource::File | KeySource::Keyring),
+            "rotate-key can't store a new key in the environment or 1Password; it needs a \
+             long-term section or the keyring"
+        );
+
+        // IAM policies commonly require MFA, so the user's session signs the IAM calls.
+        let session = match updater.reusable_session(ROTATE_MIN_REMAINING)? {
+            Some(session) => session.credentials()?,
+            _ => {
+                info!("Refreshing the session first");
+                updater.update_credentials_with(self.token()).await?
+            }
+        };
+        let iam = credentials::iam_client(&session, updater.sts_timeout()).await;
+        let confirm = |question: &str| Questions::stdio().confirm(question);
+        rotate::rotate_key(updater, &iam, keep_old, confirm).await
+    }
+
+    /// Runs `command` with the session in its environment, stored where a refresh would store it
+    /// only if `write`.
+    pub async fn exec(self, write: bool, command: Vec<OsString>) -> Result<()> {
+        let updater = &self.updater;
+        let session = match updater.reusable_session(self.run.args.min_remaining)? {
+            Some(session) => {
+                info!(
+                    "Using the session valid until {}",
+                    updater.describe_expiration(&session.expiration)
+                );
+                session.credentials()?
+            }
+            _ => {
+                if write {
+                    updater.update_credentials_with(self.token()).await?
+                } else {
+                    updater.fetch_credentials_with(self.token()).await?
+                }
+            }
+        };
+        let code = exec::run(exec::command(&session, &command)?).await?;
+        exit(code);
+    }
+
+    /// Prints the session as the JSON document credential_process expects.
+    pub async fn credential_process(self) -> Result<()> {
+        let (updater, args) = (&self.updater, &self.run.args);
+        // AWS tools start this for every client, so a reused session goes without a word.
+        let session = match updater.reusable_session(args.min_remaining)? {
+            Some(session) if !args.force => {
+                debug!(
+                    "Using the session valid until {}",
+                    updater.describe_expiration(&session.expiration)
+                );
+                session.credentials()?
+            }
+            _ => updater.update_credentials_with(self.token()).await?,
+        };
+        let section = updater.profile().session_section();
+        let keys = updater.session_keys();
+        let role_arn = updater.assumed_role();
+        let json = output::Format::Json;
+        print!("{}", output::render(&session, &section, json, keys, role_arn)?);
+        Ok(())
+    }
+
+    /// Prints a URL signing in to the AWS console with fresh credentials, or opens it with
+    /// `open`.
+    pub async fn console(self, open: bool) -> Result<()> {
+        let updater = &self.updater;
+        if updater.mode(self.federation.is_some()) == Mode::SessionToken {
+            let error = anyhow!(
+                "The console only accepts role or federated user credentials; add --role-arn or \
+                 --federation-name, or set role_arn in [{}]",
+                updater.long_term_section()
+            );
+            return Err(Failure::Config.wrap(error));
+        }
+        // Fresh credentials, as for a refresh; the stored session may be about to expire.
+        let session = match &self.federation {
+            Some(federation) => updater.update_federated_credentials(federation).await?,
+            None => updater.update_credentials_with(self.token()).await?,
+        };
+        let region = self.run.args.region.clone().or(self.settings.region.clone());
+        let sign_in = SignIn::new(region.as_deref()).with_timeout(updater.sts_timeout());
+        let token = sign_in.token(&session).await?;
+        let url = sign_in.login_url(&token, &console::destination(region.as_deref()))?;
+        // The URL signs in whoever has it, so it's never logged.
+        if open {
+            console::open(&url).failure(Failure::Config)?;
+            info!("Opened the AWS console in the default browser");
+        } else {
+            println!("{url}");
+        }
+        Ok(())
+    }
+
+    /// Encrypts the long-term keys to `--age-identity`, or to a new passphrase.
+    pub async fn encrypt_long_term(self) -> Result<()> {
+        let key = match self.run.age_identity()? {
+            Some(identity) => Key::Identity(identity),
+            None => Key::Passphrase(Questions::stdio().passphrase(true)?),
+        };
+        self.updater.encrypt_long_term(&key).await
+    }
+
+    /// Writes the encrypted long-term keys back in plaintext.
+    pub async fn decrypt_long_term(self) -> Result<()> {
+        self.updater.decrypt_long_term().await
+    }
+
+    /// Moves the long-term keys from the credentials file to the keyring.
+    pub async fn import_keyring(self) -> Result<()> {
+        self.updater.import_keyring().await
+    }
+
+    /// Refreshes the credentials once, unless the stored session is still valid, and reports the
+    /// outcome as asked to.
+    pub async fn refresh(self) -> Result<()> {
+        let (updater, args) = (&self.updater, &self.run.args);
+        if args.dry_run {
+            let (cli_cache, dotenv) = (args.write_cli_cache, self.run.dotenv_paths()?);
+            let (federation, remotes) = (self.federation.is_some(), args.push_remotes.len());
+            let (sources, verify) = (&self.sources, args.verify);
+            return plan::preview(
+                updater, sources, federation, verify, cli_cache, &dotenv, remotes,
+            )
+            .await;
+        }
+        let result = self.refresh_once().await;
+        if args.notify {
+            match &result {
+                Ok(session) => {
+                    notify::refreshed(&updater.describe_expiration(session.expiration()))
+                }
+                Err(e) => notify::failed(e),
+            }
+        }
+        result.map(drop)
+    }
+
+    /// Refreshes the credentials, or reuses the stored session, and verifies and prints it as
+    /// asked to.
+    async fn refresh_once(&self) -> Result<types::Credentials> {
+        let (updater, args) = (&self.updater, &self.run.args);
+        let no_write = args.no_write;
+        // From shell hooks and as credential_process, this runs over and over, and a valid session
+        // saves an MFA code and a call to STS. Reusing leaves the other destinations alone,
+        // though, and re-entered long-term keys call for a session made with them.
+        let reuse = !args.force
+            && !args.fix
+            && args.dotenv_paths.is_empty()
+            && !args.write_cli_cache
+            && args.push_remotes.is_empty();
+        let (session, refreshed) = match (&self.federation, no_write) {
+            (Some(federation), false) => {
+                (updater.update_federated_credentials(federation).await?, true)
+            }
+            (Some(federation), true) => {
+                (updater.fetch_federated_credentials(federation).await?, true)
+            }
+            (None, _) if reuse => match updater.reusable_session(args.min_remaining)? {
+                Some(session) => {
+                    info!(
+                        "The session is valid until {}; skipping the refresh (--force refreshes \
+                         anyway)",
+                        updater.describe_expiration(&session.expiration)
+                    );
+                    (session.credentials()?, false)
+                }
+                None if no_write => (updater.fetch_credentials_with(self.token()).await?, true),
+                None => (updater.update_credentials_with(self.token()).await?, true),
+            },
+            (None, _) => {
+                if no_write {
+                    (updater.fetch_credentials_with(self.token()).await?, true)
+                } else {
+                    (updater.update_credentials_with(self.token()).await?, true)
+                }
+            }
+        };
+
+        if args.verify {
+            let identity = updater.verify(&session).await.with_context(|| match no_write {
+                true => "The new credentials appear unusable".to_string(),
+                false => format!(
+                    "Credentials were written to {} but appear unusable",
+                    updater.path().display()
+                ),
+            })?;
+            info!("Verified: account {}, {}", identity.account, identity.arn);
+        }
+
+        // With the keyring only, there is no file for a summary to name, and credential_process
+        // reads the session from the output instead.
+        let keyring_only = !updater.credential_store().file();
+        let summary = summary(args);
+        if no_write && !args.dotenv_paths.is_empty() {
+            updater.write_dotenv(&session)?;
+        } else if no_write || summary && keyring_only {
+            let section = updater.profile().session_section();
+            let keys = updater.session_keys();
+            let role_arn = updater.assumed_role();
+            let output = args.output.unwrap_or_default();
+            print!("{}", output::render(&session, &section, output, keys, role_arn)?);
+        } else if summary {
+            let profile = updater.profile();
+            let summary = Summary::new(
+                profile.name(),
+                &profile.session_section(),
+                updater.mode(self.federation.is_some()),
+                refreshed,
+                &session,
+                updater.path(),
+            )?;
+            print!("{}", summary.to_json()?);
+        }
+
+        Ok(session)
+    }
+
+    /// Returns a function getting an MFA code.
+    fn get_token(&self) -> impl Fn() -> Result<String> + Clone + Send + 'static {
+        let get_token = self.get_token.clone();
+        move || get_token()
+    }
+
+    /// Gets an MFA code on a blocking thread, as the prompt reads stdin, while the updater
+    /// prepares the STS client.
+    async fn token(&self) -> Result<String> {
+        spawn_blocking(self.get_token()).await?
+    }
+}
+
+/// Returns whether `args` ask for a JSON summary of a refresh that writes.
+fn summary(args: &Args) -> bool {
+    !args.no_write && args.output == Some(output::Format::Json)
+}
+
+/// Selects the profile from `--profile`, then `AWS_PROFILE`, then the one `pick` returns, then
+/// `default`. Returns its name and whether it was picked.
+fn select_profile(
+    flag: Option<String>,
+    pick: impl FnOnce() -> Result<Option<String>>,
+) -> Result<(String, bool)> {
+    let env = var("AWS_PROFILE").ok().filter(|p| !p.is_empty());
+    let (name, source) = match (flag, env) {
+        (Some(name), _) => (name, "from --profile"),
+        (None, Some(name)) => (name, "from AWS_PROFILE"),
+        (None, None) => match pick()? {
+            Some(name) => {
+                info!("Using profile {name} (picked)");
+                return Ok((name, true));
+            }
+            None => ("default".to_string(), "neither --profile nor AWS_PROFILE is set"),
+        },
+    };
+
+    info!("Using profile {name} ({source})");
+    Ok((name, false))
+}
+
+/// Reads a policy document from `path`.
+fn read_policy(path: &Path) -> Result<String> {
+    read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Lets the user pick one of the profiles `candidates` on stdin.
+fn choose_profile(candidates: &[Candidate]) -> Result<usize> {
+    let items: Vec<_> = candidates.iter().map(Candidate::to_string).collect();
+    Questions::stdio().choose("Profile", &items)
+}
+
+/// Lets the user pick one of the MFA devices `names` on stdin, or fails when not `interactive`.
+fn choose_device(names: &[&str], interactive: bool) -> Result<usize> {
+    ensure!(
+        interactive,
+        "Several MFA devices are configured ({}); pick one with --mfa-device-name",
+        names.join(", ")
+    );
+    Questions::stdio().choose("MFA device", names)
+}
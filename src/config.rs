@@ -0,0 +1,124 @@
+//! Settings from the shared AWS config file (`~/.aws/config`).
+//!
+//! The config file is only a fallback for what the credentials file doesn't say, like the
+//! `mfa_serial` of a profile or the `source_profile` holding its long-term keys. Unlike the
+//! credentials file, it names profile sections `[profile <name>]`, except for `[default]`.
+
+use std::{
+    env::var_os,
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use log::debug;
+
+use crate::{credentials_file::CredentialsFile, path};
+
+/// Contents of the shared AWS config file.
+#[derive(Clone, Default)]
+pub struct AwsConfig {
+    file: CredentialsFile,
+    /// Where the file was loaded from, and is saved to.
+    path: Option<PathBuf>,
+}
+
+impl AwsConfig {
+    /// Loads the file named by `AWS_CONFIG_FILE`, defaulting to `~/.aws/config`.
+    ///
+    /// A missing file is treated as empty.
+    pub fn load() -> Result<Self> {
+        let path = match var_os("AWS_CONFIG_FILE") {
+            Some(path) => path::expand(&PathBuf::from(path))?,
+            None => match home_dir() {
+                Some(home) => home.join(".aws/config"),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        if !path.exists() {
+            debug!("No AWS config file at {}", path.display());
+            return Ok(Self { file: CredentialsFile::default(), path: Some(path) });
+        }
+        Ok(Self {
+            file: CredentialsFile::load(&path)?,
+            path: Some(path),
+        })
+    }
+
+    /// Returns the path the file was loaded from, unless it couldn't be located.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Parses the config file content.
+    ///
+    /// ```
+    /// use aws_mfa::config::AwsConfig;
+    ///
+    /// let config = AwsConfig::parse(
+    ///     "[default]\nregion = us-east-1\n\
+    ///      [profile work]\nmfa_serial = arn:aws:iam::123456789012:mfa/me\n",
+    /// );
+    /// assert_eq!(config.get("default", "region"), Some("us-east-1"));
+    /// assert_eq!(config.get("work", "mfa_serial"), Some("arn:aws:iam::123456789012:mfa/me"));
+    /// assert_eq!(config.get("work", "region"), None);
+    /// ```
+    pub fn parse(content: &str) -> Self {
+        Self { file: CredentialsFile::parse(content), path: None }
+    }
+
+    /// Returns `key` of `profile`, read from `[profile <name>]`, or `[default]` for the default
+    /// profile (which may also be written `[profile default]`).
+    pub fn get(&self, profile: &str, key: &str) -> Option<&str> {
+        let section = format!("profile {profile}");
+        match profile {
+            "default" => self.file.get("default", key).or_else(|| self.file.get(&section, key)),
+            _ => self.file.get(&section, key),
+        }
+    }
+
+    /// Returns `key` of `section`, named as in the file, e.g. `sso-session corp`.
+    pub fn get_in(&self, section: &str, key: &str) -> Option<&str> {
+        self.file.get(section, key)
+    }
+
+    /// Returns the keys of `section`, named as in the file.
+    pub fn keys<'a>(&'a self, section: &str) -> impl Iterator<Item = &'a str> {
+        self.file.keys(section)
+    }
+
+    /// Returns the names of the sections, in the order of the file.
+    pub fn sections(&self) -> impl Iterator<Item = &str> {
+        self.file.sections()
+    }
+
+    /// Sets `key` of `profile` to `value`, in `[default]` for the default profile.
+    ///
+    /// ```
+    /// use aws_mfa::config::AwsConfig;
+    ///
+    /// let mut config = AwsConfig::parse("[default]\nregion = us-east-1\n");
+    /// config.set("default", "output", "json");
+    /// config.set("work", "region", "eu-west-1");
+    /// assert_eq!(config.get("default", "output"), Some("json"));
+    /// assert_eq!(config.get("work", "region"), Some("eu-west-1"));
+    /// ```
+    pub fn set(&mut self, profile: &str, key: &str, value: &str) {
+        let section = match profile {
+            "default" => "default".to_string(),
+            _ => format!("profile {profile}"),
+        };
+        self.file.set(&section, key, value);
+    }
+
+    /// Writes the file back to where it was loaded from, creating it and its directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = self.path.as_deref().context("Could not locate the AWS config file")?;
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        self.file.save(path)
+    }
+}
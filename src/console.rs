@@ -0,0 +1,187 @@
+//! Sign-in to the AWS Management Console with temporary credentials, as `aws-vault login` does.
+//!
+//! The AWS federation endpoint exchanges the credentials of a role or federated user for a sign-in
+//! token, which a login URL then hands to the console. Sessions from GetSessionToken are refused
+//! by the endpoint. The login URL and the token in it sign in whoever opens them for the next 15
+//! minutes, so they are only ever printed to stdout or handed to the browser, never logged.
+
+use std::{process::Command, time::Duration};
+
+use anyhow::{Context, Result, anyhow, ensure};
+use aws_sdk_sts::types;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    error::{Failure, ResultExt},
+    redact::Secret,
+    tls,
+};
+
+/// Name the console shows as the issuer of the login.
+const ISSUER: &str = "aws-mfa";
+
+/// Response of `Action=getSigninToken`.
+#[derive(Deserialize)]
+struct SigninToken {
+    #[serde(rename = "SigninToken")]
+    signin_token: String,
+}
+
+/// Federation endpoint and console of the partition `region` belongs to, for the standard
+/// partition if there is no region.
+fn partition(region: Option<&str>) -> (&'static str, &'static str) {
+    match region {
+        Some(r) if r.starts_with("cn-") => {
+            ("https://signin.amazonaws.cn/federation", "console.amazonaws.cn")
+        }
+        Some(r) if r.starts_with("us-gov-") => {
+            ("https://signin.amazonaws-us-gov.com/federation", "console.amazonaws-us-gov.com")
+        }
+        _ => ("https://signin.aws.amazon.com/federation", "console.aws.amazon.com"),
+    }
+}
+
+/// Returns the console page to land on: the home page of `region`, or the console's default.
+///
+/// ```
+/// use aws_mfa::console::destination;
+///
+/// assert_eq!(destination(None), "https://console.aws.amazon.com/");
+/// assert_eq!(
+///     destination(Some("eu-west-1")),
+///     "https://eu-west-1.console.aws.amazon.com/console/home?region=eu-west-1"
+/// );
+/// assert_eq!(
+///     destination(Some("cn-north-1")),
+///     "https://cn-north-1.console.amazonaws.cn/console/home?region=cn-north-1"
+/// );
+/// ```
+pub fn destination(region: Option<&str>) -> String {
+    let (_, console) = partition(region);
+    match region {
+        Some(region) => format!("https://{region}.{console}/console/home?region={region}"),
+        None => format!("https://{console}/"),
+    }
+}
+
+/// Client of the federation endpoint.
+///
+/// ```no_run
+/// # async fn example(session: aws_sdk_sts::types::Credentials) -> anyhow::Result<()> {
+/// use aws_mfa::console::{SignIn, destination};
+///
+/// let sign_in = SignIn::new(Some("eu-west-1"));
+/// let token = sign_in.token(&session).await?;
+/// let url = sign_in.login_url(&token, &destination(Some("eu-west-1")))?;
+/// assert!(url.contains("Action=login"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct SignIn {
+    endpoint: String,
+    timeout: Duration,
+}
+
+impl SignIn {
+    /// Signs in to the console of the partition `region` belongs to, the standard one if `None`.
+    pub fn new(region: Option<&str>) -> Self {
+        let (endpoint, _) = partition(region);
+        Self {
+            endpoint: endpoint.to_string(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Uses the federation endpoint at `endpoint` instead.
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Gives up on the federation endpoint after `timeout` [default: 30s].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Exchanges `session` for a sign-in token.
+    pub async fn token(&self, session: &types::Credentials) -> Result<Secret> {
+        let json = json!({
+            "sessionId": session.access_key_id(),
+            "sessionKey": session.secret_access_key(),
+            "sessionToken": session.session_token(),
+        });
+        let client = tls::http_client(self.timeout)
+            .context("Failed to set up the HTTP client")
+            .failure(Failure::Config)?;
+        let request = client
+            .get(&self.endpoint)
+            .query(&[("Action", "getSigninToken"), ("Session", &json.to_string())]);
+
+        // The URL of the request holds the session, so errors must not quote it.
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!(e.without_url()))
+            .with_context(|| format!("Could not reach {}", self.endpoint))
+            .failure(Failure::Network)?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN => {
+                return Err(Failure::Rejected.wrap(anyhow!(
+                    "{} refused the session ({}); only role or federated user credentials can \
+                     sign in to the console, not those from GetSessionToken",
+                    self.endpoint,
+                    response.status()
+                )));
+            }
+            status => {
+                return Err(Failure::Network.wrap(anyhow!("{} answered {status}", self.endpoint)));
+            }
+        }
+        let token: SigninToken = response
+            .json()
+            .await
+            .map_err(|e| anyhow!(e.without_url()))
+            .with_context(|| format!("Unexpected answer from {}", self.endpoint))
+            .failure(Failure::Network)?;
+
+        Ok(Secret::new(token.signin_token))
+    }
+
+    /// Returns the URL signing in to the console with `token`, landing on `destination`.
+    pub fn login_url(&self, token: &Secret, destination: &str) -> Result<String> {
+        let params = [
+            ("Action", "login"),
+            ("Issuer", ISSUER),
+            ("Destination", destination),
+            ("SigninToken", token.expose()),
+        ];
+        let url = Url::parse_with_params(&self.endpoint, params)
+            .with_context(|| format!("Invalid federation endpoint {}", self.endpoint))
+            .failure(Failure::Config)?;
+        Ok(url.into())
+    }
+}
+
+/// Opens `url` in the default browser.
+pub fn open(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .arg(url)
+        .status()
+        .with_context(|| format!("Failed to run {program}"))?;
+    ensure!(status.success(), "{program} exited with {status}");
+    Ok(())
+}
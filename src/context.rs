@@ -0,0 +1,359 @@
+//! Contexts: named setups of the AWS config file, refreshed together in one run.
+//!
+//! A context is a section `[aws-mfa-context <name>]` of the AWS config file, which the AWS CLI
+//! ignores. It names a profile of a credentials file of its own, e.g. to keep work and personal
+//! setups apart, with the settings to refresh it with:
+//!
+//! ```ini
+//! [aws-mfa-context work]
+//! credentials_file = ~/.aws/credentials.work
+//! profile = default
+//! region = eu-west-1
+//! duration = 14400
+//! op_account = yourcompany.1password.com
+//! op_item_name = AWS Work
+//! ```
+//!
+//! `--context <name>` (repeatable) or `--all-contexts` refreshes them one after the other, each as
+//! a run of its own: a failure is reported and the next context refreshed, unless `--fail-fast`
+//! is given.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    future::Future,
+    path::PathBuf,
+};
+
+use anyhow::{Context as _, Result, anyhow, bail};
+use log::{info, warn};
+
+use crate::{
+    config::AwsConfig,
+    error::{self, Failure},
+    path,
+};
+
+/// Prefix of the sections of the AWS config file holding contexts.
+pub const SECTION_PREFIX: &str = "aws-mfa-context ";
+
+/// Keys a context may set.
+const KEYS: [&str; 13] = [
+    "credentials_file",
+    "profile",
+    "region",
+    "duration",
+    "op_account",
+    "op_item_name",
+    "op_secret_reference",
+    "bw_item",
+    "pass_entry",
+    "keepass_db",
+    "keepass_entry",
+    "keepass_keyfile",
+    "yubikey_account",
+];
+
+/// A context, see the [module documentation](self). Settings it leaves out are taken from the
+/// flags and environment variables of the run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Context {
+    /// Name of the context, after [`SECTION_PREFIX`].
+    pub name: String,
+    /// Credentials file, with `~` and environment variables expanded.
+    pub credentials_file: Option<PathBuf>,
+    /// Profile to refresh.
+    pub profile: Option<String>,
+    /// Region of the STS endpoint, as `region` of a long-term section.
+    pub region: Option<String>,
+    /// Session duration in seconds.
+    pub duration: Option<u32>,
+    /// 1Password account.
+    pub op_account: Option<String>,
+    /// 1Password item with the one-time password.
+    pub op_item_name: Option<String>,
+    /// 1Password secret reference to the one-time password.
+    pub op_secret_reference: Option<String>,
+    /// Bitwarden item with the TOTP.
+    pub bw_item: Option<String>,
+    /// Entry of the password store with the one-time password.
+    pub pass_entry: Option<String>,
+    /// KeePassXC database.
+    pub keepass_db: Option<PathBuf>,
+    /// KeePassXC entry with the TOTP.
+    pub keepass_entry: Option<String>,
+    /// Key file of the KeePassXC database.
+    pub keepass_keyfile: Option<PathBuf>,
+    /// OATH account of a YubiKey.
+    pub yubikey_account: Option<String>,
+}
+
+impl Context {
+    /// Returns the contexts of `config`, in the order of the file.
+    ///
+    /// ```
+    /// use aws_mfa::{config::AwsConfig, context::Context};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let config = AwsConfig::parse(
+    ///     "[profile work]\nregion = us-east-1\n\
+    ///      [aws-mfa-context work]\ncredentials_file = /home/me/.aws/credentials.work\n\
+    ///      profile = default\nregion = eu-west-1\nduration = 14400\n\
+    ///      [aws-mfa-context personal]\nop_item_name = AWS Personal\n",
+    /// );
+    /// let contexts = Context::all(&config)?;
+    /// assert_eq!(contexts.len(), 2);
+    /// assert_eq!(contexts[0].name, "work");
+    /// let work = contexts[0].credentials_file.as_deref();
+    /// assert_eq!(work, Some("/home/me/.aws/credentials.work".as_ref()));
+    /// assert_eq!(contexts[0].profile.as_deref(), Some("default"));
+    /// assert_eq!(contexts[0].region.as_deref(), Some("eu-west-1"));
+    /// assert_eq!(contexts[0].duration, Some(14400));
+    /// assert_eq!(contexts[1].op_item_name.as_deref(), Some("AWS Personal"));
+    /// assert_eq!(contexts[1].credentials_file, None);
+    ///
+    /// let config = AwsConfig::parse("[aws-mfa-context work]\nduration = 4h\n");
+    /// let error = Context::all(&config).unwrap_err();
+    /// let expected = "Invalid duration in [aws-mfa-context work]; expected seconds";
+    /// assert_eq!(error.to_string(), expected);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn all(config: &AwsConfig) -> Result<Vec<Self>> {
+        let names = config
+            .sections()
+            .filter_map(|section| section.strip_prefix(SECTION_PREFIX));
+        names.map(|name| Self::read(config, name.trim())).collect()
+    }
+
+    /// Returns the contexts of `config` named `names`, in that order.
+    ///
+    /// ```
+    /// use aws_mfa::{config::AwsConfig, context::Context};
+    ///
+    /// let config = AwsConfig::parse("[aws-mfa-context work]\n[aws-mfa-context personal]\n");
+    /// let names = |contexts: Vec<Context>| -> Vec<_> {
+    ///     contexts.into_iter().map(|c| c.name).collect()
+    /// };
+    /// let find = |names: &[&str]| {
+    ///     Context::find(&config, &names.iter().map(|n| n.to_string()).collect::<Vec<_>>())
+    /// };
+    /// assert_eq!(names(find(&["personal", "work"]).unwrap()), ["personal", "work"]);
+    /// assert_eq!(
+    ///     find(&["home"]).unwrap_err().to_string(),
+    ///     "No context home in the AWS config file; contexts: work, personal"
+    /// );
+    /// ```
+    pub fn find(config: &AwsConfig, names: &[String]) -> Result<Vec<Self>> {
+        let all = Self::all(config)?;
+        names
+            .iter()
+            .map(|name| match all.iter().find(|context| &context.name == name) {
+                Some(context) => Ok(context.clone()),
+                None if all.is_empty() => bail!(
+                    "No context {name} in the AWS config file; add an [{SECTION_PREFIX}{name}] \
+                     section"
+                ),
+                None => {
+                    let known: Vec<_> = all.iter().map(|context| context.name.as_str()).collect();
+                    bail!(
+                        "No context {name} in the AWS config file; contexts: {}",
+                        known.join(", ")
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Returns whether the context sets any of the password manager keys.
+    pub fn names_password_manager(&self) -> bool {
+        let names = [&self.op_account, &self.op_item_name, &self.op_secret_reference];
+        let mut names = names.into_iter().chain([&self.bw_item, &self.pass_entry]);
+        names.any(|name| name.is_some())
+            || self.keepass_db.is_some()
+            || self.keepass_entry.is_some()
+            || self.keepass_keyfile.is_some()
+            || self.yubikey_account.is_some()
+    }
+
+    /// Reads the context `name` from its section of `config`.
+    fn read(config: &AwsConfig, name: &str) -> Result<Self> {
+        let section = format!("{SECTION_PREFIX}{name}");
+        for key in config.keys(&section).filter(|key| !KEYS.contains(key)) {
+            warn!("Ignoring unknown key {key} in [{section}]; is it misspelled?");
+        }
+
+        let get = |key| config.get_in(&section, key).map(str::to_string);
+        let path = |key| {
+            get(key)
+                .map(|p| path::expand(&PathBuf::from(p)))
+                .transpose()
+                .with_context(|| format!("Invalid {key} in [{section}]"))
+        };
+        let duration = get("duration")
+            .map(|d| d.parse())
+            .transpose()
+            .with_context(|| format!("Invalid duration in [{section}]; expected seconds"))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            credentials_file: path("credentials_file")?,
+            profile: get("profile"),
+            region: get("region"),
+            duration,
+            op_account: get("op_account"),
+            op_item_name: get("op_item_name"),
+            op_secret_reference: get("op_secret_reference"),
+            bw_item: get("bw_item"),
+            pass_entry: get("pass_entry"),
+            keepass_db: path("keepass_db")?,
+            keepass_entry: get("keepass_entry"),
+            keepass_keyfile: path("keepass_keyfile")?,
+            yubikey_account: get("yubikey_account"),
+        })
+    }
+}
+
+/// How the refresh of a context went.
+#[derive(Debug)]
+pub enum Outcome {
+    /// Refreshed.
+    Refreshed,
+    /// Failed with the error.
+    Failed(anyhow::Error),
+    /// Not attempted, as an earlier context failed with `--fail-fast`.
+    Skipped,
+}
+
+/// Outcomes of [`refresh_all`], by context.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Name and outcome of each context, in the order they were given.
+    pub outcomes: Vec<(String, Outcome)>,
+}
+
+impl Report {
+    /// Returns an error naming the contexts that failed, if any, classified like the first
+    /// failure so that the exit code tells what went wrong.
+    pub fn into_result(self) -> Result<()> {
+        let total = self.outcomes.len();
+        let mut failed = self.outcomes.into_iter().filter_map(|(name, outcome)| match outcome {
+            Outcome::Failed(error) => Some((name, error)),
+            _ => None,
+        });
+        let Some((first, error)) = failed.next() else { return Ok(()) };
+        let names: Vec<_> = [first].into_iter().chain(failed.map(|(name, _)| name)).collect();
+        let summary = anyhow!("{} of {total} contexts failed: {}", names.len(), names.join(", "));
+        Err(match Failure::of(&error) {
+            Some(failure) => failure.wrap(summary),
+            None => summary,
+        })
+    }
+}
+
+impl Display for Report {
+    /// Writes a line per context.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, outcome) in &self.outcomes {
+            match outcome {
+                Outcome::Refreshed => writeln!(f, "{name}: refreshed")?,
+                Outcome::Failed(error) => writeln!(f, "{name}: failed: {}", error::Report(error))?,
+                Outcome::Skipped => writeln!(f, "{name}: skipped after a failure (--fail-fast)")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Refreshes `contexts` one after the other with `refresh`, going on after a failure unless
+/// `fail_fast` is set.
+///
+/// ```
+/// use std::fs;
+///
+/// use aws_mfa::{
+///     config::AwsConfig,
+///     context::{Context, refresh_all},
+///     credentials_file::CredentialsFile,
+///     error::Failure,
+/// };
+/// use anyhow::anyhow;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// // Two separate AWS homes, the second of which lacks its long-term section.
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-contexts-{}", std::process::id()));
+/// for home in ["work", "personal"] {
+///     fs::create_dir_all(dir.join(home))?;
+/// }
+/// fs::write(dir.join("work/credentials"), "[default-long-term]\naws_access_key_id = AKIA1\n")?;
+/// fs::write(dir.join("personal/credentials"), "")?;
+/// let config = AwsConfig::parse(&format!(
+///     "[aws-mfa-context work]\ncredentials_file = {0}/work/credentials\n\
+///      [aws-mfa-context personal]\ncredentials_file = {0}/personal/credentials\n",
+///     dir.display()
+/// ));
+/// let contexts = Context::all(&config)?;
+///
+/// // Stands in for a refresh, writing the session next to the long-term keys.
+/// let refresh = |context: &Context| {
+///     let path = context.credentials_file.clone().unwrap();
+///     async move {
+///         let mut file = CredentialsFile::load(&path)?;
+///         if file.get("default-long-term", "aws_access_key_id").is_none() {
+///             let error = anyhow!("No [default-long-term] in {}", path.display());
+///             return Err(Failure::Config.wrap(error));
+///         }
+///         file.set("default", "aws_access_key_id", "ASIA1");
+///         file.save(&path)
+///     }
+/// };
+///
+/// // Both are refreshed in order, and the failure of one doesn't stop the other.
+/// let mut reversed = contexts.clone();
+/// reversed.reverse();
+/// let report = refresh_all(&reversed, false, refresh).await;
+/// let lines: Vec<_> = report.to_string().lines().map(str::to_string).collect();
+/// assert_eq!(lines.len(), 2);
+/// assert!(lines[0].starts_with("personal: failed: No [default-long-term] in "), "{}", lines[0]);
+/// assert_eq!(lines[1], "work: refreshed");
+/// let work = CredentialsFile::load(&dir.join("work/credentials"))?;
+/// assert_eq!(work.get("default", "aws_access_key_id"), Some("ASIA1"));
+/// let error = report.into_result().unwrap_err();
+/// assert_eq!(error.to_string(), "1 of 2 contexts failed: personal");
+/// assert_eq!(Failure::of(&error), Some(Failure::Config));
+///
+/// // With fail_fast, the contexts after a failure are skipped.
+/// let report = refresh_all(&reversed, true, refresh).await;
+/// assert!(report.to_string().ends_with("work: skipped after a failure (--fail-fast)\n"));
+///
+/// // All refreshed, the report is a success.
+/// assert!(refresh_all(&contexts[..1], true, refresh).await.into_result().is_ok());
+/// # fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn refresh_all<F, Fut>(contexts: &[Context], fail_fast: bool, mut refresh: F) -> Report
+where
+    F: FnMut(&Context) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut report = Report::default();
+    let mut failed = false;
+    for context in contexts {
+        let outcome = match failed && fail_fast {
+            true => Outcome::Skipped,
+            false => {
+                info!("Refreshing context {}", context.name);
+                match refresh(context).await {
+                    Ok(()) => Outcome::Refreshed,
+                    Err(e) => {
+                        failed = true;
+                        Outcome::Failed(e)
+                    }
+                }
+            }
+        };
+        report.outcomes.push((context.name.clone(), outcome));
+    }
+    report
+}
@@ -0,0 +1,281 @@
+//! Native dialogs asking for the MFA code where there is no terminal, e.g. in IDE tasks or
+//! launchd jobs.
+//!
+//! Rather than linking a GUI toolkit, the dialog is shown by a program that comes with the
+//! desktop: `osascript` on macOS, `zenity` or `kdialog` elsewhere. [`DialogPrompt`] is a
+//! [`Prompt`] like any other, so it only appears once the password managers have failed.
+//! [`ManualPrompt`] picks between a dialog and stdin.
+
+use std::{
+    env::{split_paths, var_os},
+    io::{IsTerminal, stdin},
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::{
+    error::{Failure, ResultExt},
+    token::{MAX_ATTEMPTS, Prompt, StdinPrompt, TokenLength, parse_token},
+};
+
+/// Title of the dialog.
+const TITLE: &str = "aws-mfa";
+
+/// AppleScript showing the dialog, with the text and the seconds to give up after as arguments.
+const APPLESCRIPT: &str = r#"on run argv
+    set answer to display dialog (item 1 of argv) default answer "" with title "aws-mfa" ¬
+        buttons {"Cancel", "OK"} default button "OK" cancel button "Cancel" ¬
+        giving up after (item 2 of argv as integer)
+    if gave up of answer then error "timed out" number 1
+    return text returned of answer
+end run"#;
+
+/// Seconds the macOS dialog waits without a timeout; `giving up after` can't be left out.
+const NO_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Program showing the dialog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// AppleScript's `display dialog`, on macOS.
+    Osascript,
+    /// GNOME's `zenity --entry`.
+    Zenity,
+    /// KDE's `kdialog --inputbox`.
+    Kdialog,
+}
+
+/// Asks for the MFA code in a dialog, up to [`MAX_ATTEMPTS`] times while the input isn't a
+/// valid code. Cancelling fails with [`Failure::Cancelled`].
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{fs, os::unix::fs::PermissionsExt};
+///
+/// use aws_mfa::{
+///     dialog::{DialogPrompt, Kind},
+///     error::Failure,
+///     token::Prompt,
+/// };
+///
+/// // A stub zenity that answers with the lines of `answers` in turn, and cancels after them.
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-dialog-{}", std::process::id()));
+/// fs::create_dir_all(&dir)?;
+/// let stub = dir.join("zenity");
+/// let answers = dir.join("answers");
+/// fs::write(
+///     &stub,
+///     format!(
+///         "#!/bin/sh\n\
+///          echo \"$*\" >>{0}.log\n\
+///          line=$(head -n 1 {0}) && [ -n \"$line\" ] || exit 1\n\
+///          sed -i.bak 1d {0}\n\
+///          [ \"$line\" = timeout ] && exit 5\n\
+///          echo \"$line\"\n",
+///         answers.display()
+///     ),
+/// )?;
+/// fs::set_permissions(&stub, fs::Permissions::from_mode(0o755))?;
+/// let prompt = DialogPrompt::new(Kind::Zenity, &stub);
+///
+/// // Invalid input is asked for again, with the reason.
+/// fs::write(&answers, "12\nYour code is 123456\n")?;
+/// assert_eq!(prompt.prompt()?, "123456");
+/// let log = fs::read_to_string(dir.join("answers.log"))?;
+/// assert!(log.lines().nth(1).unwrap().contains("Invalid code: expected 6 digits, got 2"));
+///
+/// fs::write(&answers, "")?;
+/// let cancelled = prompt.prompt().unwrap_err();
+/// assert_eq!(Failure::of(&cancelled), Some(Failure::Cancelled));
+///
+/// fs::write(&answers, "timeout\n")?;
+/// let timed_out = prompt.prompt().unwrap_err();
+/// assert_eq!(Failure::of(&timed_out), Some(Failure::Token));
+///
+/// fs::write(&answers, "1\n2\n3\n4\n")?;
+/// assert!(prompt.prompt().unwrap_err().to_string().contains("after 3 attempts"));
+/// # fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug)]
+pub struct DialogPrompt {
+    kind: Kind,
+    program: PathBuf,
+    /// Give up when no code is entered within this time.
+    pub timeout: Option<Duration>,
+    /// Number of digits a code must have.
+    pub length: TokenLength,
+}
+
+impl DialogPrompt {
+    /// Shows dialogs of `kind` by running `program`.
+    pub fn new(kind: Kind, program: impl Into<PathBuf>) -> Self {
+        Self {
+            kind,
+            program: program.into(),
+            timeout: None,
+            length: TokenLength::default(),
+        }
+    }
+
+    /// Returns a prompt for the desktop this runs on, if there is one: macOS outside of SSH
+    /// sessions, or an X11 or Wayland display with `zenity` or `kdialog` on the `PATH`.
+    pub fn detect() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            return var_os("SSH_CONNECTION")
+                .is_none()
+                .then(|| Self::new(Kind::Osascript, "osascript"));
+        }
+        if var_os("DISPLAY").is_none() && var_os("WAYLAND_DISPLAY").is_none() {
+            return None;
+        }
+        let path = var_os("PATH")?;
+        [(Kind::Zenity, "zenity"), (Kind::Kdialog, "kdialog")]
+            .into_iter()
+            .find_map(|(kind, name)| {
+                let program = split_paths(&path).map(|dir| dir.join(name)).find(|p| p.is_file())?;
+                Some(Self::new(kind, program))
+            })
+    }
+
+    /// Shows the dialog with `text` and returns what was entered, or `None` if it was cancelled.
+    fn ask(&self, text: &str) -> Result<Option<String>> {
+        let mut command = Command::new(&self.program);
+        let timeout = self.timeout.map(|t| t.as_secs().max(1));
+        match self.kind {
+            Kind::Osascript => {
+                let secs = timeout.unwrap_or(NO_TIMEOUT_SECS).to_string();
+                command.args(["-e", APPLESCRIPT, text, &secs]);
+            }
+            Kind::Zenity => {
+                command.args(["--entry", "--title", TITLE, "--text", text]);
+                if let Some(secs) = timeout {
+                    command.arg(format!("--timeout={secs}"));
+                }
+            }
+            Kind::Kdialog => {
+                command.args(["--title", TITLE, "--inputbox", text]);
+            }
+        }
+
+        let output = command
+            .output()
+            .with_context(|| format!("Failed to run {}", self.program.display()))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match (self.kind, output.status.code()) {
+            (_, Some(0)) => Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned())),
+            (Kind::Osascript, _) if stderr.contains("(-128)") => Ok(None),
+            (Kind::Osascript, _) if stderr.contains("timed out") => bail!("Dialog timed out"),
+            (Kind::Zenity, Some(5)) => bail!("Dialog timed out"),
+            (Kind::Zenity | Kind::Kdialog, Some(1)) => Ok(None),
+            _ => {
+                bail!("{} exited with {}: {}", self.program.display(), output.status, stderr.trim())
+            }
+        }
+    }
+}
+
+impl Prompt for DialogPrompt {
+    fn prompt(&self) -> Result<String> {
+        let mut text = "Enter the AWS MFA code:".to_string();
+        for _ in 0..MAX_ATTEMPTS {
+            let Some(input) = self.ask(&text).failure(Failure::Token)? else {
+                return Err(Failure::Cancelled.wrap(anyhow!("MFA code entry was cancelled")));
+            };
+            match parse_token(&input, self.length) {
+                Ok(token) => return Ok(token),
+                Err(problem) => {
+                    text = format!("Invalid code: {problem}\n\nEnter the AWS MFA code:")
+                }
+            }
+        }
+
+        Err(Failure::Token.wrap(anyhow!("No valid MFA code entered after {MAX_ATTEMPTS} attempts")))
+    }
+}
+
+/// How the MFA code is asked for when no password manager provides it.
+#[derive(Clone)]
+pub enum ManualPrompt {
+    /// On stdin.
+    Terminal(StdinPrompt),
+    /// In a dialog.
+    Dialog(DialogPrompt),
+    /// Neither a terminal nor a desktop to ask on: fails right away rather than waiting on stdin.
+    Unavailable,
+}
+
+impl ManualPrompt {
+    /// Picks the prompt for MFA codes of `length`: a dialog with `gui`, or without a terminal
+    /// where a desktop is available, and stdin otherwise. `unattended` runs have none.
+    pub fn pick(
+        gui: bool,
+        unattended: bool,
+        timeout: Option<Duration>,
+        length: TokenLength,
+    ) -> Result<Self> {
+        let dialog = || {
+            DialogPrompt::detect().map(|mut dialog| {
+                dialog.timeout = timeout;
+                dialog.length = length;
+                Self::Dialog(dialog)
+            })
+        };
+        if gui {
+            return dialog()
+                .context(
+                    "--gui-prompt needs a desktop: macOS, or an X11 or Wayland display with zenity \
+                     or kdialog",
+                )
+                .failure(Failure::Config);
+        }
+
+        if unattended {
+            return Ok(Self::Unavailable);
+        }
+        let terminal = Self::Terminal(StdinPrompt { timeout, length });
+        if stdin().is_terminal() {
+            return Ok(terminal);
+        }
+        Ok(match dialog() {
+            Some(dialog) => dialog,
+            None if stdin_is_pipe() => terminal,
+            None => Self::Unavailable,
+        })
+    }
+}
+
+impl Prompt for ManualPrompt {
+    fn prompt(&self) -> Result<String> {
+        match self {
+            Self::Terminal(prompt) => prompt.prompt(),
+            Self::Dialog(prompt) => prompt.prompt(),
+            Self::Unavailable => Err(Failure::Token.wrap(anyhow!(
+                "No terminal or desktop to ask for the MFA code on; configure a password manager, \
+                 pipe the code to stdin, or run aws-mfa in a terminal"
+            ))),
+        }
+    }
+}
+
+/// Returns whether stdin is a pipe or a file that a code can be read from, rather than e.g.
+/// `/dev/null` as in launchd jobs.
+#[cfg(unix)]
+fn stdin_is_pipe() -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    std::fs::metadata("/dev/stdin")
+        .map(|meta| meta.file_type())
+        .is_ok_and(|t| t.is_fifo() || t.is_file() || t.is_socket())
+}
+
+#[cfg(not(unix))]
+fn stdin_is_pipe() -> bool {
+    true
+}
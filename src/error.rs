@@ -0,0 +1,216 @@
+//! Failure classification and process exit codes.
+//!
+//! Errors are plain [`anyhow::Error`]s throughout; the places that know what went wrong wrap them
+//! with a [`Failure`] class, which `main` turns into the exit code. Unclassified errors exit with
+//! 1.
+
+use std::{
+    error::Error as StdError,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, ErrorKind},
+    process::ExitCode,
+};
+
+use aws_sdk_sts::error::SdkError;
+
+/// Failure classes, each mapped to a distinct exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Configuration or credentials file problems.
+    Config = 2,
+    /// No MFA token could be obtained.
+    Token = 3,
+    /// STS rejected the request, usually because of a bad MFA code.
+    Rejected = 4,
+    /// Network or other transient errors while talking to AWS.
+    Network = 5,
+    /// Writing the credentials failed.
+    Write = 6,
+    /// The user cancelled the MFA code dialog.
+    Cancelled = 7,
+    /// An MFA code had to be asked for in a non-interactive run.
+    Unattended = 8,
+}
+
+impl Failure {
+    /// Returns a short human-readable description of the class.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Config => "configuration problem",
+            Self::Token => "no MFA code available",
+            Self::Rejected => "rejected by AWS",
+            Self::Network => "network error",
+            Self::Write => "could not write credentials",
+            Self::Cancelled => "cancelled by the user",
+            Self::Unattended => "input needed in a non-interactive run",
+        }
+    }
+
+    /// Returns the class attached to `error`, if any.
+    pub fn of(error: &anyhow::Error) -> Option<Self> {
+        error.downcast_ref::<Classified>().map(|c| c.failure)
+    }
+
+    /// Returns the process exit code for `error`.
+    pub fn exit_code(error: &anyhow::Error) -> ExitCode {
+        ExitCode::from(Self::of(error).map_or(1, |f| f as u8))
+    }
+
+    /// Attaches this class to `error`, unless it already carries one.
+    pub fn wrap(self, error: impl Into<anyhow::Error>) -> anyhow::Error {
+        let error = error.into();
+        if Self::of(&error).is_some() {
+            return error;
+        }
+        Classified { failure: self, source: error }.into()
+    }
+
+    /// Classifies an AWS SDK error: service responses are rejections, anything else is treated
+    /// as a network/transient problem.
+    pub fn sdk<E, R>(error: SdkError<E, R>) -> anyhow::Error
+    where
+        SdkError<E, R>: StdError + Send + Sync + 'static,
+    {
+        let failure = match &error {
+            SdkError::ServiceError(_) => Self::Rejected,
+            _ => Self::Network,
+        };
+        failure.wrap(error)
+    }
+}
+
+/// Returns whether `error` was caused by a file system operation that may well succeed when
+/// tried again, such as one interrupted or timed out, rather than by a full disk or missing
+/// permissions.
+///
+/// ```
+/// use std::io::{Error, ErrorKind};
+///
+/// use anyhow::Context;
+/// use aws_mfa::error::{Failure, is_transient};
+///
+/// let failed = |kind| {
+///     let error = Err::<(), _>(Error::from(kind)).context("Failed to write credentials");
+///     error.map_err(|e| Failure::Write.wrap(e)).unwrap_err()
+/// };
+/// assert!(is_transient(&failed(ErrorKind::Interrupted)));
+/// assert!(is_transient(&failed(ErrorKind::TimedOut)));
+/// assert!(!is_transient(&failed(ErrorKind::StorageFull)));
+/// assert!(!is_transient(&failed(ErrorKind::PermissionDenied)));
+/// assert!(!is_transient(&anyhow::anyhow!("Invalid expiration")));
+/// ```
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    let transient = |e: &io::Error| {
+        matches!(
+            e.kind(),
+            ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::TimedOut
+                | ErrorKind::ResourceBusy
+        )
+    };
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<io::Error>())
+        .any(transient)
+}
+
+/// Extension for attaching a [`Failure`] class to results.
+pub trait ResultExt<T> {
+    /// Attaches `failure` to the error, unless it already carries a class.
+    fn failure(self, failure: Failure) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for Result<T, E> {
+    fn failure(self, failure: Failure) -> anyhow::Result<T> {
+        self.map_err(|e| failure.wrap(e))
+    }
+}
+
+/// An error tagged with its class. Displays exactly like the wrapped error.
+struct Classified {
+    failure: Failure,
+    source: anyhow::Error,
+}
+
+impl Display for Classified {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.source, f)
+    }
+}
+
+impl Debug for Classified {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.source, f)
+    }
+}
+
+impl StdError for Classified {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// Formats an error with its causes, on one line with `{}` like anyhow's `{:#}`, or as a list
+/// with `{:?}` like anyhow's `{:?}`.
+///
+/// Unlike anyhow's formats, the message of an error a [`Failure`] is attached to is shown once,
+/// not once for the class and once for the error itself.
+///
+/// ```
+/// use anyhow::{Context, anyhow};
+/// use aws_mfa::error::{Failure, Report};
+///
+/// let error = Failure::Config
+///     .wrap(anyhow!("No such file").context("Failed to read ~/.aws/credentials"))
+///     .context("Failed to load the profile");
+/// assert_eq!(
+///     Report(&error).to_string(),
+///     "Failed to load the profile: Failed to read ~/.aws/credentials: No such file"
+/// );
+/// assert_eq!(
+///     format!("{:?}", Report(&error)),
+///     "Failed to load the profile\n\nCaused by:\n    0: Failed to read ~/.aws/credentials\n    \
+///      1: No such file"
+/// );
+/// ```
+pub struct Report<'a>(pub &'a anyhow::Error);
+
+impl Report<'_> {
+    /// Returns the messages of the error and its causes, without immediate repeats.
+    fn messages(&self) -> Vec<String> {
+        let mut messages: Vec<String> = Vec::new();
+        for error in self.0.chain() {
+            let message = error.to_string();
+            if messages.last() != Some(&message) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.messages().join(": "))
+    }
+}
+
+impl Debug for Report<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let messages = self.messages();
+        let Some((first, causes)) = messages.split_first() else { return Ok(()) };
+        f.write_str(first)?;
+        match causes {
+            [] => Ok(()),
+            [cause] => write!(f, "\n\nCaused by:\n    {cause}"),
+            causes => {
+                f.write_str("\n\nCaused by:")?;
+                causes
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(i, cause)| write!(f, "\n    {i}: {cause}"))
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Running a command with the session credentials in its environment, like `aws-vault exec`.
+//!
+//! On Unix, aws-mfa replaces itself with the command (`execvp`), so the command gets the
+//! terminal, every signal and the process ID aws-mfa had, and its exit status is aws-mfa's. A
+//! Ctrl-C thus reaches the command just once, and tools such as Terraform can clean up. Elsewhere
+//! the command runs as a child that aws-mfa waits for, ignoring Ctrl-C itself, which the console
+//! delivers to the child too.
+
+use std::{ffi::OsString, process::Command};
+
+use anyhow::{Context, Result, ensure};
+use aws_sdk_sts::types;
+
+use crate::output::env_vars;
+
+/// Builds the command `args` (program first) with `session` in its environment, replacing any
+/// AWS credentials it would have inherited. Standard streams are inherited.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use aws_mfa::exec::command;
+/// use aws_sdk_sts::types::Credentials;
+/// use aws_smithy_types::DateTime;
+///
+/// let session = Credentials::builder()
+///     .access_key_id("ASIAEXAMPLE")
+///     .secret_access_key("secret")
+///     .session_token("token")
+///     .expiration(DateTime::from_secs(1_714_764_064))
+///     .build()?;
+///
+/// let script = "echo $AWS_SESSION_TOKEN $AWS_CREDENTIAL_EXPIRATION; exit 7";
+/// let output = command(&session, &["sh".into(), "-c".into(), script.into()])?.output()?;
+/// assert_eq!(String::from_utf8(output.stdout)?, "token 2024-05-03T19:21:04Z\n");
+/// assert_eq!(output.status.code(), Some(7));
+///
+/// assert!(command(&session, &[]).is_err());
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub fn command(session: &types::Credentials, args: &[OsString]) -> Result<Command> {
+    ensure!(!args.is_empty(), "No command to run");
+
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+    // A profile or a stale token from the environment would otherwise win in some tools.
+    for name in ["AWS_PROFILE", "AWS_DEFAULT_PROFILE", "AWS_SECURITY_TOKEN"] {
+        command.env_remove(name);
+    }
+    command.envs(env_vars(session)?);
+    Ok(command)
+}
+
+/// Runs `command` in place of this process. Only returns if the command couldn't be run.
+#[cfg(unix)]
+pub async fn run(mut command: Command) -> Result<i32> {
+    use std::os::unix::process::CommandExt;
+
+    let error = command.exec();
+    Err(error).with_context(|| format!("Failed to run {}", command.get_program().display()))
+}
+
+/// Runs `command` and returns its exit code once it has finished.
+#[cfg(not(unix))]
+pub async fn run(mut command: Command) -> Result<i32> {
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run {}", command.get_program().display()))?;
+
+    // Ctrl-C reaches the child as well; aws-mfa only has to outlive it.
+    tokio::spawn(async { while tokio::signal::ctrl_c().await.is_ok() {} });
+    let status = tokio::task::spawn_blocking(move || child.wait()).await??;
+    Ok(status.code().unwrap_or(1))
+}
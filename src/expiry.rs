@@ -0,0 +1,141 @@
+//! Session expirations: whether a recorded session is still worth handing out, and their
+//! human-friendly display.
+//!
+//! The credentials file keeps the UTC ISO-8601 form for compatibility; the display is only used
+//! for output, e.g. "12:21 local time (in 11h 59m)".
+
+use std::time::{Duration, SystemTime};
+
+use aws_smithy_types::{DateTime, date_time::Format};
+use jiff::{Timestamp, tz::TimeZone};
+
+use crate::history::parse_age;
+
+/// Remaining validity below which the modes serving credentials on demand refresh the session
+/// rather than hand it out.
+pub const DEFAULT_MIN_REMAINING: Duration = Duration::from_secs(10 * 60);
+
+/// Returns whether the session recorded to expire at `expiration`, in the form of the credentials
+/// file, has at least `min_remaining` left at `now`. A missing or malformed expiration calls for a
+/// refresh like an expired one does.
+///
+/// `exec`, `server` and `--output json` all decide with this whether to hand out the session in
+/// the file, so that a session doesn't run out in the middle of what it was handed out for.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// use aws_mfa::expiry::is_reusable;
+///
+/// // 2024-05-03T19:21:04Z
+/// let expiration = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_764_064);
+/// let recorded = Some("2024-05-03T19:21:04Z");
+/// let ten_minutes = Duration::from_secs(600);
+/// let before = |secs| expiration - Duration::from_secs(secs);
+///
+/// assert!(is_reusable(before(3600), recorded, ten_minutes));
+/// // Exactly the threshold left is enough, a second less isn't.
+/// assert!(is_reusable(before(600), recorded, ten_minutes));
+/// assert!(!is_reusable(before(599), recorded, ten_minutes));
+/// // Still valid for 90 seconds, but not for long enough.
+/// assert!(!is_reusable(before(90), recorded, ten_minutes));
+/// assert!(!is_reusable(expiration, recorded, ten_minutes));
+/// assert!(!is_reusable(expiration + ten_minutes, recorded, ten_minutes));
+/// // Without a threshold, any session not expired yet does.
+/// assert!(is_reusable(before(1), recorded, Duration::ZERO));
+///
+/// // Fractional seconds are fine; anything else refreshes rather than panics.
+/// assert!(is_reusable(before(600), Some("2024-05-03T19:21:04.000Z"), ten_minutes));
+/// assert!(!is_reusable(before(3600), Some("2024-05-03 19:21"), ten_minutes));
+/// assert!(!is_reusable(before(3600), Some("tomorrow"), ten_minutes));
+/// assert!(!is_reusable(before(3600), Some(""), ten_minutes));
+/// assert!(!is_reusable(before(3600), None, ten_minutes));
+/// ```
+pub fn is_reusable(now: SystemTime, expiration: Option<&str>, min_remaining: Duration) -> bool {
+    let expiration = expiration
+        .and_then(|expiration| DateTime::from_str(expiration, Format::DateTime).ok())
+        .and_then(|expiration| SystemTime::try_from(expiration).ok());
+    expiration
+        .and_then(|expiration| expiration.duration_since(now).ok())
+        .is_some_and(|left| !left.is_zero() && left >= min_remaining)
+}
+
+/// Parses a minimum remaining validity such as `10m` or `1h`, or a plain number of seconds.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use aws_mfa::expiry::parse_min_remaining;
+///
+/// assert_eq!(parse_min_remaining("10m"), Ok(Duration::from_secs(600)));
+/// assert_eq!(parse_min_remaining("300"), Ok(Duration::from_secs(300)));
+/// assert!(parse_min_remaining("soon").is_err());
+/// ```
+pub fn parse_min_remaining(value: &str) -> Result<Duration, String> {
+    match value.parse() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => parse_age(value).map_err(|_| {
+            format!("invalid duration {value:?}; use seconds, or a number with s, m or h, e.g. 10m")
+        }),
+    }
+}
+
+/// Describes `expiration` relative to now, in local time unless `utc` is set.
+pub fn describe(expiration: &DateTime, utc: bool) -> String {
+    let now = DateTime::from(SystemTime::now());
+    let remaining = humanize(expiration.secs() - now.secs());
+
+    let at = match Timestamp::from_second(expiration.secs()) {
+        Ok(timestamp) if !utc => {
+            let zone = TimeZone::system();
+            let local = timestamp.to_zoned(zone.clone());
+            let today = Timestamp::now().to_zoned(zone).date();
+            if local.date() == today {
+                local.strftime("%H:%M local time").to_string()
+            } else {
+                local.strftime("%a %H:%M local time").to_string()
+            }
+        }
+        _ => expiration.fmt(Format::DateTime).unwrap_or_default(),
+    };
+
+    format!("{at} ({remaining})")
+}
+
+/// Renders a session duration of `secs` seconds as hours and minutes.
+///
+/// ```
+/// use aws_mfa::expiry::span;
+///
+/// assert_eq!(span(43_200), "12h");
+/// assert_eq!(span(5_400), "1h 30m");
+/// assert_eq!(span(900), "15m");
+/// ```
+pub fn span(secs: u32) -> String {
+    match (secs / 3600, secs % 3600 / 60) {
+        (0, m) => format!("{m}m"),
+        (h, 0) => format!("{h}h"),
+        (h, m) => format!("{h}h {m}m"),
+    }
+}
+
+/// Renders a remaining duration of `secs` seconds with minute precision.
+///
+/// ```
+/// use aws_mfa::expiry::humanize;
+///
+/// assert_eq!(humanize(43_140), "in 11h 59m");
+/// assert_eq!(humanize(300), "in 5m");
+/// assert_eq!(humanize(42), "in less than a minute");
+/// assert_eq!(humanize(0), "expired");
+/// assert_eq!(humanize(-90), "expired");
+/// ```
+pub fn humanize(secs: i64) -> String {
+    let minutes = secs / 60;
+    match (minutes / 60, minutes % 60) {
+        _ if secs <= 0 => "expired".to_string(),
+        (0, 0) => "in less than a minute".to_string(),
+        (0, m) => format!("in {m}m"),
+        (h, m) => format!("in {h}h {m}m"),
+    }
+}
@@ -0,0 +1,227 @@
+//! Commands run on events of the session, such as a refresh, e.g. to update a status bar.
+//!
+//! A hook is a shell command. It learns about the event from environment variables, never from
+//! the credentials: the profile, the expiration and the mode, see [`Event::vars`]. Hooks are
+//! conveniences, so one that fails or hangs only gets a warning, and what it prints is logged at
+//! debug level.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use log::{debug, warn};
+use tokio::{process::Command, time::timeout};
+
+use crate::error::Report;
+
+/// How long a hook may run before it is killed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Variables that could hand credentials to a hook if it inherited them.
+const CREDENTIAL_VARS: [&str; 5] = [
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AWS_SECURITY_TOKEN",
+    "AWS_CREDENTIAL_EXPIRATION",
+];
+
+/// What happened to the session of a profile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// What happened.
+    pub kind: Kind,
+    /// Profile the session belongs to.
+    pub profile: String,
+    /// Expiration of the session, in the ISO-8601 form of the credentials file.
+    pub expiration: String,
+    /// How the session was obtained, as in the JSON summary, e.g. `session-token`.
+    pub mode: String,
+}
+
+/// Kinds of [`Event`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// The session was refreshed.
+    Refreshed,
+    /// The session is about to expire, and will be refreshed.
+    Expiring,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Refreshed => "refreshed",
+            Self::Expiring => "expiring",
+        })
+    }
+}
+
+impl Event {
+    /// Returns the environment variables a hook gets for the event.
+    ///
+    /// ```
+    /// use aws_mfa::hook::{Event, Kind};
+    ///
+    /// let event = Event {
+    ///     kind: Kind::Refreshed,
+    ///     profile: "work".into(),
+    ///     expiration: "2024-05-03T19:21:04Z".into(),
+    ///     mode: "session-token".into(),
+    /// };
+    /// assert_eq!(
+    ///     event.vars(),
+    ///     [
+    ///         ("AWS_MFA_EVENT", "refreshed".to_string()),
+    ///         ("AWS_MFA_PROFILE", "work".to_string()),
+    ///         ("AWS_MFA_EXPIRATION", "2024-05-03T19:21:04Z".to_string()),
+    ///         ("AWS_MFA_MODE", "session-token".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn vars(&self) -> [(&'static str, String); 4] {
+        [
+            ("AWS_MFA_EVENT", self.kind.to_string()),
+            ("AWS_MFA_PROFILE", self.profile.clone()),
+            ("AWS_MFA_EXPIRATION", self.expiration.clone()),
+            ("AWS_MFA_MODE", self.mode.clone()),
+        ]
+    }
+}
+
+/// A shell command run on an [`Event`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hook {
+    command: String,
+    timeout: Duration,
+}
+
+impl Hook {
+    /// Creates a hook running `command` with the shell (`sh -c`, or `cmd /C` on Windows), killed
+    /// after [`DEFAULT_TIMEOUT`].
+    pub fn new(command: String) -> Self {
+        Self { command, timeout: DEFAULT_TIMEOUT }
+    }
+
+    /// Kills the hook after `timeout` instead of [`DEFAULT_TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the command the hook runs.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Runs the hook for `event`, failing if it can't be started, exits unsuccessfully or is
+    /// still running after the timeout, in which case it is killed. What it prints is logged at
+    /// debug level. See [`Self::fire`] for a run that only warns.
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// use std::time::{Duration, Instant};
+    ///
+    /// use aws_mfa::hook::{Event, Hook, Kind};
+    ///
+    /// let dir = std::env::temp_dir().join(format!("aws-mfa-hook-{}", std::process::id()));
+    /// std::fs::create_dir_all(&dir)?;
+    /// let out = dir.join("out");
+    /// let event = Event {
+    ///     kind: Kind::Expiring,
+    ///     profile: "work".into(),
+    ///     expiration: "2024-05-03T19:21:04Z".into(),
+    ///     mode: "assume-role".into(),
+    /// };
+    ///
+    /// // A hook that succeeds sees the event, and no credentials in the environment of aws-mfa.
+    /// let script = format!(
+    ///     "echo \"$AWS_MFA_EVENT $AWS_MFA_PROFILE $AWS_MFA_MODE $AWS_MFA_EXPIRATION \
+    ///      ${{AWS_SECRET_ACCESS_KEY:-none}}\" > '{}'",
+    ///     out.display()
+    /// );
+    /// Hook::new(script).run(&event).await?;
+    /// assert_eq!(
+    ///     std::fs::read_to_string(&out)?,
+    ///     "expiring work assume-role 2024-05-03T19:21:04Z none\n"
+    /// );
+    ///
+    /// // One that fails says how, with the last line it printed.
+    /// let error = Hook::new("echo 'widget not running' >&2; exit 3".into()).run(&event).await;
+    /// assert_eq!(
+    ///     error.unwrap_err().to_string(),
+    ///     "The hook exited with exit status: 3: widget not running"
+    /// );
+    ///
+    /// // One that hangs is killed once the timeout is up.
+    /// let started = Instant::now();
+    /// let hook = Hook::new("sleep 10".into()).with_timeout(Duration::from_millis(200));
+    /// let error = hook.run(&event).await.unwrap_err();
+    /// assert_eq!(error.to_string(), "The hook didn't finish within 0.2s, and was killed");
+    /// assert!(started.elapsed() < Duration::from_secs(5));
+    ///
+    /// // Firing a hook never fails.
+    /// Hook::new("exit 1".into()).fire(&event).await;
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(unix))]
+    /// # fn main() {}
+    /// ```
+    pub async fn run(&self, event: &Event) -> Result<()> {
+        let mut command = shell(&self.command);
+        for name in CREDENTIAL_VARS {
+            command.env_remove(name);
+        }
+        let child = command
+            .envs(event.vars())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start the hook")?;
+
+        let output = match timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => output.context("Failed to wait for the hook")?,
+            Err(_) => bail!(
+                "The hook didn't finish within {}s, and was killed",
+                self.timeout.as_secs_f32()
+            ),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        for line in stdout.lines().chain(stderr.lines()) {
+            debug!("Hook: {line}");
+        }
+
+        if !output.status.success() {
+            match stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+                Some(message) => bail!("The hook exited with {}: {message}", output.status),
+                None => bail!("The hook exited with {}", output.status),
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the hook for `event` like [`Self::run`], only warning if that fails.
+    pub async fn fire(&self, event: &Event) {
+        debug!("Running the {} hook: {}", event.kind, self.command);
+        if let Err(e) = self.run(event).await {
+            warn!("{} ({})", Report(&e), self.command);
+        }
+    }
+}
+
+/// Returns the command running `command` with the shell.
+fn shell(command: &str) -> Command {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut shell = Command::new(shell);
+    shell.arg(flag).arg(command);
+    shell
+}
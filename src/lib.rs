@@ -0,0 +1,74 @@
+//! AWS MFA Token Manager
+//!
+//! Library behind the `aws-mfa` binary. Refreshes AWS temporary credentials using MFA tokens,
+//! keeping long-term credentials in `[<profile>-long-term]` and writing the session to
+//! `[<profile>]`.
+//!
+//! ```no_run
+//! use aws_mfa::{
+//!     permissions::Policy,
+//!     token::get_mfa_token,
+//!     updater::{AwsMfaUpdater, Profile},
+//! };
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let updater = AwsMfaUpdater::new(None, Profile::default(), None, Policy::Warn)?;
+//! let token = get_mfa_token(None, None, &|| Ok("123456".to_string()))?;
+//! let session = updater.update_credentials(&token).await?;
+//! println!("Expires at {}", session.expiration());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Errors are [`anyhow::Error`]s; use [`error::Failure::of`] to find out what class of failure
+//! occurred.
+
+#![deny(missing_docs)]
+
+pub mod clear;
+pub mod cli_cache;
+pub mod clock;
+pub mod config;
+pub mod console;
+pub mod context;
+pub mod credentials;
+pub mod credentials_file;
+pub mod dialog;
+pub mod doctor;
+pub mod dotenv;
+pub mod error;
+pub mod exec;
+pub mod expiry;
+pub mod history;
+pub mod hook;
+pub mod keyring_keys;
+pub mod lock;
+pub mod notify;
+pub mod op;
+pub mod op_keys;
+pub mod output;
+pub mod path;
+pub mod permissions;
+pub mod picker;
+pub mod plan;
+pub mod progress;
+pub mod questions;
+pub mod redact;
+pub mod remaining;
+pub mod remote;
+pub mod rotate;
+pub mod server;
+pub mod session_keys;
+pub mod session_store;
+pub mod settings;
+pub mod setup;
+pub mod status;
+pub mod tls;
+pub mod token;
+pub mod totp;
+pub mod totp_seed;
+pub mod totp_window;
+pub mod updater;
+pub mod vault;
+pub mod watch;
+pub mod wizard;
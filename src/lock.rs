@@ -0,0 +1,70 @@
+//! Advisory locking of the credentials file.
+//!
+//! Concurrent invocations serialize their read-modify-write cycles through an OS-level lock
+//! (`flock` on Unix, `LockFileEx` on Windows) on a sidecar `<file>.lock`. The lock lives on a
+//! separate file so that it survives the credentials file being replaced, and the OS releases it
+//! when the process exits, even if it was killed. It is next to the file the path resolves to,
+//! which is the one replaced, so that runs reaching it through a symlink or a relative path
+//! take the same lock.
+
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use log::debug;
+use tokio::time::sleep;
+
+/// How often a held lock is retried.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Exclusive lock on a credentials file, released when dropped.
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Locks `path`, waiting at most `timeout` for other holders to release it.
+    pub async fn acquire(path: &Path, timeout: Duration) -> Result<Self> {
+        let lock_path = lock_path(path);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file {}", lock_path.display()))?;
+
+        let started = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                    debug!("Waiting for lock on {}", lock_path.display());
+                    sleep(POLL_INTERVAL).await;
+                }
+                Err(TryLockError::WouldBlock) => bail!(
+                    "Another aws-mfa instance is running (could not lock {} within {}s)",
+                    lock_path.display(),
+                    timeout.as_secs()
+                ),
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).context(format!("Failed to lock {}", lock_path.display()));
+                }
+            }
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Returns the sidecar lock file path for `path`, or for the file it resolves to if it exists.
+fn lock_path(path: &Path) -> PathBuf {
+    // The same resolution as `CredentialsFile::save`.
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut lock_path = OsString::from(path);
+    lock_path.push(".lock");
+    lock_path.into()
+}
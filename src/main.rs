@@ -1,65 +1,192 @@
 //! AWS MFA Token Manager
 //!
 //! Automates refreshing AWS temporary credentials using MFA tokens.
-//! Supports both automated token retrieval from 1Password and manual input.
+//! Supports automated token retrieval from password managers and the keyring, and manual input.
+//! This binary is a thin wrapper over the `aws_mfa` library.
 
 use std::{
-    io::{Write, stdin, stdout},
-    process::Command,
+    env::var,
+    io::{IsTerminal, Write, stderr},
+    process::ExitCode,
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow, ensure};
+use aws_mfa::{
+    config::AwsConfig,
+    context,
+    error::{Failure, Report, ResultExt},
+    output, progress, redact,
+};
 use clap::Parser;
-use log::{info, warn};
+use log::info;
 
 mod cli;
-mod credentials;
-mod updater;
+mod commands;
+
+use cli::{Action, Args, Command, Peek};
+use commands::Run;
 
-use cli::Args;
-use updater::AwsMfaUpdater;
+fn main() -> ExitCode {
+    let mut args = Args::parse();
+    let action = match args.command.take() {
+        // Shell prompts run these before every command, so they go without the logger and runtime.
+        Some(Command::Peek(Peek::Remaining { porcelain })) => {
+            return commands::remaining(&args, porcelain);
+        }
+        Some(Command::Peek(Peek::Status)) => return commands::status(&args),
+        Some(Command::Act(action)) => Some(action),
+        None => None,
+    };
 
-#[tokio::main]
-async fn main() -> Result<()> {
     env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(match args.quiet {
+            true => log::LevelFilter::Warn,
+            false => log::LevelFilter::Info,
+        })
+        .format(|buf, record| {
+            let style = buf.default_level_style(record.level());
+            let message = redact::scrub(&record.args().to_string());
+            writeln!(
+                buf,
+                "{}[{} {style}{:<5}{style:#} {}] {message}",
+                progress::clear_line(),
+                buf.timestamp(),
+                record.level(),
+                record.target()
+            )
+        })
         .init();
+    // Whoever reads the JSON, such as an AWS tool running credential-process, may capture stderr.
+    let json = args.output == Some(output::Format::Json)
+        || matches!(action, Some(Action::CredentialProcess));
+    let term = var("TERM").ok();
+    progress::enable(progress::visible(stderr().is_terminal(), term.as_deref(), json, args.quiet));
 
-    let Args {
-        credentials_path,
-        duration,
-        op_account,
-        op_item_name,
-    } = Args::parse();
-    let updater = AwsMfaUpdater::new(credentials_path, duration)?;
-    let token = get_mfa_token(op_account, op_item_name)?;
-
-    updater.update_credentials(&token).await
+    let result = tokio::runtime::Runtime::new()
+        .context("Failed to start the async runtime")
+        .and_then(|runtime| {
+            runtime.block_on(async {
+                match args.contexts.is_empty() && !args.all_contexts {
+                    true => run(args, action, None).await,
+                    false => run_contexts(args, action).await,
+                }
+            })
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", redact::scrub(&format!("{:?}", Report(&e))));
+            Failure::exit_code(&e)
+        }
+    }
 }
 
-/// Retrieves an MFA token using either 1Password automation or manual user input.
-///
-/// Falls back to manual input if 1Password retrieval fails or isn't configured.
-fn get_mfa_token(op_account: Option<String>, op_item_name: Option<String>) -> Result<String> {
-    if let (Some(account), Some(item)) = (op_account, op_item_name) {
-        if let Ok(output) = Command::new("op")
-            .args(["item", "get", "--account", &account, &item, "--otp"])
-            .output()
-            && output.status.success()
-        {
-            let otp = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if otp.len() == 6 && otp.chars().all(|c| c.is_ascii_digit()) {
-                info!("Retrieved MFA token from 1Password");
-                return Ok(otp);
-            }
+/// Runs `action` as `args` say, or refreshes the credentials once without one, for one profile.
+/// `context_region` is that of the context the run is for, if any.
+async fn run(args: Args, action: Option<Action>, context_region: Option<String>) -> Result<()> {
+    let run = Run::new(args, action.as_ref())?;
+    let region = context_region;
+    let result = match action {
+        Some(Action::History { since, json }) => run.history(since, json),
+        Some(Action::Enroll) => run.enroll(),
+        Some(Action::Code { window }) => run.code(window),
+        Some(Action::Unenroll) => run.unenroll(),
+        Some(Action::Clear { all, expired_only }) => run.clear(all, expired_only).await,
+        Some(Action::Doctor { json }) => run.doctor(json).await,
+        Some(Action::Init) => run.init().await,
+        Some(Action::Watch { lead_time }) => run.open(false, region).await?.watch(lead_time).await,
+        Some(Action::Server { port, secret }) => {
+            run.open(false, region).await?.serve(port, secret).await
+        }
+        Some(Action::RotateKey { keep_old }) => {
+            run.open(false, region).await?.rotate_key(keep_old).await
+        }
+        Some(Action::Exec { write, command, .. }) => {
+            run.open(false, region).await?.exec(write, command).await
         }
-        warn!("Failed to get token from 1Password, falling back to manual input");
+        Some(Action::CredentialProcess) => {
+            run.open(false, region).await?.credential_process().await
+        }
+        Some(Action::Console { open, .. }) => run.open(true, region).await?.console(open).await,
+        Some(Action::EncryptLongTerm) => run.open(false, region).await?.encrypt_long_term().await,
+        Some(Action::DecryptLongTerm) => run.open(false, region).await?.decrypt_long_term().await,
+        Some(Action::ImportKeyring) => run.open(false, region).await?.import_keyring().await,
+        None => run.open(true, region).await?.refresh().await,
+    };
+
+    // Remembered for the terminal session only, rather than in a file shared by all of them.
+    if let (Ok(()), Some(name)) = (&result, run.picked()) {
+        info!("To skip the menu in this shell, run: export AWS_PROFILE={name}");
     }
+    result
+}
+
+/// Refreshes the contexts of `--context`, or all of them with `--all-contexts`, each in a run of
+/// its own with the settings of the context in place of those of `args`.
+async fn run_contexts(args: Args, action: Option<Action>) -> Result<()> {
+    ensure!(action.is_none(), "--context and --all-contexts can't be combined with a command");
+    let config = AwsConfig::load().failure(Failure::Config)?;
+    let contexts = match args.all_contexts {
+        true => context::Context::all(&config),
+        false => context::Context::find(&config, &args.contexts),
+    }
+    .failure(Failure::Config)?;
+    ensure!(
+        args.token.is_none() || contexts.len() <= 1,
+        "--token can't refresh several contexts, as AWS accepts each MFA code only once"
+    );
+    if contexts.is_empty() {
+        let path = config
+            .path()
+            .map_or("the AWS config file".into(), |p| p.display().to_string());
+        let error =
+            anyhow!("No contexts in {path}; add [{}<name>] sections", context::SECTION_PREFIX);
+        return Err(Failure::Config.wrap(error));
+    }
+
+    let report = context::refresh_all(&contexts, args.fail_fast, |context| {
+        let (args, context) = (args.clone(), context.clone());
+        async move { run(with_context(args, &context)?, None, context.region).await }
+    })
+    .await;
+    for line in report.to_string().lines() {
+        info!("{line}");
+    }
+    report.into_result()
+}
 
-    print!("Enter AWS MFA code for device: ");
-    stdout().flush()?;
+/// Returns `args` for a run refreshing `context`. The profile is that of the context, or of
+/// `--profile`, or `default`; `AWS_PROFILE` belongs to a single context at most. A context that
+/// names a password manager replaces those of `args`.
+fn with_context(mut args: Args, context: &context::Context) -> Result<Args> {
+    args.contexts.clear();
+    args.all_contexts = false;
+    args.fail_fast = false;
+    let profile = context.profile.clone().or(args.profile.take());
+    args.profile = Some(profile.unwrap_or_else(|| "default".to_string()));
+    if let Some(path) = &context.credentials_file {
+        args.credentials_path = Some(path.clone());
+    }
+    if context.duration.is_some() {
+        args.duration = context.duration;
+    }
 
-    let mut input = String::new();
-    stdin().read_line(&mut input)?;
-    Ok(input.trim().to_string())
+    if context.names_password_manager() {
+        args.op_account = context.op_account.clone();
+        args.op_item_name = context.op_item_name.clone();
+        args.op_secret_reference = context
+            .op_secret_reference
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .with_context(|| format!("Invalid op_secret_reference of context {}", context.name))
+            .failure(Failure::Config)?;
+        args.bw_item = context.bw_item.clone();
+        args.pass_entry = context.pass_entry.clone();
+        args.keepass_db = context.keepass_db.clone();
+        args.keepass_entry = context.keepass_entry.clone();
+        args.keepass_keyfile = context.keepass_keyfile.clone();
+        args.yubikey_account = context.yubikey_account.clone();
+    }
+    Ok(args)
 }
@@ -0,0 +1,43 @@
+//! Desktop notifications.
+//!
+//! Notifications never carry secret material, only expirations and failure classes. Where no
+//! notification service is reachable (headless machines, SSH sessions), they degrade to a log
+//! line.
+
+use std::time::Duration;
+
+use log::info;
+use notify_rust::Notification;
+
+use crate::error::Failure;
+
+/// Notifies about a successful refresh, given the described expiration.
+pub fn refreshed(expiration: &str) {
+    send("AWS credentials refreshed", &format!("Valid until {expiration}"));
+}
+
+/// Notifies about a failed refresh, naming only the failure class.
+pub fn failed(error: &anyhow::Error) {
+    let class = Failure::of(error).map_or("unexpected error", Failure::description);
+    send("AWS credential refresh failed", &format!("Reason: {class}"));
+}
+
+/// Notifies that the credentials are about to expire and an MFA code will be needed.
+pub fn expiring(within: Duration) {
+    send(
+        "AWS credentials expiring soon",
+        &format!("Refresh in {} minutes, an MFA code will be needed", within.as_secs() / 60),
+    );
+}
+
+/// Shows a notification, logging it instead if that isn't possible.
+fn send(summary: &str, body: &str) {
+    if let Err(e) = Notification::new()
+        .appname("aws-mfa")
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        info!("{summary}: {body} (desktop notification unavailable: {e})");
+    }
+}
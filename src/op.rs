@@ -0,0 +1,276 @@
+//! Runs of the 1Password CLI, signing in again when its session is gone.
+//!
+//! `op` fails like it does for any other problem when it isn't signed in or its session expired,
+//! which used to send users to manual entry when a sign-in would have done. [`Op::run`] tells
+//! that case apart by what `op` prints. When allowed to, it runs `op signin` with the terminal, so
+//! that the password or biometric prompt reaches the user, and retries the command once;
+//! otherwise the error says which `op signin` to run.
+//!
+//! Under WSL, `op.exe` of the Windows side is used when there is no `op` on the `PATH`.
+
+use std::{
+    env::{split_paths, var_os},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result, bail};
+use log::info;
+
+use crate::{progress::Progress, redact::Secret};
+
+/// 1Password CLI executable.
+pub const PROGRAM: &str = if cfg!(windows) { "op.exe" } else { "op" };
+
+/// 1Password CLI of the Windows side, which WSL runs as well.
+const WINDOWS_PROGRAM: &str = "op.exe";
+
+/// What `op` prints, in lower case, when it has no usable session.
+const SIGNED_OUT: [&str; 6] = [
+    "not currently signed in",
+    "account is not signed in",
+    "session expired",
+    "no active session",
+    "invalid session token",
+    "authentication required",
+];
+
+/// Session token handed out by `op signin` without the app integration, with its account.
+static SESSION: Mutex<Option<(Option<String>, Secret)>> = Mutex::new(None);
+
+/// Returns whether `stderr` of `op` says it isn't signed in or its session expired.
+///
+/// ```
+/// use aws_mfa::op::is_signed_out;
+///
+/// assert!(is_signed_out(
+///     "[ERROR] 2024/05/03 19:21:04 You are not currently signed in. Please run `op signin \
+///      --help` for instructions"
+/// ));
+/// assert!(is_signed_out("[ERROR] session expired, sign in to create a new session"));
+/// assert!(!is_signed_out("[ERROR] 2024/05/03 19:21:04 \"AWS\" isn't an item."));
+/// ```
+pub fn is_signed_out(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    SIGNED_OUT.iter().any(|pattern| stderr.contains(pattern))
+}
+
+/// Returns whether this is Linux running in WSL, where the 1Password CLI is usually only
+/// installed on the Windows side.
+pub fn is_wsl() -> bool {
+    cfg!(target_os = "linux")
+        && (var_os("WSL_DISTRO_NAME").is_some()
+            || read_to_string("/proc/version")
+                .is_ok_and(|version| version.to_lowercase().contains("microsoft")))
+}
+
+/// Returns the names the 1Password CLI is looked for under on the `PATH`, in order.
+fn candidates() -> Vec<&'static str> {
+    match is_wsl() {
+        true => vec![PROGRAM, WINDOWS_PROGRAM],
+        false => vec![PROGRAM],
+    }
+}
+
+/// The 1Password CLI, for one account.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{fs, os::unix::fs::PermissionsExt};
+///
+/// use aws_mfa::op::Op;
+///
+/// // A stub op that is signed out until `op signin` hands out the session "tok", and counts the
+/// // sign-ins in a file.
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-op-signin-{}", std::process::id()));
+/// fs::create_dir_all(&dir)?;
+/// let stub = |name: &str, signed_in: &str| -> anyhow::Result<_> {
+///     let path = dir.join(name);
+///     fs::write(
+///         &path,
+///         format!(
+///             r#"#!/bin/sh
+/// if [ "$1" = signin ]; then echo x >> "$0.signins"; echo tok; exit 0; fi
+/// case "$*" in
+///     {signed_in}) echo 123456;;
+///     *) echo "[ERROR] 2024/05/03 19:21:04 You are not currently signed in." >&2; exit 1;;
+/// esac
+/// "#
+///         ),
+///     )?;
+///     fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+///     Ok(path)
+/// };
+/// let signins = |path: &std::path::Path| {
+///     let count = fs::read_to_string(path.with_extension("signins")).unwrap_or_default();
+///     count.lines().count()
+/// };
+/// let args = ["item", "get", "AWS", "--otp"];
+/// let account = Some("my.1password.com".to_string());
+///
+/// // Unattended, the error says how to sign in.
+/// let op = stub("op", "*--session\\ tok*")?;
+/// let error = Op::new().with_program(&op).with_account(account.clone()).run(&args, "Fetching");
+/// let error = error.unwrap_err().to_string();
+/// assert!(error.contains("run `op signin --account my.1password.com`"), "{error}");
+/// assert_eq!(signins(&op), 0);
+///
+/// // Otherwise it signs in, and retries with the session.
+/// let signing_in = Op::new().with_program(&op).with_account(account.clone()).with_sign_in(true);
+/// assert_eq!(signing_in.run(&args, "Fetching")?, "123456");
+/// assert_eq!(signins(&op), 1);
+/// // The session is kept for the next command.
+/// assert_eq!(signing_in.run(&args, "Fetching")?, "123456");
+/// assert_eq!(signins(&op), 1);
+///
+/// // A sign-in that doesn't help is only tried once.
+/// let broken = stub("op-broken", "never")?;
+/// let error = Op::new().with_program(&broken).with_sign_in(true).run(&args, "Fetching");
+/// assert!(error.unwrap_err().to_string().contains("not currently signed in"));
+/// assert_eq!(signins(&broken), 1);
+/// # fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Op {
+    program: Option<PathBuf>,
+    account: Option<String>,
+    sign_in: bool,
+}
+
+impl Op {
+    /// Runs `op` from the `PATH` in the account it uses by default, without signing in.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs commands in `account` rather than the account `op` uses by default.
+    pub fn with_account(mut self, account: Option<String>) -> Self {
+        self.account = account;
+        self
+    }
+
+    /// Runs `program` instead of looking for `op` on the `PATH`.
+    pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Runs `op signin` with the terminal when there is no session, instead of failing; only for
+    /// interactive runs [default: false].
+    pub fn with_sign_in(mut self, sign_in: bool) -> Self {
+        self.sign_in = sign_in;
+        self
+    }
+
+    /// Returns the program that is run: the one given with [`Self::with_program`], or else the
+    /// first found on the `PATH` of `op` and, on WSL, `op.exe` of the Windows side.
+    ///
+    /// ```
+    /// use aws_mfa::op::Op;
+    ///
+    /// let given = Op::new().with_program("/opt/1password/op");
+    /// assert_eq!(given.program()?, std::path::Path::new("/opt/1password/op"));
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn program(&self) -> Result<PathBuf> {
+        if let Some(program) = &self.program {
+            return Ok(program.clone());
+        }
+        let candidates = candidates();
+        let path = var_os("PATH").unwrap_or_default();
+        for name in &candidates {
+            if let Some(found) = split_paths(&path).map(|dir| dir.join(name)).find(|p| p.is_file())
+            {
+                return Ok(found);
+            }
+        }
+        bail!("1Password CLI not found on the PATH; tried {}", candidates.join(", "))
+    }
+
+    /// Runs `op` with `args`, showing `message` as progress, and returns what it printed.
+    pub fn run(&self, args: &[&str], message: &str) -> Result<String> {
+        let program = self.program()?;
+        let mut output = self.attempt(&program, args, message)?;
+        if !output.status.success() && is_signed_out(&String::from_utf8_lossy(&output.stderr)) {
+            if !self.sign_in {
+                bail!(
+                    "1Password CLI is not signed in or its session expired; run `{}` and try again",
+                    self.signin_command()
+                );
+            }
+            self.sign_in(&program)?;
+            output = self.attempt(&program, args, message)?;
+        }
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match stderr.lines().map(str::trim).rfind(|line| !line.is_empty()) {
+                Some(message) => bail!("{message}"),
+                None => bail!("{} exited with {}", program.display(), output.status),
+            }
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Runs `op` with `args` once, capturing its output. Its stdin is closed, so that it fails
+    /// rather than waiting for a password nobody sees.
+    fn attempt(&self, program: &Path, args: &[&str], message: &str) -> Result<Output> {
+        let mut command = Command::new(program);
+        command.args(args);
+        if let Some(account) = &self.account {
+            command.args(["--account", account]);
+        }
+        if let Ok(session) = SESSION.lock()
+            && let Some((_, token)) = session.as_ref().filter(|(a, _)| *a == self.account)
+        {
+            command.args(["--session", token.expose()]);
+        }
+
+        let progress = Progress::start(message);
+        let output = command.output();
+        progress.finish(output.as_ref().is_ok_and(|output| output.status.success()));
+        output.with_context(|| format!("Failed to run {}", program.display()))
+    }
+
+    /// Runs `op signin` with the terminal, keeping the session token it prints, if any.
+    fn sign_in(&self, program: &Path) -> Result<()> {
+        info!("1Password CLI is not signed in; running {}", self.signin_command());
+        let mut command = Command::new(program);
+        command.args(["signin", "--raw"]);
+        if let Some(account) = &self.account {
+            command.args(["--account", account]);
+        }
+        let output = command
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run {}", program.display()))?;
+        if !output.status.success() {
+            bail!("{} exited with {}", self.signin_command(), output.status);
+        }
+
+        // With the app integration, there is no token: the app holds the session.
+        let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !token.is_empty()
+            && let Ok(mut session) = SESSION.lock()
+        {
+            *session = Some((self.account.clone(), Secret::new(token)));
+        }
+        Ok(())
+    }
+
+    /// Returns the command signing in to the account.
+    fn signin_command(&self) -> String {
+        match &self.account {
+            Some(account) => format!("op signin --account {account}"),
+            None => "op signin".to_string(),
+        }
+    }
+}
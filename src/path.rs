@@ -0,0 +1,110 @@
+//! Expansion of user-supplied paths.
+//!
+//! Neither clap nor the shell expand `~` inside environment variables such as
+//! `AWS_SHARED_CREDENTIALS_FILE`, so paths are expanded here: a leading `~` or `~user`, `$VAR` and
+//! `${VAR}` references and, on Windows, `%VAR%` references.
+
+use std::{
+    env::{self, VarError},
+    path::{Path, PathBuf, absolute},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use dirs::home_dir;
+
+/// Expands `path` and makes it absolute.
+///
+/// Existing paths are canonicalized so that error messages show the file that is actually used.
+///
+/// ```
+/// use aws_mfa::path::expand;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let home = dirs::home_dir().unwrap();
+/// assert_eq!(expand("~/my creds".as_ref())?, home.join("my creds"));
+/// assert!(expand("$AWS_MFA_SURELY_UNSET/credentials".as_ref()).is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub fn expand(path: &Path) -> Result<PathBuf> {
+    // Non-UTF-8 paths can't contain anything worth expanding in a portable way.
+    let expanded = match path.to_str() {
+        Some(s) => PathBuf::from(expand_vars(&expand_tilde(s)?)?),
+        None => path.to_path_buf(),
+    };
+
+    match expanded.canonicalize() {
+        Ok(canonical) => Ok(canonical),
+        Err(_) => {
+            absolute(&expanded).with_context(|| format!("Invalid path: {}", expanded.display()))
+        }
+    }
+}
+
+/// Expands a leading `~` (the current user's home) or `~user` (a sibling of it).
+fn expand_tilde(s: &str) -> Result<String> {
+    let Some(rest) = s.strip_prefix('~') else {
+        return Ok(s.to_string());
+    };
+
+    let end = rest.find(['/', '\\']).unwrap_or(rest.len());
+    let (user, rest) = rest.split_at(end);
+    let home = home_dir().context("Could not expand ~: the home directory is unknown")?;
+    let home = match user {
+        "" => home,
+        user => home
+            .parent()
+            .map(|d| d.join(user))
+            .with_context(|| format!("Could not expand ~{user}"))?,
+    };
+
+    Ok(format!("{}{rest}", home.display()))
+}
+
+/// Expands `$VAR`, `${VAR}` and, on Windows, `%VAR%` references. Unset variables are an error.
+fn expand_vars(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(i) = rest.find(['$', '%']) {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+
+        let reference = match rest.as_bytes()[i] {
+            b'$' => match after.strip_prefix('{') {
+                Some(braced) => braced.find('}').map(|end| (&braced[..end], end + 2)),
+                None => {
+                    let end = after
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(after.len());
+                    (end > 0).then(|| (&after[..end], end))
+                }
+            },
+            _ if cfg!(windows) => after.find('%').map(|end| (&after[..end], end + 1)),
+            _ => None,
+        };
+
+        match reference {
+            Some((name, len)) if !name.is_empty() => {
+                out.push_str(&var(name)?);
+                rest = &after[len..];
+            }
+            _ => {
+                out.push_str(&rest[i..=i]);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+/// Returns the value of the environment variable `name`.
+fn var(name: &str) -> Result<String> {
+    match env::var(name) {
+        Ok(value) => Ok(value),
+        Err(VarError::NotPresent) => bail!("Environment variable {name} is not set"),
+        Err(e) => Err(anyhow!("Environment variable {name}: {e}")),
+    }
+}
@@ -0,0 +1,184 @@
+//! Pre-flight audit of the credentials file permissions.
+//!
+//! A credentials file readable by other users, or a directory others can write to, is usually a
+//! sign that something else is wrong on the machine, so it is reported before any secret is read.
+//! On Unix the mode bits are checked and can be fixed; on Windows the ACL is inspected on a
+//! best-effort basis with `icacls`, and never changed.
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use log::warn;
+
+/// What to do about overly permissive credentials files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Log a warning and carry on.
+    #[default]
+    Warn,
+    /// Fail before reading any secrets.
+    Strict,
+    /// Tighten the permissions: the file to 0600, and its directory to 0700 if others can write
+    /// to it.
+    Fix,
+}
+
+/// Checks the credentials file at `path` and its directory, and applies `policy` to what is
+/// found.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{
+///     fs::{create_dir_all, metadata, set_permissions, write},
+///     os::unix::fs::PermissionsExt,
+/// };
+///
+/// use aws_mfa::permissions::{Policy, audit, check};
+///
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-permissions-{}", std::process::id()));
+/// create_dir_all(&dir)?;
+/// let path = dir.join("credentials");
+/// write(&path, "[default]\n")?;
+/// set_permissions(&path, PermissionsExt::from_mode(0o644))?;
+/// set_permissions(&dir, PermissionsExt::from_mode(0o777))?;
+///
+/// assert_eq!(audit(&path).len(), 2);
+/// assert!(check(&path, Policy::Strict).is_err());
+///
+/// check(&path, Policy::Fix)?;
+/// assert!(audit(&path).is_empty());
+/// assert_eq!(metadata(&path)?.permissions().mode() & 0o777, 0o600);
+/// assert_eq!(metadata(&dir)?.permissions().mode() & 0o777, 0o700);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub fn check(path: &Path, policy: Policy) -> Result<()> {
+    let problems = audit(path);
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    match policy {
+        Policy::Warn => {
+            for problem in &problems {
+                warn!("{problem}; run with --fix-permissions to tighten it");
+            }
+            Ok(())
+        }
+        Policy::Strict => bail!(
+            "Refusing to read credentials: {} (use --fix-permissions to tighten them)",
+            problems.join("; ")
+        ),
+        Policy::Fix => {
+            for problem in &problems {
+                warn!("{problem}");
+            }
+            imp::fix(path)
+        }
+    }
+}
+
+/// Returns a description of each permission problem of the file at `path` and its directory.
+pub fn audit(path: &Path) -> Vec<String> {
+    imp::audit(path)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::{
+        fs::{metadata, set_permissions},
+        os::unix::fs::PermissionsExt,
+        path::Path,
+    };
+
+    use anyhow::{Context, Result};
+    use log::info;
+
+    /// Mode the credentials file is fixed to.
+    const FILE_MODE: u32 = 0o600;
+
+    /// Mode a world-writable directory of the credentials file is fixed to.
+    const DIR_MODE: u32 = 0o700;
+
+    pub fn audit(path: &Path) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Ok(meta) = metadata(path) {
+            let mode = meta.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                problems.push(format!(
+                    "{} is accessible by other users (mode {mode:03o})",
+                    path.display()
+                ));
+            }
+        }
+
+        if let Some(dir) = path.parent()
+            && let Ok(meta) = metadata(dir)
+        {
+            let mode = meta.permissions().mode() & 0o7777;
+            // Sticky world-writable directories such as /tmp are fine.
+            if mode & 0o1002 == 0o002 {
+                problems.push(format!("{} is world-writable (mode {mode:03o})", dir.display()));
+            }
+        }
+
+        problems
+    }
+
+    pub fn fix(path: &Path) -> Result<()> {
+        if metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o077 != 0) {
+            set_mode(path, FILE_MODE)?;
+        }
+        if let Some(dir) = path.parent()
+            && metadata(dir).is_ok_and(|meta| meta.permissions().mode() & 0o1002 == 0o002)
+        {
+            set_mode(dir, DIR_MODE)?;
+        }
+        Ok(())
+    }
+
+    fn set_mode(path: &Path, mode: u32) -> Result<()> {
+        set_permissions(path, PermissionsExt::from_mode(mode))
+            .with_context(|| format!("Failed to change the permissions of {}", path.display()))?;
+        info!("Changed the permissions of {} to {mode:03o}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{path::Path, process::Command};
+
+    use anyhow::Result;
+    use log::{debug, warn};
+
+    /// Principals that mean anyone on the machine can access the file.
+    const BROAD_PRINCIPALS: [&str; 3] = ["Everyone", "BUILTIN\\Users", "Authenticated Users"];
+
+    pub fn audit(path: &Path) -> Vec<String> {
+        let output = match Command::new("icacls").arg(path).output() {
+            Ok(output) if output.status.success() => output,
+            _ => {
+                debug!("Could not inspect the ACL of {}", path.display());
+                return Vec::new();
+            }
+        };
+
+        let acl = String::from_utf8_lossy(&output.stdout);
+        BROAD_PRINCIPALS
+            .into_iter()
+            .filter(|principal| acl.contains(&format!("{principal}:")))
+            .map(|principal| format!("{} is accessible by {principal}", path.display()))
+            .collect()
+    }
+
+    pub fn fix(path: &Path) -> Result<()> {
+        warn!("Not changing the ACL of {}; restrict it to your user by hand", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Preview of what a refresh would write, for `--dry-run`.
+//!
+//! [`AwsMfaUpdater::plan`](crate::updater::AwsMfaUpdater::plan) applies placeholder credentials
+//! to a copy of the credentials file through the same code a refresh writes with, so the preview
+//! shows the file as it would be, comments, other sections and stale keys included. Only the
+//! values STS would hand out are made up. [`Plan::diff`] renders the changes as a unified diff
+//! with the values of secret keys left out. [`preview`] shows the plan along with everything else
+//! a refresh would do.
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result, anyhow};
+use aws_sdk_sts::types;
+use aws_smithy_types::DateTime;
+use log::info;
+
+use crate::{
+    doctor::{self, Status},
+    error::{Failure, ResultExt},
+    redact::SENSITIVE_FIELDS,
+    token::Sources,
+    updater::AwsMfaUpdater,
+};
+
+/// Lines of unchanged context around each change.
+const CONTEXT: usize = 3;
+
+/// Stands in for values STS would hand out.
+pub const PLACEHOLDER: &str = "<from STS>";
+
+/// The credentials file before and after a refresh.
+pub struct Plan {
+    path: PathBuf,
+    before: String,
+    after: String,
+}
+
+impl Plan {
+    /// Describes changing the file at `path` from `before` to `after`.
+    pub fn new(path: impl Into<PathBuf>, before: String, after: String) -> Self {
+        Self { path: path.into(), before, after }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the content of the file as it would be after the refresh, secrets included.
+    pub fn after(&self) -> &str {
+        &self.after
+    }
+
+    /// Returns whether the refresh would change the file.
+    pub fn changes(&self) -> bool {
+        self.before != self.after
+    }
+
+    /// Renders the changes as a unified diff, with the values of secret keys elided.
+    ///
+    /// ```
+    /// use aws_mfa::plan::Plan;
+    ///
+    /// let before = "; work\n[work]\naws_access_key_id = ASIAOLD\n\
+    ///               aws_session_token = FwoGOLDTOKEN\nregion = eu-west-1\n[other]\nkey = 1\n";
+    /// let after = "; work\n[work]\naws_access_key_id = <from STS>\n\
+    ///              aws_session_token = <from STS>\nregion = eu-west-1\n[other]\nkey = 1\n\
+    ///              [work-session]\nexpiration=2024-05-03T19:21:04Z\n";
+    /// let plan = Plan::new("/home/me/.aws/credentials", before.into(), after.into());
+    /// assert_eq!(
+    ///     plan.diff(),
+    ///     "--- /home/me/.aws/credentials\n\
+    ///      +++ /home/me/.aws/credentials (planned)\n\
+    ///      @@ -1,7 +1,9 @@\n \
+    ///      ; work\n \
+    ///      [work]\n\
+    ///      -aws_access_key_id = ASIAOLD\n\
+    ///      -aws_session_token = …\n\
+    ///      +aws_access_key_id = <from STS>\n\
+    ///      +aws_session_token = …\n \
+    ///      region = eu-west-1\n \
+    ///      [other]\n \
+    ///      key = 1\n\
+    ///      +[work-session]\n\
+    ///      +expiration=2024-05-03T19:21:04Z\n"
+    /// );
+    /// assert!(!Plan::new("c", before.into(), before.into()).changes());
+    /// ```
+    pub fn diff(&self) -> String {
+        let old: Vec<_> = self.before.lines().collect();
+        let new: Vec<_> = self.after.lines().collect();
+        let ops = edits(&old, &new);
+
+        let path = self.path.display();
+        let mut out = format!("--- {path}\n+++ {path} (planned)\n");
+        let changed: Vec<_> = (0..ops.len()).filter(|&i| !matches!(ops[i], Op::Keep(_))).collect();
+        let mut i = 0;
+        while i < changed.len() {
+            // A hunk runs while the next change is within twice the context of the last.
+            let mut last = i;
+            while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * CONTEXT {
+                last += 1;
+            }
+            let start = changed[i].saturating_sub(CONTEXT);
+            let end = (changed[last] + CONTEXT + 1).min(ops.len());
+            out += &hunk(&ops, start, end);
+            i = last + 1;
+        }
+        out
+    }
+}
+
+/// One line of the diff.
+#[derive(Clone, Copy)]
+enum Op<'a> {
+    Keep(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+impl Op<'_> {
+    /// Returns whether the line is in the old file, or with `old` unset, in the new one.
+    fn shows(&self, old: bool) -> bool {
+        match self {
+            Self::Keep(_) => true,
+            Self::Remove(_) => old,
+            Self::Add(_) => !old,
+        }
+    }
+}
+
+/// Returns the edits turning `old` into `new`, from their longest common subsequence. Removals
+/// come before additions where both are possible, as in `diff -u`.
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(Op::Keep(old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            ops.push(Op::Remove(old[i]));
+            i += 1;
+        } else {
+            ops.push(Op::Add(new[j]));
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Renders `ops[start..end]` as a hunk with its `@@` header.
+fn hunk(ops: &[Op], start: usize, end: usize) -> String {
+    let range = |old: bool| {
+        let before = ops[..start].iter().filter(|op| op.shows(old)).count();
+        match ops[start..end].iter().filter(|op| op.shows(old)).count() {
+            // An empty range names the line before it, as in `diff -u`.
+            0 => format!("{before},0"),
+            n => format!("{},{n}", before + 1),
+        }
+    };
+
+    let mut out = format!("@@ -{} +{} @@\n", range(true), range(false));
+    for op in &ops[start..end] {
+        let (sign, line) = match op {
+            Op::Keep(line) => (' ', line),
+            Op::Remove(line) => ('-', line),
+            Op::Add(line) => ('+', line),
+        };
+        out += &format!("{sign}{}\n", elide(line));
+    }
+    out
+}
+
+/// Returns `line` with the value left out if it is a `key = value` line of a secret.
+fn elide(line: &str) -> String {
+    let Some((key, value)) = line.split_once('=') else {
+        return line.to_string();
+    };
+    if !SENSITIVE_FIELDS.contains(&key.trim()) || value.trim().is_empty() {
+        return line.to_string();
+    }
+    let spacing = &value[..value.len() - value.trim_start().len()];
+    format!("{key}={spacing}…")
+}
+
+/// Shows what a refresh by `updater` would do, short of asking for a code, calling STS for a
+/// session and writing: the password managers of `sources` are run with `--version`, with `verify`
+/// the long-term credentials are checked, and the changes to the credentials file are printed.
+/// The session would be for a `federation` user, and also written to the AWS CLI cache with
+/// `cli_cache`, to the `dotenv` files and to `remotes` remotes.
+pub async fn preview(
+    updater: &AwsMfaUpdater,
+    sources: &Sources,
+    federation: bool,
+    verify: bool,
+    cli_cache: bool,
+    dotenv: &[PathBuf],
+    remotes: usize,
+) -> Result<()> {
+    for (name, program) in sources.programs() {
+        let check = doctor::program(name, &program);
+        if check.status == Status::Fail {
+            let hint = check.hint.unwrap_or_default();
+            return Err(Failure::Token.wrap(anyhow!("{name}: {}. {hint}", check.detail)));
+        }
+        info!("{name}: {}", check.detail);
+    }
+    if verify {
+        let identity = updater
+            .caller_identity()
+            .await
+            .context("The long-term credentials appear unusable")?;
+        info!("Verified: account {}, {}", identity.account, identity.arn);
+    }
+
+    // STS hands out whole seconds.
+    let now = DateTime::from(SystemTime::now()).secs();
+    let duration = match federation {
+        true => updater.duration(),
+        false => updater.written_duration(),
+    };
+    let expiration = DateTime::from_secs(now + i64::from(duration));
+    let session = types::Credentials::builder()
+        .access_key_id(PLACEHOLDER)
+        .secret_access_key(PLACEHOLDER)
+        .session_token(PLACEHOLDER)
+        .expiration(expiration)
+        .build()?;
+    let store = updater.credential_store();
+    if store.file() {
+        let plan = updater.plan(&session, federation).failure(Failure::Config)?;
+        match plan.changes() {
+            true => print!("{}", plan.diff()),
+            false => info!("No changes to {}", plan.path().display()),
+        }
+    }
+    if store.keyring() {
+        let section = updater.profile().session_section();
+        let also = if store.file() { " also" } else { "" };
+        info!("A refresh would{also} store the session of [{section}] in the keyring");
+    }
+    if cli_cache {
+        info!("A refresh would also write the AWS CLI cache entry of {}", updater.profile().name());
+    }
+    for path in dotenv {
+        info!("A refresh would also write the session to {}", path.display());
+    }
+    if remotes > 0 {
+        info!("A refresh would also push the session to {remotes} remote(s)");
+    }
+    info!("Dry run: nothing was written");
+
+    Ok(())
+}
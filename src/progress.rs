@@ -0,0 +1,136 @@
+//! Progress indicator for the slow steps: 1Password and STS.
+//!
+//! A spinner is drawn on stderr while a step runs and replaced by a check mark or a cross with
+//! the elapsed time. Library consumers get nothing unless [`enable`] is called, and `main` only
+//! enables it when stderr is a terminal and the run is neither quiet nor printing JSON, so piped
+//! output stays clean. Log lines written while a
+//! spinner runs start with [`clear_line`] so that they replace the spinner line, which is drawn
+//! again below them.
+
+use std::{
+    future::Future,
+    io::{Write, stderr},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{JoinHandle, sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+/// Spinner frames, drawn in turn.
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Delay between two frames.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Terminal sequence that moves to the start of the line and erases it.
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Whether spinners are drawn at all, see [`enable`].
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a spinner is currently drawn.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether progress should be shown on a stderr that `is_terminal`, given the `TERM`
+/// environment variable, for a run that prints `json` or is `quiet`.
+///
+/// ```
+/// use aws_mfa::progress::visible;
+///
+/// assert!(visible(true, Some("xterm-256color"), false, false));
+/// assert!(visible(true, None, false, false));
+/// // Piped or redirected output, and terminals that can't redraw a line.
+/// assert!(!visible(false, Some("xterm-256color"), false, false));
+/// assert!(!visible(true, Some("dumb"), false, false));
+/// ```
+pub fn visible(is_terminal: bool, term: Option<&str>, json: bool, quiet: bool) -> bool {
+    is_terminal && term != Some("dumb") && !json && !quiet
+}
+
+/// Turns progress output on or off for the whole process. It is off by default.
+pub fn enable(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns the sequence a log line has to start with to replace a running spinner, or an empty
+/// string when none is drawn.
+pub fn clear_line() -> &'static str {
+    if ACTIVE.load(Ordering::Relaxed) { CLEAR_LINE } else { "" }
+}
+
+/// A step in progress, shown as a spinner until [`Self::finish`] is called.
+///
+/// Dropping it unfinished marks the step as failed.
+pub struct Progress {
+    message: String,
+    start: Instant,
+    spinner: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+impl Progress {
+    /// Starts showing `message`, e.g. "Fetching code from 1Password…".
+    pub fn start(message: impl Into<String>) -> Self {
+        let message = message.into();
+        // Only one spinner at a time; a nested step just isn't drawn.
+        let draw = ENABLED.load(Ordering::Relaxed) && !ACTIVE.swap(true, Ordering::Relaxed);
+
+        let spinner = draw.then(|| {
+            let stop = Arc::new(AtomicBool::new(false));
+            let handle = {
+                let (stop, message) = (stop.clone(), message.clone());
+                spawn(move || {
+                    for frame in FRAMES.iter().cycle() {
+                        if stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        let mut err = stderr().lock();
+                        let _ = write!(err, "{CLEAR_LINE}{frame} {message}");
+                        let _ = err.flush();
+                        drop(err);
+                        sleep(TICK);
+                    }
+                })
+            };
+            (stop, handle)
+        });
+
+        Self { message, start: Instant::now(), spinner }
+    }
+
+    /// Replaces the spinner with a check mark if `ok`, or a cross otherwise.
+    pub fn finish(mut self, ok: bool) {
+        self.stop(ok);
+    }
+
+    fn stop(&mut self, ok: bool) {
+        let Some((stop, handle)) = self.spinner.take() else { return };
+        stop.store(true, Ordering::Relaxed);
+        let _ = handle.join();
+
+        let mark = if ok { '✓' } else { '✗' };
+        let elapsed = self.start.elapsed().as_secs_f32();
+        let _ = writeln!(stderr(), "{CLEAR_LINE}{mark} {} ({elapsed:.1}s)", self.message);
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.stop(false);
+    }
+}
+
+/// Shows `message` while `future` runs, marking the step by its result.
+pub async fn track<T>(
+    message: impl Into<String>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let progress = Progress::start(message);
+    let result = future.await;
+    progress.finish(result.is_ok());
+    result
+}
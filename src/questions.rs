@@ -0,0 +1,150 @@
+//! Questions asked of the user while setting up a profile or unlocking the long-term keys.
+//!
+//! Questions are written to stderr and answered on stdin, so that stdout stays clean for what
+//! `aws-mfa` prints. [`Questions`] takes any reader and writer instead, so that library consumers
+//! and tests can answer without a terminal.
+
+use std::{
+    fmt::Display,
+    io::{BufRead, Stderr, StdinLock, Write, stderr, stdin},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+
+use crate::redact::Secret;
+
+/// Asks questions on `output` and reads the answers from `input`.
+///
+/// ```
+/// use aws_mfa::questions::Questions;
+///
+/// let mut output = Vec::new();
+/// let mut questions = Questions::new("alice\ny\n".as_bytes(), &mut output);
+/// assert_eq!(questions.ask("Name")?, "alice");
+/// assert!(questions.confirm("Write this?")?);
+/// // Every question fails once the input is closed, rather than taking an empty answer.
+/// assert!(questions.ask("Name").is_err());
+/// assert_eq!(String::from_utf8(output)?, "Name: Write this? [y/N] Name: \n");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Questions<R, W> {
+    input: R,
+    output: W,
+    /// Whether secrets are read from the terminal without echoing them, rather than from `input`.
+    terminal: bool,
+}
+
+impl Questions<StdinLock<'static>, Stderr> {
+    /// Asks on stderr and reads the answers from stdin, and secrets from the terminal.
+    pub fn stdio() -> Self {
+        Self {
+            input: stdin().lock(),
+            output: stderr(),
+            terminal: true,
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Questions<R, W> {
+    /// Asks on `output` and reads the answers, secrets included, from `input`.
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output, terminal: false }
+    }
+
+    /// Prompts for the value of `name` and returns the trimmed answer, failing at the end of
+    /// input rather than answering every question with an empty line.
+    pub fn ask(&mut self, name: &str) -> Result<String> {
+        write!(self.output, "{name}: ")?;
+        self.output.flush()?;
+
+        let mut input = String::new();
+        if self.input.read_line(&mut input)? == 0 {
+            writeln!(self.output)?;
+            bail!("No answer for {name}; stdin was closed");
+        }
+        Ok(input.trim().to_string())
+    }
+
+    /// Tells the user `message`, on a line of its own.
+    pub fn say(&mut self, message: impl Display) -> Result<()> {
+        writeln!(self.output, "{message}")?;
+        Ok(())
+    }
+
+    /// Asks `question` and returns whether it was answered with yes.
+    ///
+    /// ```
+    /// use aws_mfa::questions::Questions;
+    ///
+    /// for (answer, yes) in [("y\n", true), ("yes\n", true), ("\n", false), ("no\n", false)] {
+    ///     let mut questions = Questions::new(answer.as_bytes(), Vec::new());
+    ///     assert_eq!(questions.confirm("Write this?")?, yes, "{answer:?}");
+    /// }
+    /// // A closed input is no.
+    /// assert!(!Questions::new("".as_bytes(), Vec::new()).confirm("Write this?")?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn confirm(&mut self, question: &str) -> Result<bool> {
+        write!(self.output, "{question} [y/N] ")?;
+        self.output.flush()?;
+
+        let mut input = String::new();
+        self.input.read_line(&mut input)?;
+        Ok(matches!(input.trim(), "y" | "Y" | "yes"))
+    }
+
+    /// Lists `items` and returns the index of the one whose number is entered.
+    ///
+    /// ```
+    /// use aws_mfa::questions::Questions;
+    ///
+    /// let mut output = Vec::new();
+    /// let mut questions = Questions::new("2\n0\n".as_bytes(), &mut output);
+    /// assert_eq!(questions.choose("MFA device", &["phone", "yubikey"])?, 1);
+    /// let error = questions.choose("MFA device", &["phone", "yubikey"]).unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid choice \"0\"");
+    /// let listed = String::from_utf8(output)?;
+    /// assert!(listed.starts_with("  1) phone\n  2) yubikey\nMFA device [1-2]: "));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn choose(&mut self, what: &str, items: &[impl AsRef<str>]) -> Result<usize> {
+        for (i, item) in items.iter().enumerate() {
+            writeln!(self.output, "  {}) {}", i + 1, item.as_ref())?;
+        }
+        let choice = self.ask(&format!("{what} [1-{}]", items.len()))?;
+        match choice.parse::<usize>() {
+            Ok(n) if (1..=items.len()).contains(&n) => Ok(n - 1),
+            _ => bail!("Invalid choice {choice:?}"),
+        }
+    }
+
+    /// Reads a secret after `prompt`, without echoing it on a terminal.
+    pub fn secret(&mut self, prompt: &str) -> Result<String> {
+        if self.terminal {
+            return Ok(rpassword::prompt_password(prompt)?);
+        }
+        write!(self.output, "{prompt}")?;
+        self.output.flush()?;
+
+        let mut input = String::new();
+        self.input.read_line(&mut input)?;
+        Ok(input.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Reads the passphrase of the long-term keys, twice if `new`.
+    pub fn passphrase(&mut self, new: bool) -> Result<Secret> {
+        let question = match new {
+            true => "New passphrase for the long-term keys: ",
+            false => "Passphrase of the long-term keys: ",
+        };
+        let context = "Failed to read the passphrase from the terminal; use --age-identity for \
+                       unattended runs";
+        let passphrase = Secret::new(self.secret(question).context(context)?);
+        if new {
+            ensure!(!passphrase.expose().is_empty(), "The passphrase can't be empty");
+            let again = self.secret("Repeat the passphrase: ").context(context)?;
+            ensure!(again == passphrase.expose(), "The passphrases don't match");
+        }
+        Ok(passphrase)
+    }
+}
@@ -0,0 +1,150 @@
+//! Redaction of secrets in log and error output.
+//!
+//! Secret values are held in [`Secret`], which never formats the full value. Since errors from
+//! dependencies may still quote them, every secret the process handles is also registered here,
+//! and `main` passes log lines and the final error through [`scrub`] before printing them.
+
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    sync::Mutex,
+};
+
+/// Number of leading characters left readable, enough to tell keys apart.
+const VISIBLE: usize = 4;
+
+/// Fields whose values are always hidden by [`scrub`], whether or not they were registered.
+pub(crate) const SENSITIVE_FIELDS: [&str; 4] =
+    ["aws_secret_access_key", "aws_session_token", "aws_security_token", "token_code"];
+
+/// Secrets seen by this process, see [`register`].
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A secret value that formats as its first characters followed by `…REDACTED`.
+///
+/// The value itself is only available through [`Self::expose`], e.g. for writing the credentials
+/// file.
+///
+/// ```
+/// use aws_mfa::redact::Secret;
+///
+/// let secret = Secret::new("wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY");
+/// assert_eq!(secret.to_string(), "wJal…REDACTED");
+/// assert!(!format!("{secret:?}").contains("bPxRfiCY"));
+/// assert_eq!(secret.expose(), "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY");
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wraps `value` and registers it for [`scrub`].
+    pub fn new(value: impl Into<String>) -> Self {
+        let value = value.into();
+        register(&value);
+        Self(value)
+    }
+
+    /// Returns the secret value.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&redacted(&self.0))
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({self})")
+    }
+}
+
+/// Registers `secret` so that [`scrub`] hides it wherever it shows up.
+pub fn register(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.lock()
+        && !secrets.iter().any(|s| s == secret)
+    {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// Returns `text` with registered secrets and the values of sensitive fields such as
+/// `aws_session_token = …` redacted.
+///
+/// ```
+/// use anyhow::{Context, anyhow};
+/// use aws_mfa::redact::{register, scrub};
+///
+/// let secret = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+/// register(secret);
+/// let error = anyhow!("invalid key {secret}").context("Failed to refresh");
+///
+/// let rendered = scrub(&format!("{error:?}"));
+/// assert!(!rendered.contains(secret));
+/// assert!(rendered.contains("wJal…REDACTED"));
+///
+/// let rendered = scrub("aws_session_token = FwoGZXIvYXdzEExample\ntoken_code=123456");
+/// assert_eq!(rendered, "aws_session_token = FwoG…REDACTED\ntoken_code=1234…REDACTED");
+/// ```
+pub fn scrub(text: &str) -> String {
+    let mut text = text.to_string();
+
+    if let Ok(secrets) = SECRETS.lock() {
+        // Longest first, so that a secret containing another is replaced as a whole.
+        let mut secrets: Vec<&String> = secrets.iter().collect();
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        for secret in secrets {
+            text = text.replace(secret.as_str(), &redacted(secret));
+        }
+    }
+
+    for field in SENSITIVE_FIELDS {
+        text = scrub_field(&text, field);
+    }
+    text
+}
+
+/// Redacts the values following `field` and `=` or `:` in `text`.
+fn scrub_field(text: &str, field: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find(field) {
+        let (before, after) = rest.split_at(i + field.len());
+        out.push_str(before);
+
+        let separator = after
+            .find(|c: char| !(c.is_whitespace() || "=:\"'".contains(c)))
+            .unwrap_or(after.len());
+        let (separator, value) = after.split_at(separator);
+        if !separator.contains(['=', ':']) {
+            rest = after;
+            continue;
+        }
+        out.push_str(separator);
+
+        let end = value
+            .find(|c: char| c.is_whitespace() || "\"',;)}".contains(c))
+            .unwrap_or(value.len());
+        let (value, after) = value.split_at(end);
+        match value {
+            v if v.is_empty() || v.ends_with("REDACTED") => out.push_str(v),
+            v => out.push_str(&redacted(v)),
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Returns the first [`VISIBLE`] characters of `value` followed by `…REDACTED`.
+fn redacted(value: &str) -> String {
+    let visible: String = value.chars().take(VISIBLE).collect();
+    format!("{visible}…REDACTED")
+}
@@ -0,0 +1,165 @@
+//! Validity left of the session of a profile, for shell prompts.
+//!
+//! Prompts ask for it before every command, so it is read from the credentials file alone, with
+//! neither the SDK, the async runtime nor the logger set up, and anything in the way of an answer
+//! means that there is no session to show rather than an error.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::read_to_string,
+    path::PathBuf,
+    process::ExitCode,
+    time::{Duration, SystemTime},
+};
+
+use aws_smithy_types::{DateTime, date_time::Format};
+
+use crate::{credentials_file::CredentialsFile, session_keys::SessionKeys, updater};
+
+/// Validity left of a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Remaining {
+    /// The session is valid for this long.
+    Valid(Duration),
+    /// The session has expired.
+    Expired,
+    /// There is no session, or no expiration that can be read.
+    NoSession,
+}
+
+impl Remaining {
+    /// Returns the validity left at `now` of the session recorded to expire at `expiration`, in
+    /// the form of the credentials file.
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// use aws_mfa::remaining::Remaining;
+    ///
+    /// // 2024-05-03T19:21:04Z
+    /// let expiration = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_764_064);
+    /// let recorded = Some("2024-05-03T19:21:04Z");
+    ///
+    /// let before = expiration - Duration::from_secs(90);
+    /// assert_eq!(Remaining::at(before, recorded), Remaining::Valid(Duration::from_secs(90)));
+    /// assert_eq!(Remaining::at(expiration, recorded), Remaining::Expired);
+    /// assert_eq!(Remaining::at(before, Some("tomorrow")), Remaining::NoSession);
+    /// assert_eq!(Remaining::at(before, None), Remaining::NoSession);
+    /// ```
+    pub fn at(now: SystemTime, expiration: Option<&str>) -> Self {
+        let expiration = expiration
+            .and_then(|expiration| DateTime::from_str(expiration, Format::DateTime).ok())
+            .and_then(|expiration| SystemTime::try_from(expiration).ok());
+        match expiration.map(|expiration| expiration.duration_since(now)) {
+            Some(Ok(left)) if !left.is_zero() => Self::Valid(left),
+            Some(_) => Self::Expired,
+            None => Self::NoSession,
+        }
+    }
+
+    /// Reads the validity left at `now` of the session in `section` of the credentials file at
+    /// `path` [default: ~/.aws/credentials]. The expiration is looked up under `keys`, then the
+    /// known keys. A file that is missing or can't be read has no session.
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// use aws_mfa::{remaining::Remaining, session_keys::SessionKeys};
+    ///
+    /// let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/remaining/credentials");
+    /// // 2024-05-03T16:09:04Z
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_752_544);
+    /// let keys = SessionKeys::default();
+    /// let read = |section| Remaining::read(Some(fixture.into()), section, &keys, now);
+    ///
+    /// assert_eq!(read("work").to_string(), "3h12m");
+    /// assert_eq!(read("work").exit_code(), std::process::ExitCode::SUCCESS);
+    /// assert_eq!(read("legacy"), Remaining::Expired);
+    /// assert_eq!(read("broken"), Remaining::NoSession);
+    /// assert_eq!(read("work-long-term"), Remaining::NoSession);
+    /// assert_eq!(read("personal"), Remaining::NoSession);
+    ///
+    /// let missing = Remaining::read(Some("/nonexistent".into()), "work", &keys, now);
+    /// assert_eq!(missing, Remaining::NoSession);
+    /// ```
+    pub fn read(path: Option<PathBuf>, section: &str, keys: &SessionKeys, now: SystemTime) -> Self {
+        let Ok(content) = updater::resolve_path(path).and_then(|path| Ok(read_to_string(path)?))
+        else {
+            return Self::NoSession;
+        };
+        let file = CredentialsFile::parse(&content);
+        Self::at(now, keys.expiration(&file, section))
+    }
+
+    /// Returns the number of seconds left, for scripts: `0` once expired, and nothing without a
+    /// session.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use aws_mfa::remaining::Remaining;
+    ///
+    /// assert_eq!(Remaining::Valid(Duration::from_millis(11_520_900)).porcelain(), "11520");
+    /// assert_eq!(Remaining::Expired.porcelain(), "0");
+    /// assert_eq!(Remaining::NoSession.porcelain(), "");
+    /// ```
+    pub fn porcelain(&self) -> String {
+        match self {
+            Self::Valid(left) => left.as_secs().to_string(),
+            Self::Expired => "0".to_string(),
+            Self::NoSession => String::new(),
+        }
+    }
+
+    /// Returns the exit code of the `remaining` command: 0 while the session is valid, 1 once it
+    /// has expired and 2 without a session.
+    ///
+    /// ```
+    /// use std::{process::ExitCode, time::Duration};
+    ///
+    /// use aws_mfa::remaining::Remaining;
+    ///
+    /// assert_eq!(Remaining::Valid(Duration::from_secs(1)).exit_code(), ExitCode::SUCCESS);
+    /// assert_eq!(Remaining::Expired.exit_code(), ExitCode::from(1));
+    /// assert_eq!(Remaining::NoSession.exit_code(), ExitCode::from(2));
+    /// ```
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::Valid(_) => ExitCode::SUCCESS,
+            Self::Expired => ExitCode::from(1),
+            Self::NoSession => ExitCode::from(2),
+        }
+    }
+}
+
+/// Renders the validity left compactly, with minute precision: `3h12m`, `47m`, `<1m`, `EXPIRED`,
+/// and nothing without a session.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use aws_mfa::remaining::Remaining;
+///
+/// let valid = |secs| Remaining::Valid(Duration::from_secs(secs)).to_string();
+/// assert_eq!(valid(11_520), "3h12m");
+/// assert_eq!(valid(10_800), "3h0m");
+/// assert_eq!(valid(129_600), "36h0m");
+/// assert_eq!(valid(2_859), "47m");
+/// assert_eq!(valid(59), "<1m");
+/// assert_eq!(Remaining::Expired.to_string(), "EXPIRED");
+/// assert_eq!(Remaining::NoSession.to_string(), "");
+/// ```
+impl Display for Remaining {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let minutes = match self {
+            Self::Valid(left) => left.as_secs() / 60,
+            Self::Expired => return f.write_str("EXPIRED"),
+            Self::NoSession => return Ok(()),
+        };
+        match (minutes / 60, minutes % 60) {
+            (0, 0) => f.write_str("<1m"),
+            (0, m) => write!(f, "{m}m"),
+            (h, m) => write!(f, "{h}h{m}m"),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Rotation of the long-term IAM access key.
+//!
+//! The steps are ordered so that the credentials file never points at a deleted key: the new key
+//! is created, written and verified before the old one is touched, and if writing or verifying
+//! fails, the old key is restored in the file and the new one deleted again.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use aws_sdk_iam::{Client, types::StatusType};
+use aws_sdk_sts::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::get_caller_identity::GetCallerIdentityError,
+};
+use log::{info, warn};
+use tokio::time::sleep;
+
+use crate::{credentials::caller_identity, error::Failure, updater::AwsMfaUpdater};
+
+/// How often the new key is tried before it is considered broken. IAM keys take a few seconds to
+/// become usable.
+const VERIFY_ATTEMPTS: u32 = 6;
+
+/// Delay between attempts to use the new key.
+const VERIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Replaces the long-term access key of `updater` with a new one created through `iam`, which must
+/// sign requests as the same IAM user (usually with its MFA session).
+///
+/// The old key is deactivated and deleted once `confirm` agrees, unless `keep_old` is set.
+pub async fn rotate_key(
+    updater: &AwsMfaUpdater,
+    iam: &Client,
+    keep_old: bool,
+    confirm: impl Fn(&str) -> Result<bool>,
+) -> Result<()> {
+    let old = updater.credentials().clone();
+    let old_id = old.access_key_id().to_string();
+
+    info!("Creating a new access key to replace {old_id}");
+    let created = match iam.create_access_key().send().await {
+        Ok(output) => output.access_key.context("No access key returned")?,
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_limit_exceeded_exception()) => {
+            let others = other_keys(iam, &old_id).await.unwrap_or_default();
+            bail!(
+                "This IAM user already has two access keys, the maximum. Delete the one aws-mfa \
+                 doesn't use first: aws iam delete-access-key --access-key-id {}",
+                others.first().map_or("<key id>", String::as_str)
+            );
+        }
+        Err(e) => return Err(Failure::sdk(e)).context("Failed to create a new access key"),
+    };
+    let (new_id, new_secret) = (created.access_key_id(), created.secret_access_key());
+
+    let result = async {
+        updater.set_long_term_keys(new_id, new_secret).await?;
+        verify(&updater.client_with_keys(new_id, new_secret).await).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        warn!("The new access key {new_id} can't be used; restoring {old_id}");
+        updater
+            .set_long_term_keys(&old_id, old.secret_access_key())
+            .await
+            .context("Failed to restore the old access key; re-enter it with `aws-mfa --fix`")?;
+        if let Err(e) = iam.delete_access_key().access_key_id(new_id).send().await {
+            warn!("Failed to delete the new access key {new_id}: {}", Failure::sdk(e));
+        }
+        return Err(e.context("The new access key didn't work"));
+    }
+    info!("The new access key {new_id} works");
+
+    if keep_old {
+        info!("Keeping the old access key {old_id}; delete it once nothing else uses it");
+        return Ok(());
+    }
+    if !confirm(&format!("Deactivate and delete the old access key {old_id}?"))? {
+        info!("Keeping the old access key {old_id}");
+        return Ok(());
+    }
+
+    iam.update_access_key()
+        .access_key_id(&old_id)
+        .status(StatusType::Inactive)
+        .send()
+        .await
+        .map_err(Failure::sdk)
+        .with_context(|| format!("Failed to deactivate the old access key {old_id}"))?;
+    iam.delete_access_key()
+        .access_key_id(&old_id)
+        .send()
+        .await
+        .map_err(Failure::sdk)
+        .with_context(|| format!("Deactivated but failed to delete the old access key {old_id}"))?;
+    info!("Deleted the old access key {old_id}");
+
+    Ok(())
+}
+
+/// Calls GetCallerIdentity with the new key until it works or [`VERIFY_ATTEMPTS`] are used up.
+/// Only a key STS doesn't know yet is tried again; any other failure won't go away by waiting.
+async fn verify(sts: &aws_sdk_sts::Client) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match caller_identity(sts).await {
+            Ok(identity) => {
+                info!("Verified: account {}, {}", identity.account, identity.arn);
+                return Ok(());
+            }
+            Err(e) if attempt < VERIFY_ATTEMPTS && unknown_key(&e) => {
+                info!("The new access key isn't active yet, retrying");
+                sleep(VERIFY_INTERVAL).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Returns whether STS refused `error`'s request as signed with an access key it doesn't know,
+/// which is what a new key gives until it has propagated.
+fn unknown_key(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<SdkError<GetCallerIdentityError>>())
+        .any(|e| e.code() == Some("InvalidClientTokenId"))
+}
+
+/// Returns the IDs of the user's access keys other than `current`.
+async fn other_keys(iam: &Client, current: &str) -> Result<Vec<String>> {
+    let keys = iam.list_access_keys().send().await.map_err(Failure::sdk)?;
+    Ok(keys
+        .access_key_metadata()
+        .iter()
+        .filter_map(|key| key.access_key_id())
+        .filter(|id| *id != current)
+        .map(str::to_string)
+        .collect())
+}
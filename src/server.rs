@@ -0,0 +1,216 @@
+//! Local credential server mimicking the EC2 instance metadata service (IMDS).
+//!
+//! Serves the session credentials of the selected profile on `127.0.0.1` under the IMDS credential
+//! paths, so SDKs that only know how to talk to IMDS can use them via
+//! `AWS_EC2_METADATA_SERVICE_ENDPOINT`.
+//! All paths are nested below a secret prefix so that other local processes can't scrape the
+//! credentials without knowing it.
+//!
+//! [`run`] serves until a shutdown signal; a bound [`Server`] serves until dropped, which lets
+//! library consumers and tests pick the port and stop it themselves.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use aws_smithy_types::date_time::Format;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    select,
+    task::spawn_blocking,
+};
+
+use crate::{error::Report, updater::AwsMfaUpdater, watch::shutdown_signal};
+
+/// Role name reported by the credential listing endpoint.
+const ROLE_NAME: &str = "aws-mfa";
+
+/// Upper bound for the size of a request head.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Credential document in the format returned by IMDS.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SecurityCredentials {
+    code: &'static str,
+    last_updated: String,
+    #[serde(rename = "Type")]
+    kind: &'static str,
+    access_key_id: String,
+    secret_access_key: String,
+    token: String,
+    expiration: String,
+}
+
+/// A credential server listening on `127.0.0.1`.
+pub struct Server {
+    listener: TcpListener,
+    secret: String,
+}
+
+impl Server {
+    /// Listens on `127.0.0.1:<port>`, any free port if 0. `secret` is the path prefix clients
+    /// must use; a random one is generated when omitted.
+    pub async fn bind(port: u16, secret: Option<String>) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .await
+            .with_context(|| format!("Failed to bind 127.0.0.1:{port}"))?;
+        let secret = secret.unwrap_or_else(random_secret);
+        Ok(Self { listener, secret })
+    }
+
+    /// Returns the endpoint to set as `AWS_EC2_METADATA_SERVICE_ENDPOINT`, secret included.
+    pub fn endpoint(&self) -> Result<String> {
+        let port = self.listener.local_addr()?.port();
+        Ok(format!("http://127.0.0.1:{port}/{}", self.secret))
+    }
+
+    /// Answers requests one at a time, forever unless accepting a connection fails.
+    ///
+    /// Credentials are refreshed through `get_token` when a request arrives less than
+    /// `min_remaining` before expiration.
+    pub async fn serve<F>(
+        &self,
+        updater: &AwsMfaUpdater,
+        min_remaining: Duration,
+        get_token: F,
+    ) -> Result<()>
+    where
+        F: Fn() -> Result<String> + Clone + Send + 'static,
+    {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            if let Err(e) = handle(stream, updater, &self.secret, min_remaining, &get_token).await {
+                warn!("Failed to handle request: {}", Report(&e));
+            }
+        }
+    }
+}
+
+/// Serves credentials on `127.0.0.1:<port>` until SIGINT/SIGTERM is received; see [`Server`].
+pub async fn run<F>(
+    updater: &AwsMfaUpdater,
+    port: u16,
+    secret: Option<String>,
+    min_remaining: Duration,
+    get_token: F,
+) -> Result<()>
+where
+    F: Fn() -> Result<String> + Clone + Send + 'static,
+{
+    let server = Server::bind(port, secret).await?;
+
+    info!("Serving credentials, point your tools at this endpoint:");
+    info!("  export AWS_EC2_METADATA_SERVICE_ENDPOINT={}", server.endpoint()?);
+
+    select! {
+        result = server.serve(updater, min_remaining, get_token) => result,
+        result = shutdown_signal() => {
+            info!("Shutting down");
+            result
+        }
+    }
+}
+
+async fn handle<F>(
+    mut stream: TcpStream,
+    updater: &AwsMfaUpdater,
+    secret: &str,
+    min_remaining: Duration,
+    get_token: &F,
+) -> Result<()>
+where
+    F: Fn() -> Result<String> + Clone + Send + 'static,
+{
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || request.len() + n > MAX_REQUEST_SIZE {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    debug!("{method} request");
+
+    let path = path.strip_prefix('/').and_then(|p| p.strip_prefix(secret));
+    let credentials_path = "/latest/meta-data/iam/security-credentials/";
+
+    let (status, body) = match (method, path) {
+        ("PUT", Some("/latest/api/token")) => ("200 OK", random_secret()),
+        ("GET", Some(p)) if p == credentials_path => ("200 OK", ROLE_NAME.to_string()),
+        ("GET", Some(p)) if p.strip_prefix(credentials_path) == Some(ROLE_NAME) => {
+            match credentials(updater, min_remaining, get_token).await {
+                Ok(body) => ("200 OK", body),
+                Err(e) => {
+                    error!("Failed to provide credentials: {}", Report(&e));
+                    ("500 Internal Server Error", String::new())
+                }
+            }
+        }
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(stream.shutdown().await?)
+}
+
+/// Returns the IMDS credential document, refreshing the session first if it is about to expire.
+async fn credentials<F>(
+    updater: &AwsMfaUpdater,
+    min_remaining: Duration,
+    get_token: &F,
+) -> Result<String>
+where
+    F: Fn() -> Result<String> + Clone + Send + 'static,
+{
+    let session = match updater.reusable_session(min_remaining)? {
+        Some(session) => session,
+        None => {
+            info!("Session missing or about to expire, refreshing");
+            let token = async { spawn_blocking(get_token.clone()).await? };
+            updater.update_credentials_with(token).await?;
+            updater.session()?.context("No session credentials after refresh")?
+        }
+    };
+    let credentials = SecurityCredentials {
+        code: "Success",
+        last_updated: aws_smithy_types::DateTime::from(SystemTime::now()).fmt(Format::DateTime)?,
+        kind: "AWS-HMAC",
+        access_key_id: session.access_key_id,
+        secret_access_key: session.secret_access_key.expose().to_string(),
+        token: session.session_token.expose().to_string(),
+        expiration: session.expiration.fmt(Format::DateTime)?,
+    };
+
+    Ok(serde_json::to_string_pretty(&credentials)?)
+}
+
+/// Generates a 128-bit random hex string from the standard library's per-process hash keys.
+fn random_secret() -> String {
+    let half = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(), half())
+}
@@ -0,0 +1,257 @@
+//! Keys written to a session section.
+//!
+//! Tools disagree on what they read: the AWS SDKs only need `aws_session_token`, some old tools
+//! only know `aws_security_token`, and a few look for the expiration under another name. By
+//! default the section gets the keys aws-mfa has always written; [`SessionKeys`] drops the legacy
+//! token or renames the expiration. Role credentials also get `assumed_role` and
+//! `assumed_role_arn`, as written by the Python aws-mfa, for prompts and status lines to show.
+//! Settings of the long-term section that tools read per profile, such as `region`, can be copied
+//! along with [`SessionKeys::with_copied_keys`].
+
+use anyhow::{Result, ensure};
+use aws_sdk_sts::types;
+use aws_smithy_types::date_time::Format;
+
+use crate::{credentials_file::CredentialsFile, redact, vault};
+
+/// Expiration keys known to be read by some tool, in the order they are looked up.
+pub const EXPIRATION_KEYS: [&str; 3] =
+    ["expiration", "aws_session_expiration", "x_security_token_expires"];
+
+/// Keys written for role credentials only.
+pub const ROLE_KEYS: [&str; 2] = ["assumed_role", "assumed_role_arn"];
+
+/// Keys holding the session itself, which can't be renamed.
+const CREDENTIAL_KEYS: [&str; 4] =
+    ["aws_access_key_id", "aws_secret_access_key", "aws_session_token", "aws_security_token"];
+
+/// Parts of key names that give away secret material, which is never copied.
+const SECRET_MARKERS: [&str; 4] = ["secret", "token", "password", "access_key"];
+
+/// Which keys a session section gets.
+///
+/// ```
+/// use aws_mfa::{credentials_file::CredentialsFile, session_keys::SessionKeys};
+/// use aws_sdk_sts::types::Credentials;
+/// use aws_smithy_types::DateTime;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let session = Credentials::builder()
+///     .access_key_id("ASIAEXAMPLE")
+///     .secret_access_key("secret")
+///     .session_token("token")
+///     .expiration(DateTime::from_secs(1_714_764_064))
+///     .build()?;
+/// let render = |keys: &SessionKeys, role| -> anyhow::Result<String> {
+///     let mut file = CredentialsFile::default();
+///     for (key, value) in keys.entries(&session, role)? {
+///         file.set("work", key, &value);
+///     }
+///     Ok(file.render())
+/// };
+/// let role = Some("arn:aws:iam::123456789012:role/admin");
+///
+/// // Defaults, as aws-mfa has always written them.
+/// assert_eq!(
+///     render(&SessionKeys::default(), None)?,
+///     "[work]\naws_access_key_id=ASIAEXAMPLE\naws_secret_access_key=secret\n\
+///      aws_session_token=token\naws_security_token=token\nexpiration=2024-05-03T19:21:04Z\n"
+/// );
+///
+/// // --no-legacy-token --expiration-key aws_session_expiration --expiration-key expiration
+/// let keys = SessionKeys::default()
+///     .with_legacy_token(false)
+///     .with_expiration_keys(vec!["aws_session_expiration".into(), "expiration".into()])?;
+/// assert_eq!(
+///     render(&keys, None)?,
+///     "[work]\naws_access_key_id=ASIAEXAMPLE\naws_secret_access_key=secret\n\
+///      aws_session_token=token\naws_session_expiration=2024-05-03T19:21:04Z\n\
+///      expiration=2024-05-03T19:21:04Z\n"
+/// );
+/// assert_eq!(keys.dropped(true), ["aws_security_token", "x_security_token_expires"]);
+///
+/// // --expiration-key x_security_token_expires, with a role
+/// let expires = vec!["x_security_token_expires".to_string()];
+/// let keys = SessionKeys::default().with_expiration_keys(expires)?;
+/// assert_eq!(
+///     render(&keys, role)?,
+///     "[work]\naws_access_key_id=ASIAEXAMPLE\naws_secret_access_key=secret\n\
+///      aws_session_token=token\naws_security_token=token\n\
+///      x_security_token_expires=2024-05-03T19:21:04Z\nassumed_role=True\n\
+///      assumed_role_arn=arn:aws:iam::123456789012:role/admin\n"
+/// );
+/// assert_eq!(keys.dropped(true), ["expiration", "aws_session_expiration"]);
+/// assert_eq!(
+///     SessionKeys::default().dropped(false),
+///     ["aws_session_expiration", "x_security_token_expires", "assumed_role", "assumed_role_arn"]
+/// );
+///
+/// // The credentials themselves can't be renamed.
+/// for key in ["aws_session_token", "", "my expiration", "[x]"] {
+///     assert!(SessionKeys::default().with_expiration_keys(vec![key.into()]).is_err());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SessionKeys {
+    legacy_token: bool,
+    expiration_keys: Vec<String>,
+    copied: Vec<String>,
+}
+
+impl Default for SessionKeys {
+    fn default() -> Self {
+        Self {
+            legacy_token: true,
+            expiration_keys: vec![EXPIRATION_KEYS[0].to_string()],
+            copied: Vec::new(),
+        }
+    }
+}
+
+impl SessionKeys {
+    /// Sets whether the session token is also written as `aws_security_token` [default: true].
+    pub fn with_legacy_token(mut self, enabled: bool) -> Self {
+        self.legacy_token = enabled;
+        self
+    }
+
+    /// Writes the expiration under each of `keys`, in order [default: `expiration`]. An empty
+    /// list keeps the default.
+    pub fn with_expiration_keys(mut self, keys: Vec<String>) -> Result<Self> {
+        for key in &keys {
+            ensure!(
+                !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "Invalid expiration key {key:?}; use letters, digits and underscores"
+            );
+            ensure!(
+                !CREDENTIAL_KEYS.contains(&key.as_str()) && !ROLE_KEYS.contains(&key.as_str()),
+                "{key} can't hold the expiration"
+            );
+        }
+        if !keys.is_empty() {
+            self.expiration_keys = keys;
+        }
+        Ok(self)
+    }
+
+    /// Copies `keys` from the long-term section to the session section on every refresh, e.g.
+    /// `region` for tools that only read it from the profile they use [default: none]. A copied
+    /// key overwrites the session section's own; other keys there are kept as they are.
+    ///
+    /// Keys that hold secret material, or that aws-mfa writes for the session itself, are
+    /// refused, naming the key:
+    ///
+    /// ```
+    /// use aws_mfa::session_keys::SessionKeys;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let keys = SessionKeys::default().with_copied_keys(vec!["region".into(), "output".into()])?;
+    /// assert_eq!(keys.copied(), ["region", "output"]);
+    /// assert!(SessionKeys::default().copied().is_empty());
+    ///
+    /// let secrets = [
+    ///     "aws_access_key_id",
+    ///     "aws_secret_access_key",
+    ///     "aws_session_token",
+    ///     "aws_security_token",
+    ///     "aws_encrypted_keys",
+    ///     "my_api_token",
+    ///     "AWS_SECRET_ACCESS_KEY",
+    /// ];
+    /// for key in secrets {
+    ///     let error = SessionKeys::default().with_copied_keys(vec![key.into()]).unwrap_err();
+    ///     assert!(error.to_string().contains("secret material"), "{key}: {error}");
+    /// }
+    ///
+    /// let expires = SessionKeys::default()
+    ///     .with_expiration_keys(vec!["aws_session_expiration".into()])?;
+    /// for key in ["expiration", "aws_session_expiration", "assumed_role_arn"] {
+    ///     let error = expires.clone().with_copied_keys(vec![key.into()]).unwrap_err();
+    ///     assert!(error.to_string().contains("written for the session"), "{key}: {error}");
+    /// }
+    /// assert!(SessionKeys::default().with_copied_keys(vec!["my region".into()]).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_copied_keys(mut self, keys: Vec<String>) -> Result<Self> {
+        for key in &keys {
+            ensure!(
+                !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "Invalid key to copy {key:?}; use letters, digits and underscores"
+            );
+            let lower = key.to_ascii_lowercase();
+            ensure!(
+                !CREDENTIAL_KEYS.contains(&lower.as_str())
+                    && lower != vault::ENCRYPTED_KEYS
+                    && !SECRET_MARKERS.iter().any(|marker| lower.contains(marker)),
+                "{key} may hold secret material, and is never copied to the session section"
+            );
+            let managed = EXPIRATION_KEYS
+                .into_iter()
+                .chain(ROLE_KEYS)
+                .chain(self.expiration_keys.iter().map(String::as_str));
+            ensure!(
+                !managed.into_iter().any(|k| k == lower),
+                "{key} is written for the session itself, and can't be copied"
+            );
+        }
+        self.copied = keys;
+        Ok(self)
+    }
+
+    /// Returns the keys copied from the long-term section, see [`Self::with_copied_keys`].
+    pub fn copied(&self) -> &[String] {
+        &self.copied
+    }
+
+    /// Returns the keys to write for `session`, with their values; `role_arn` is the role the
+    /// session belongs to, if any.
+    pub fn entries(
+        &self,
+        session: &types::Credentials,
+        role_arn: Option<&str>,
+    ) -> Result<Vec<(&str, String)>> {
+        redact::register(session.secret_access_key());
+        redact::register(session.session_token());
+
+        let mut entries = vec![
+            ("aws_access_key_id", session.access_key_id().to_string()),
+            ("aws_secret_access_key", session.secret_access_key().to_string()),
+            ("aws_session_token", session.session_token().to_string()),
+        ];
+        if self.legacy_token {
+            entries.push(("aws_security_token", session.session_token().to_string()));
+        }
+        let expiration = session.expiration().fmt(Format::DateTime)?;
+        for key in &self.expiration_keys {
+            entries.push((key, expiration.clone()));
+        }
+        if let Some(role_arn) = role_arn {
+            entries.push((ROLE_KEYS[0], "True".to_string()));
+            entries.push((ROLE_KEYS[1], role_arn.to_string()));
+        }
+        Ok(entries)
+    }
+
+    /// Returns the keys aws-mfa may have written before that [`Self::entries`] no longer writes,
+    /// to be removed from the section so that they don't outlive the session they describe.
+    pub fn dropped(&self, role: bool) -> Vec<&'static str> {
+        let legacy = (!self.legacy_token).then_some(CREDENTIAL_KEYS[3]);
+        let expiration = EXPIRATION_KEYS
+            .into_iter()
+            .filter(|key| !self.expiration_keys.iter().any(|k| k == key));
+        let role = ROLE_KEYS.into_iter().filter(|_| !role);
+        legacy.into_iter().chain(expiration).chain(role).collect()
+    }
+
+    /// Returns the expiration recorded in `section`, looking up the configured keys first, then
+    /// the known ones.
+    pub fn expiration<'a>(&self, file: &'a CredentialsFile, section: &str) -> Option<&'a str> {
+        let configured = self.expiration_keys.iter().map(String::as_str);
+        configured
+            .chain(EXPIRATION_KEYS)
+            .find_map(|key| file.get(section, key))
+    }
+}
@@ -0,0 +1,468 @@
+//! Defaults a profile keeps in its long-term section, e.g. `[work-long-term]`.
+//!
+//! Besides the keys, the section may say how the profile is refreshed: for how long, in which
+//! region, assuming which role, with which 1Password item. Flags and environment variables
+//! override these. Settings that mean the same to the AWS CLI are also read from the profile in
+//! the AWS config file; the others would change what the AWS CLI does there, and are read from
+//! the long-term section only. Sessions are written to their own section, so none of this ends
+//! up next to the temporary credentials.
+//!
+//! Settings kept apart from the AWS files go to the aws-mfa [`ConfigFile`],
+//! `~/.config/aws-mfa/config.toml`, which fills in what neither of them says.
+
+use std::{
+    collections::BTreeMap,
+    env::var_os,
+    fmt::{self, Display, Formatter},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::{
+    config::AwsConfig,
+    credentials_file::CredentialsFile,
+    keyring_keys, path,
+    session_store::CredentialStore,
+    setup,
+    token::Providers,
+    updater::{MAX_MFA_DEVICES, Profile, resolve_path},
+};
+
+/// Location of the aws-mfa config file in the home directory, unless `AWS_MFA_CONFIG_FILE` names
+/// another.
+pub const CONFIG_FILE: &str = ".config/aws-mfa/config.toml";
+
+/// Session duration in seconds.
+pub const DURATION: &str = "aws_mfa_duration";
+
+/// Duration in seconds of the MFA session roles are assumed with, see `--via-session`.
+pub const SESSION_DURATION: &str = "aws_mfa_session_duration";
+
+/// Duration in seconds of the role sessions.
+pub const ROLE_DURATION: &str = "aws_mfa_role_duration";
+
+/// Command run after every refresh, see [`crate::hook`].
+pub const ON_REFRESH_HOOK: &str = "aws_mfa_on_refresh_hook";
+
+/// Command run in watch mode when the session is about to expire, see [`crate::hook`].
+pub const ON_EXPIRING_HOOK: &str = "aws_mfa_on_expiring_hook";
+
+/// Comma-separated keys copied from the long-term section to the session section, see
+/// [`crate::session_keys::SessionKeys::with_copied_keys`].
+pub const COPY_KEYS: &str = "aws_mfa_copy_keys";
+
+/// Where sessions are kept: `file`, `keyring` or `both`, see [`crate::session_store`].
+pub const CREDENTIAL_STORE: &str = "aws_mfa_credential_store";
+
+/// Bitwarden item with the TOTP, see [`crate::token::Bitwarden`].
+pub const BW_ITEM: &str = "aws_mfa_bw_item";
+
+/// Entry of the password store with the one-time password, see [`crate::token::Pass`].
+pub const PASS_ENTRY: &str = "aws_mfa_pass_entry";
+
+/// OATH account of a YubiKey, see [`crate::token::YubiKey`].
+pub const YUBIKEY_ACCOUNT: &str = "aws_mfa_yubikey_account";
+
+/// Comma-separated sources of MFA codes, in the order they are asked, see
+/// [`Providers`].
+pub const PROVIDERS: &str = "aws_mfa_providers";
+
+/// Command printing the current MFA code, see [`crate::token::Sources::with_command`].
+pub const TOKEN_COMMAND: &str = "aws_mfa_token_command";
+
+/// Section the session is written to instead of the profile's own, see `--target-profile`.
+pub const TARGET_PROFILE: &str = "aws_mfa_target_profile";
+
+/// Region of the STS endpoint, unless `AWS_REGION` or `AWS_DEFAULT_REGION` is set.
+pub const REGION: &str = "region";
+
+/// Role to assume, or a comma-separated chain of them.
+pub const ROLE_ARN: &str = "role_arn";
+
+/// `aws_mfa_*` keys that may appear in a long-term section, besides `aws_mfa_device_<n>`.
+const KNOWN_KEYS: [&str; 18] = [
+    "aws_mfa_device",
+    "aws_mfa_device_last_used",
+    setup::OP_ACCOUNT,
+    setup::OP_ITEM_NAME,
+    BW_ITEM,
+    PASS_ENTRY,
+    YUBIKEY_ACCOUNT,
+    DURATION,
+    SESSION_DURATION,
+    ROLE_DURATION,
+    ON_REFRESH_HOOK,
+    ON_EXPIRING_HOOK,
+    COPY_KEYS,
+    CREDENTIAL_STORE,
+    PROVIDERS,
+    TOKEN_COMMAND,
+    TARGET_PROFILE,
+    keyring_keys::KEYS_IN_KEYRING,
+];
+
+/// Settings of a profile, see the [module documentation](self).
+///
+/// ```
+/// use aws_mfa::{
+///     config::AwsConfig, credentials_file::CredentialsFile, session_store::CredentialStore,
+///     settings::ProfileSettings, token::Providers,
+/// };
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let file = CredentialsFile::parse(
+///     "[work-long-term]\naws_mfa_duration = 14400\nregion = eu-west-1\n\
+///      aws_mfa_session_duration = 43200\naws_mfa_role_duration = 3600\n\
+///      role_arn = arn:aws:iam::123456789012:role/a, arn:aws:iam::210987654321:role/b\n\
+///      aws_mfa_op_item_name = AWS Work\nsession_tags = team=infra, env=prod\n\
+///      aws_mfa_copy_keys = region, output,\naws_mfa_providers = keyring, op\n",
+/// );
+/// let config = AwsConfig::parse(
+///     "[profile work]\naws_mfa_op_item_name = AWS Shared\naws_mfa_op_account = my.1password.com\n\
+///      aws_mfa_on_refresh_hook = pkill -RTMIN+8 waybar\naws_mfa_credential_store = keyring\n\
+///      role_arn = arn:aws:iam::123456789012:role/cli\nregion = us-east-1\n\
+///      aws_mfa_providers = op, prompt\naws_mfa_target_profile = work-mfa\n\
+///      aws_mfa_token_command = gopass otp -o aws/work\naws_mfa_bw_item = AWS\n\
+///      aws_mfa_pass_entry = aws/work\naws_mfa_yubikey_account = AWS:work\n",
+/// );
+/// let settings = ProfileSettings::read(&file, "work-long-term", &config, "work")?;
+/// assert_eq!(settings.duration, Some(14400));
+/// assert_eq!((settings.session_duration, settings.role_duration), (Some(43200), Some(3600)));
+/// assert_eq!(settings.region.as_deref(), Some("eu-west-1"));
+/// assert_eq!(settings.role_arns.len(), 2);
+/// assert_eq!(settings.session_tags, ["team=infra", "env=prod"]);
+/// assert_eq!(settings.copy_keys, ["region", "output"]);
+/// // The long-term section beats the config file, which fills in what it lacks.
+/// assert_eq!(settings.op_item_name.as_deref(), Some("AWS Work"));
+/// assert_eq!(settings.op_account.as_deref(), Some("my.1password.com"));
+/// assert_eq!(settings.on_refresh_hook.as_deref(), Some("pkill -RTMIN+8 waybar"));
+/// assert_eq!(settings.credential_store, Some(CredentialStore::Keyring));
+/// assert_eq!(settings.providers, Some("keyring, op".parse::<Providers>()?));
+/// assert_eq!(settings.target_profile.as_deref(), Some("work-mfa"));
+/// assert_eq!(settings.bw_item.as_deref(), Some("AWS"));
+/// assert_eq!(settings.pass_entry.as_deref(), Some("aws/work"));
+/// assert_eq!(settings.yubikey_account.as_deref(), Some("AWS:work"));
+/// assert_eq!(settings.token_command.as_deref(), Some("gopass otp -o aws/work"));
+///
+/// // role_arn and region of the config file are the AWS CLI's, and not taken over.
+/// let empty = CredentialsFile::default();
+/// let settings = ProfileSettings::read(&empty, "work-long-term", &config, "work")?;
+/// assert_eq!(settings.op_item_name.as_deref(), Some("AWS Shared"));
+/// assert_eq!(settings.providers, Some("op, prompt".parse::<Providers>()?));
+/// assert!(settings.role_arns.is_empty() && settings.region.is_none());
+/// assert_eq!(settings.duration, None);
+///
+/// let file = CredentialsFile::parse("[work-long-term]\naws_mfa_duration = 4h\n");
+/// assert!(ProfileSettings::read(&file, "work-long-term", &config, "work").is_err());
+/// let file = CredentialsFile::parse("[work-long-term]\naws_mfa_credential_store = disk\n");
+/// assert!(ProfileSettings::read(&file, "work-long-term", &config, "work").is_err());
+/// // Unknown providers fail when the settings are read, rather than when a code is needed.
+/// let file = CredentialsFile::parse("[work-long-term]\naws_mfa_providers = op, authy\n");
+/// let error = ProfileSettings::read(&file, "work-long-term", &config, "work").unwrap_err();
+/// assert!(format!("{error:#}").contains("Unknown token provider \"authy\""), "{error:#}");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileSettings {
+    /// [`DURATION`].
+    pub duration: Option<u32>,
+    /// [`SESSION_DURATION`].
+    pub session_duration: Option<u32>,
+    /// [`ROLE_DURATION`].
+    pub role_duration: Option<u32>,
+    /// [`REGION`].
+    pub region: Option<String>,
+    /// [`ROLE_ARN`], split into the roles of the chain.
+    pub role_arns: Vec<String>,
+    /// `external_id` required by the role.
+    pub external_id: Option<String>,
+    /// `role_session_name` of the role session.
+    pub role_session_name: Option<String>,
+    /// `session_tags`, split into `key=value` pairs.
+    pub session_tags: Vec<String>,
+    /// 1Password account, [`setup::OP_ACCOUNT`].
+    pub op_account: Option<String>,
+    /// 1Password item with the one-time password, [`setup::OP_ITEM_NAME`].
+    pub op_item_name: Option<String>,
+    /// [`BW_ITEM`].
+    pub bw_item: Option<String>,
+    /// [`PASS_ENTRY`].
+    pub pass_entry: Option<String>,
+    /// [`YUBIKEY_ACCOUNT`].
+    pub yubikey_account: Option<String>,
+    /// [`ON_REFRESH_HOOK`].
+    pub on_refresh_hook: Option<String>,
+    /// [`ON_EXPIRING_HOOK`].
+    pub on_expiring_hook: Option<String>,
+    /// [`COPY_KEYS`], split into the keys.
+    pub copy_keys: Vec<String>,
+    /// [`CREDENTIAL_STORE`].
+    pub credential_store: Option<CredentialStore>,
+    /// [`PROVIDERS`].
+    pub providers: Option<Providers>,
+    /// [`TOKEN_COMMAND`].
+    pub token_command: Option<String>,
+    /// [`TARGET_PROFILE`].
+    pub target_profile: Option<String>,
+}
+
+impl ProfileSettings {
+    /// Reads the settings of `profile` from `section` of `file` and, for those the AWS CLI
+    /// shares, the profile in `config`. Warns about `aws_mfa_*` keys that aren't known, which
+    /// are likely misspelled.
+    pub fn read(
+        file: &CredentialsFile,
+        section: &str,
+        config: &AwsConfig,
+        profile: &str,
+    ) -> Result<Self> {
+        for key in unknown_keys(file, section) {
+            warn!("Ignoring unknown key {key} in [{section}]; is it misspelled?");
+        }
+
+        let own = |key| file.get(section, key).map(str::to_string);
+        let shared = |key| own(key).or_else(|| config.get(profile, key).map(str::to_string));
+        let seconds = |key| {
+            own(key)
+                .map(|d| d.parse())
+                .transpose()
+                .with_context(|| format!("Invalid {key} in [{section}]; expected seconds"))
+        };
+
+        Ok(Self {
+            duration: seconds(DURATION)?,
+            session_duration: seconds(SESSION_DURATION)?,
+            role_duration: seconds(ROLE_DURATION)?,
+            region: own(REGION),
+            role_arns: list(own(ROLE_ARN)),
+            external_id: shared("external_id"),
+            role_session_name: shared("role_session_name"),
+            session_tags: list(shared("session_tags")),
+            op_account: shared(setup::OP_ACCOUNT),
+            op_item_name: shared(setup::OP_ITEM_NAME),
+            bw_item: shared(BW_ITEM),
+            pass_entry: shared(PASS_ENTRY),
+            yubikey_account: shared(YUBIKEY_ACCOUNT),
+            on_refresh_hook: shared(ON_REFRESH_HOOK),
+            on_expiring_hook: shared(ON_EXPIRING_HOOK),
+            copy_keys: list(own(COPY_KEYS)),
+            credential_store: shared(CREDENTIAL_STORE)
+                .map(|store| store.parse())
+                .transpose()
+                .with_context(|| format!("Invalid {CREDENTIAL_STORE} of profile {profile}"))?,
+            providers: shared(PROVIDERS)
+                .map(|providers| providers.parse())
+                .transpose()
+                .with_context(|| format!("Invalid {PROVIDERS} of profile {profile}"))?,
+            token_command: shared(TOKEN_COMMAND),
+            target_profile: shared(TARGET_PROFILE),
+        })
+    }
+
+    /// Reads the settings of `profile` from its long-term section of the credentials file at
+    /// `path` [default: ~/.aws/credentials], which may not exist yet, the AWS config file and the
+    /// aws-mfa config file.
+    pub fn of(path: Option<PathBuf>, profile: &Profile) -> Result<Self> {
+        let path = resolve_path(path)?;
+        let file = match path.exists() {
+            true => CredentialsFile::load(&path)?,
+            false => CredentialsFile::default(),
+        };
+        let config = AwsConfig::load()?;
+        Self::read(&file, &profile.long_term_section(), &config, profile.name())?
+            .with_defaults(&ConfigFile::load()?, profile.name())
+    }
+
+    /// Fills in what the credentials and AWS config files don't say about `profile` from its
+    /// table in the aws-mfa config file `defaults`.
+    ///
+    /// ```
+    /// use aws_mfa::{
+    ///     config::AwsConfig, credentials_file::CredentialsFile,
+    ///     settings::{ConfigFile, ProfileSettings},
+    /// };
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let defaults = ConfigFile::parse(
+    ///     "[profiles.work]\nduration = 3600\nregion = \"eu-west-1\"\nop_item = \"AWS Work\"\n\
+    ///      target_profile = \"work-mfa\"\nproviders = \"op, prompt\"\n",
+    /// )?;
+    /// let file = CredentialsFile::parse("[work-long-term]\naws_mfa_duration = 14400\n");
+    /// let config = AwsConfig::default();
+    /// let settings = ProfileSettings::read(&file, "work-long-term", &config, "work")?
+    ///     .with_defaults(&defaults, "work")?;
+    /// // The long-term section beats the config file.
+    /// assert_eq!(settings.duration, Some(14400));
+    /// assert_eq!(settings.region.as_deref(), Some("eu-west-1"));
+    /// assert_eq!(settings.op_item_name.as_deref(), Some("AWS Work"));
+    /// assert_eq!(settings.target_profile.as_deref(), Some("work-mfa"));
+    /// assert!(settings.providers.is_some());
+    ///
+    /// let settings = ProfileSettings::default().with_defaults(&defaults, "personal")?;
+    /// assert_eq!(settings, ProfileSettings::default());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_defaults(mut self, defaults: &ConfigFile, profile: &str) -> Result<Self> {
+        let Some(table) = defaults.profile(profile) else {
+            return Ok(self);
+        };
+        let providers = table
+            .providers
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .with_context(|| format!("Invalid providers of [profiles.{profile}] in {defaults}"))?;
+
+        self.duration = self.duration.or(table.duration);
+        self.region = self.region.or_else(|| table.region.clone());
+        self.op_account = self.op_account.or_else(|| table.op_account.clone());
+        self.op_item_name = self.op_item_name.or_else(|| table.op_item.clone());
+        self.target_profile = self.target_profile.or_else(|| table.target_profile.clone());
+        self.providers = self.providers.or(providers);
+        Ok(self)
+    }
+}
+
+/// The aws-mfa config file: settings of each profile in a `[profiles.<name>]` table, for those
+/// who'd rather not keep them in the AWS files.
+///
+/// ```
+/// use aws_mfa::settings::ConfigFile;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let config = ConfigFile::parse(
+///     "[profiles.work]\nduration = 14400\nmfa_serial = \"arn:aws:iam::123456789012:mfa/me\"\n\
+///      op_account = \"my.1password.com\"\n",
+/// )?;
+/// let work = config.profile("work").unwrap();
+/// assert_eq!(work.duration, Some(14400));
+/// assert_eq!(work.mfa_serial.as_deref(), Some("arn:aws:iam::123456789012:mfa/me"));
+/// assert!(config.profile("personal").is_none());
+///
+/// // Misspelled settings fail rather than being ignored.
+/// let error = ConfigFile::parse("[profiles.work]\ndurration = 3600\n").unwrap_err();
+/// assert!(format!("{error:#}").contains("unknown field `durration`"), "{error:#}");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, ConfigProfile>,
+    /// Where the file was loaded from.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Settings of a profile in the [`ConfigFile`].
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigProfile {
+    /// Session duration in seconds, like [`DURATION`].
+    pub duration: Option<u32>,
+    /// MFA device, an ARN or a hardware token serial number, used if the long-term section has
+    /// none, like `mfa_serial` in the AWS config file.
+    pub mfa_serial: Option<String>,
+    /// 1Password item with the one-time password, like [`setup::OP_ITEM_NAME`].
+    pub op_item: Option<String>,
+    /// 1Password account, like [`setup::OP_ACCOUNT`].
+    pub op_account: Option<String>,
+    /// Section the session is written to, like [`TARGET_PROFILE`].
+    pub target_profile: Option<String>,
+    /// Region of the STS endpoint, like [`REGION`].
+    pub region: Option<String>,
+    /// Comma-separated sources of MFA codes, like [`PROVIDERS`].
+    pub providers: Option<String>,
+}
+
+impl ConfigFile {
+    /// Loads the file named by `AWS_MFA_CONFIG_FILE`, defaulting to [`CONFIG_FILE`] in the home
+    /// directory.
+    ///
+    /// A missing file is treated as empty.
+    pub fn load() -> Result<Self> {
+        let path = match var_os("AWS_MFA_CONFIG_FILE") {
+            Some(path) => path::expand(&PathBuf::from(path))?,
+            None => match home_dir() {
+                Some(home) => home.join(CONFIG_FILE),
+                None => return Ok(Self::default()),
+            },
+        };
+        Self::read(&path)
+    }
+
+    /// Loads the file at `path`, which may not exist.
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            debug!("No aws-mfa config file at {}", path.display());
+            return Ok(Self { path: Some(path.to_path_buf()), ..Self::default() });
+        }
+        let content =
+            read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let config =
+            Self::parse(&content).with_context(|| format!("Invalid {}", path.display()))?;
+        Ok(Self { path: Some(path.to_path_buf()), ..config })
+    }
+
+    /// Parses the content of a config file.
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Returns the settings of `profile`, if the file has a table for it.
+    pub fn profile(&self, profile: &str) -> Option<&ConfigProfile> {
+        self.profiles.get(profile)
+    }
+}
+
+impl Display for ConfigFile {
+    /// Names the file, for messages.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}", path.display()),
+            None => f.write_str("the aws-mfa config file"),
+        }
+    }
+}
+
+/// Returns the `aws_mfa_*` keys of `section` aws-mfa doesn't know.
+///
+/// ```
+/// use aws_mfa::{credentials_file::CredentialsFile, settings::unknown_keys};
+///
+/// let file = CredentialsFile::parse(
+///     "[work-long-term]\naws_mfa_device = GAHT12345678\naws_mfa_device_2 = GAHT87654321\n\
+///      aws_mfa_durration = 3600\naws_mfa_device_9 = GAHT00000000\nregion = eu-west-1\n",
+/// );
+/// assert_eq!(unknown_keys(&file, "work-long-term"), ["aws_mfa_durration", "aws_mfa_device_9"]);
+/// ```
+pub fn unknown_keys<'a>(file: &'a CredentialsFile, section: &str) -> Vec<&'a str> {
+    let numbered = |key: &str| {
+        key.strip_prefix("aws_mfa_device_")
+            .and_then(|n| n.parse::<usize>().ok())
+            .is_some_and(|n| (2..=MAX_MFA_DEVICES).contains(&n))
+    };
+    file.keys(section)
+        .filter(|key| key.starts_with("aws_mfa_"))
+        .filter(|key| !KNOWN_KEYS.contains(key) && !numbered(key))
+        .collect()
+}
+
+/// Splits a comma-separated list, dropping empty items.
+fn list(value: Option<String>) -> Vec<String> {
+    let value = value.unwrap_or_default();
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
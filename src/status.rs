@@ -0,0 +1,143 @@
+//! Overview of the sessions in the credentials file, for the `status` command.
+//!
+//! Like [`crate::remaining`], only the credentials file is read, so that scripts and shell
+//! prompts can call it cheaply. Sessions in the keyring can't be listed and aren't shown.
+
+use std::{fs::read_to_string, path::PathBuf, time::SystemTime};
+
+use anyhow::Result;
+
+use crate::{
+    credentials_file::CredentialsFile, history, output::KEY_ID_PREFIX, remaining::Remaining,
+    session_keys::SessionKeys, updater::resolve_path,
+};
+
+/// A section of the credentials file holding a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// Name of the section, the profile AWS tools use the session with.
+    pub section: String,
+    /// Leading characters of the access key ID, e.g. `ASIAIOSF`.
+    pub access_key_id_prefix: String,
+    /// Expiration as recorded in the section, if any.
+    pub expiration: Option<String>,
+    /// Validity left.
+    pub remaining: Remaining,
+}
+
+/// Lists the sections with a session token in the credentials file at `path` [default:
+/// ~/.aws/credentials], in file order, with their validity left at `now`. The expiration is
+/// looked up under `keys`, then the known keys. A missing file has no sessions.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// use aws_mfa::{
+///     remaining::Remaining,
+///     session_keys::SessionKeys,
+///     status::{read, render},
+/// };
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/remaining/credentials");
+/// // 2024-05-03T16:09:04Z
+/// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_752_544);
+/// let sessions = read(Some(fixture.into()), &SessionKeys::default(), now)?;
+///
+/// let found: Vec<_> = sessions.iter().map(|s| (s.section.as_str(), s.remaining)).collect();
+/// assert_eq!(
+///     found,
+///     [
+///         ("work", Remaining::Valid(Duration::from_secs(11_520))),
+///         ("legacy", Remaining::Expired),
+///         ("broken", Remaining::NoSession),
+///     ]
+/// );
+///
+/// let table = render(&sessions, "work", true);
+/// assert_eq!(
+///     table.lines().collect::<Vec<_>>(),
+///     [
+///         "  PROFILE  KEY       EXPIRES           LEFT",
+///         "* work     ASIAIOSF  2024-05-03 19:21  3h12m",
+///         "  legacy   ASIAIOSF  2024-05-03 16:00  EXPIRED",
+///         "  broken   ASIAIOSF  tomorrow          unknown",
+///     ]
+/// );
+///
+/// assert!(read(Some("/nonexistent".into()), &SessionKeys::default(), now)?.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+pub fn read(path: Option<PathBuf>, keys: &SessionKeys, now: SystemTime) -> Result<Vec<Session>> {
+    let path = resolve_path(path)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = CredentialsFile::parse(&read_to_string(path)?);
+
+    let mut sessions: Vec<Session> = Vec::new();
+    for section in file.sections() {
+        let is_session = file.get(section, "aws_session_token").is_some()
+            || file.get(section, "aws_security_token").is_some();
+        if !is_session || sessions.iter().any(|s| s.section == section) {
+            continue;
+        }
+        let expiration = keys.expiration(&file, section);
+        sessions.push(Session {
+            section: section.to_string(),
+            access_key_id_prefix: file
+                .get(section, "aws_access_key_id")
+                .map_or(String::new(), |id| id.chars().take(KEY_ID_PREFIX).collect()),
+            expiration: expiration.map(str::to_string),
+            remaining: Remaining::at(now, expiration),
+        });
+    }
+    Ok(sessions)
+}
+
+/// Renders `sessions` as a table, with times in local time unless `utc` is set, and the
+/// `selected` section marked with `*`.
+pub fn render(sessions: &[Session], selected: &str, utc: bool) -> String {
+    let rows: Vec<[String; 4]> = sessions
+        .iter()
+        .map(|s| {
+            let left = match s.remaining {
+                Remaining::NoSession => "unknown".to_string(),
+                remaining => remaining.to_string(),
+            };
+            [
+                s.section.clone(),
+                s.access_key_id_prefix.clone(),
+                s.expiration
+                    .as_deref()
+                    .map_or("-".to_string(), |e| history::time(e, utc)),
+                left,
+            ]
+        })
+        .collect();
+    let header = ["PROFILE", "KEY", "EXPIRES", "LEFT"].map(String::from);
+
+    let mut widths = [0; 4];
+    for row in rows.iter().chain([&header]) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    let marked =
+        sessions
+            .iter()
+            .zip(&rows)
+            .map(|(session, row)| match session.section == selected {
+                true => ("* ", row),
+                false => ("  ", row),
+            });
+    for (marker, row) in [("  ", &header)].into_iter().chain(marked) {
+        let cells: Vec<_> = widths.iter().zip(row).map(|(w, c)| format!("{c:w$}")).collect();
+        out += marker;
+        out += cells.join("  ").trim_end();
+        out += "\n";
+    }
+    out
+}
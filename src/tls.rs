@@ -0,0 +1,150 @@
+//! Extra root certificates, for proxies that intercept TLS and re-sign it with a corporate CA.
+//!
+//! The PEM file named by `AWS_CA_BUNDLE`, which the AWS CLI and the other SDKs honor too, or by
+//! `--ca-bundle` is trusted in addition to the platform's roots by every HTTP client of the
+//! process: the AWS SDK clients through [`sdk_http_client`], and the console sign-in through
+//! [`http_client`]. The bundle is checked when it is loaded, so a broken file is reported by name
+//! rather than failing each connection.
+
+use std::{
+    fs::read,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use aws_sdk_sts::config::SharedHttpClient;
+use aws_smithy_http_client::{
+    Builder, ConnectorBuilder,
+    proxy::ProxyConfig,
+    tls::{Provider, TlsContext, TrustStore, rustls_provider::CryptoMode},
+};
+use rustls::RootCertStore;
+use rustls_pki_types::{CertificateDer, pem::PemObject};
+
+/// Root certificates of the clients built from now on, see [`set_ca_bundle`].
+static CA_BUNDLE: Mutex<Option<CaBundle>> = Mutex::new(None);
+
+/// A PEM file of one or more root certificates.
+///
+/// ```
+/// use aws_mfa::tls::CaBundle;
+/// use rcgen::{CertificateParams, KeyPair};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-ca-bundle-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+/// let cert = |name: &str| -> anyhow::Result<String> {
+///     let params = CertificateParams::new(vec![name.to_string()])?;
+///     Ok(params.self_signed(&KeyPair::generate()?)?.pem())
+/// };
+///
+/// let path = dir.join("bundle.pem");
+/// std::fs::write(&path, cert("proxy.example.com")? + "\n" + &cert("proxy2.example.com")?)?;
+/// assert_eq!(CaBundle::load(&path)?.count(), 2);
+///
+/// std::fs::write(&path, "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n")?;
+/// let error = CaBundle::load(&path).unwrap_err();
+/// assert!(format!("{error:#}").contains("bundle.pem"));
+///
+/// std::fs::write(&path, "no certificates here\n")?;
+/// assert!(CaBundle::load(&path).unwrap_err().to_string().contains("no PEM certificates"));
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CaBundle {
+    path: PathBuf,
+    pem: Vec<u8>,
+    count: usize,
+}
+
+impl CaBundle {
+    /// Reads the bundle at `path` and checks each of its certificates.
+    pub fn load(path: &Path) -> Result<Self> {
+        let pem = read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let invalid = || format!("Invalid CA bundle {}", path.display());
+
+        let mut roots = RootCertStore::empty();
+        for (i, cert) in CertificateDer::pem_slice_iter(&pem).enumerate() {
+            let cert = cert
+                .map_err(|e| anyhow!("certificate {} is not valid PEM: {e}", i + 1))
+                .with_context(invalid)?;
+            roots
+                .add(cert)
+                .map_err(|e| anyhow!("certificate {} can't be parsed: {e}", i + 1))
+                .with_context(invalid)?;
+        }
+        if roots.is_empty() {
+            bail!("{} holds no PEM certificates", path.display());
+        }
+
+        Ok(Self { path: path.to_path_buf(), pem, count: roots.len() })
+    }
+
+    /// Returns the path the bundle was read from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of certificates in the bundle.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Trusts `bundle` in addition to the platform's roots in the HTTP clients built from now on.
+///
+/// ```no_run
+/// use aws_mfa::tls::{CaBundle, set_ca_bundle};
+///
+/// let bundle = CaBundle::load("/etc/ssl/corporate-ca.pem".as_ref())?;
+/// set_ca_bundle(Some(bundle));
+/// # anyhow::Ok(())
+/// ```
+pub fn set_ca_bundle(bundle: Option<CaBundle>) {
+    if let Ok(mut current) = CA_BUNDLE.lock() {
+        *current = bundle;
+    }
+}
+
+/// Returns the bundle set with [`set_ca_bundle`].
+fn ca_bundle() -> Option<CaBundle> {
+    CA_BUNDLE.lock().ok().and_then(|bundle| bundle.clone())
+}
+
+/// Returns the HTTP client for the AWS SDK trusting the bundle, or `None` for the SDK's default
+/// without one. Otherwise it is set up like the default: rustls with aws-lc, and the proxy from
+/// `HTTPS_PROXY` and `NO_PROXY`.
+pub fn sdk_http_client() -> Option<SharedHttpClient> {
+    let bundle = ca_bundle()?;
+    let trust_store = TrustStore::default().with_pem_certificate(bundle.pem);
+    let context = match TlsContext::builder().with_trust_store(trust_store).build() {
+        Ok(context) => context,
+        Err(_) => unreachable!("a TLS context only holds the trust store"),
+    };
+
+    Some(Builder::new().build_with_connector_fn(move |settings, components| {
+        let mut builder =
+            ConnectorBuilder::default().tls_provider(Provider::Rustls(CryptoMode::AwsLc));
+        builder.set_connector_settings(settings.cloned());
+        if let Some(components) = components {
+            builder.set_sleep_impl(components.sleep_impl());
+        }
+        builder.set_proxy_config(Some(ProxyConfig::from_env()));
+        builder.tls_context(context.clone()).build()
+    }))
+}
+
+/// Returns an HTTP client trusting the bundle, giving up on a request after `timeout`.
+pub fn http_client(timeout: Duration) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().timeout(timeout);
+    if let Some(bundle) = ca_bundle() {
+        for cert in reqwest::Certificate::from_pem_bundle(&bundle.pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,1886 @@
+//! MFA token retrieval.
+//!
+//! Tokens come from a TOTP secret in the keyring, 1Password, Bitwarden, pass, KeePassXC, a YubiKey
+//! or a command of the user's when configured (see [`Sources`]), and from a [`Prompt`] otherwise.
+//! The prompt is injectable so that library consumers aren't forced into reading stdin. Which of
+//! them are asked, and in which order, is up to [`Providers`].
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::read_to_string,
+    io::{BufRead, BufReader, IsTerminal, Read, Write, stderr, stdin},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, channel},
+    },
+    thread::{sleep, spawn},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use dirs::cache_dir;
+use log::{debug, info, warn};
+
+use crate::{
+    credentials_file::{create_private, create_private_dir},
+    error::{Failure, Report, ResultExt},
+    op::{self, Op},
+    progress::Progress,
+    redact::Secret,
+    totp::Totp,
+    totp_window::{DEFAULT_MIN_VALIDITY, PERIOD, remaining, wait_for_validity, window},
+};
+
+/// Source of manually entered MFA tokens.
+///
+/// Implemented for closures, so a callback can be passed wherever a prompt is expected:
+///
+/// ```
+/// use aws_mfa::token::get_mfa_token;
+///
+/// let token = get_mfa_token(None, None, &|| Ok("123456".to_string())).unwrap();
+/// assert_eq!(token, "123456");
+/// ```
+pub trait Prompt {
+    /// Asks the user for an MFA token.
+    fn prompt(&self) -> Result<String>;
+}
+
+impl<F: Fn() -> Result<String>> Prompt for F {
+    fn prompt(&self) -> Result<String> {
+        self()
+    }
+}
+
+/// Prompts on stderr, keeping stdout clean for output, and reads the token from stdin.
+///
+/// Input is validated locally and re-prompted up to [`MAX_ATTEMPTS`] times.
+#[derive(Clone, Copy, Default)]
+pub struct StdinPrompt {
+    /// Give up when no line is entered within this time.
+    pub timeout: Option<Duration>,
+    /// Number of digits a code must have.
+    pub length: TokenLength,
+}
+
+impl Prompt for StdinPrompt {
+    fn prompt(&self) -> Result<String> {
+        let timeout = self.timeout;
+        let read_line = || match timeout {
+            Some(timeout) => match stdin_lines().lock() {
+                Ok(lines) => match lines.recv_timeout(timeout) {
+                    Ok(line) => Ok(Some(line)),
+                    Err(RecvTimeoutError::Timeout) => Err(Failure::Token
+                        .wrap(anyhow!("No MFA code entered within {}s", timeout.as_secs()))),
+                    Err(RecvTimeoutError::Disconnected) => Ok(None),
+                },
+                Err(_) => Ok(None),
+            },
+            None => {
+                let mut input = String::new();
+                let n = stdin().read_line(&mut input).failure(Failure::Token)?;
+                Ok((n > 0).then_some(input))
+            }
+        };
+
+        read_token(read_line, self.length, &mut stderr())
+    }
+}
+
+/// Number of attempts the interactive prompt gives the user.
+pub const MAX_ATTEMPTS: usize = 3;
+
+/// Accepted numbers of digits in an MFA code. AWS devices emit 6 today; longer codes are let
+/// through for STS to judge rather than rejected locally.
+pub const TOKEN_LENGTHS: RangeInclusive<usize> = 6..=8;
+
+/// Number of digits MFA codes are expected to have.
+///
+/// ```
+/// use aws_mfa::token::TokenLength;
+///
+/// assert_eq!("6".parse(), Ok(TokenLength::Exact(6)));
+/// assert_eq!("7".parse(), Ok(TokenLength::Exact(7)));
+/// assert_eq!("8".parse(), Ok(TokenLength::Exact(8)));
+/// assert_eq!("auto".parse(), Ok(TokenLength::Auto));
+/// for invalid in ["5", "9", "0", "", "six"] {
+///     let error = invalid.parse::<TokenLength>().unwrap_err();
+///     assert_eq!(error, "expected 6, 7, 8 or auto");
+/// }
+///
+/// assert_eq!(TokenLength::default().check("123456"), Ok(()));
+/// assert_eq!(TokenLength::Exact(8).check("12345678"), Ok(()));
+/// assert_eq!(TokenLength::Exact(8).check("123456"), Err("expected 8 digits, got 6".into()));
+/// assert_eq!(TokenLength::Auto.check("1234567"), Ok(()));
+/// assert_eq!(TokenLength::Auto.check("123456789"), Err("expected 6 to 8 digits, got 9".into()));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenLength {
+    /// Exactly this many digits, one of [`TOKEN_LENGTHS`].
+    Exact(usize),
+    /// Any number of digits in [`TOKEN_LENGTHS`].
+    Auto,
+}
+
+impl Default for TokenLength {
+    fn default() -> Self {
+        Self::Exact(6)
+    }
+}
+
+impl TokenLength {
+    /// Returns whether codes of `digits` digits are accepted.
+    pub fn accepts(self, digits: usize) -> bool {
+        match self {
+            Self::Exact(length) => digits == length,
+            Self::Auto => TOKEN_LENGTHS.contains(&digits),
+        }
+    }
+
+    /// Checks that `code` has as many digits as expected, describing the problem otherwise.
+    pub fn check(self, code: &str) -> Result<(), String> {
+        match code.len() {
+            0 => Err(format!("expected {self} digits, got none")),
+            _ if !code.chars().all(|c| c.is_ascii_digit()) => {
+                Err(format!("expected {self} digits, got other characters"))
+            }
+            n if self.accepts(n) => Ok(()),
+            n => Err(format!("expected {self} digits, got {n}")),
+        }
+    }
+}
+
+impl Display for TokenLength {
+    /// Writes the expected number of digits, e.g. `6` or `6 to 8`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(length) => write!(f, "{length}"),
+            Self::Auto => write!(f, "{} to {}", TOKEN_LENGTHS.start(), TOKEN_LENGTHS.end()),
+        }
+    }
+}
+
+impl FromStr for TokenLength {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        match s.parse() {
+            Ok(length) if TOKEN_LENGTHS.contains(&length) => Ok(Self::Exact(length)),
+            _ => Err("expected 6, 7, 8 or auto".to_string()),
+        }
+    }
+}
+
+/// A source of MFA codes, named in `--providers` and `aws_mfa_providers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    /// `keyring`: the TOTP secret stored with `aws-mfa enroll`, see [`Sources::with_totp`].
+    Keyring,
+    /// `op`: 1Password, see [`Sources::with_one_password`].
+    OnePassword,
+    /// `bw`: Bitwarden, see [`Sources::with_bitwarden`].
+    Bitwarden,
+    /// `pass`: the standard Unix password store, see [`Sources::with_pass`].
+    Pass,
+    /// `keepass`: KeePassXC, see [`Sources::with_keepass`].
+    KeePass,
+    /// `yubikey`: an OATH account of a YubiKey, see [`Sources::with_yubikey`].
+    YubiKey,
+    /// `command`: a command printing the code, see [`Sources::with_command`].
+    Command,
+    /// `prompt`: the [`Prompt`], asking the user.
+    Prompt,
+}
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keyring" => Ok(Self::Keyring),
+            "op" => Ok(Self::OnePassword),
+            "bw" => Ok(Self::Bitwarden),
+            "pass" => Ok(Self::Pass),
+            "keepass" => Ok(Self::KeePass),
+            "yubikey" => Ok(Self::YubiKey),
+            "command" => Ok(Self::Command),
+            "prompt" => Ok(Self::Prompt),
+            _ => bail!(
+                "Unknown token provider {s:?}; use keyring, op, bw, pass, keepass, yubikey, \
+                 command or prompt"
+            ),
+        }
+    }
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Keyring => "keyring",
+            Self::OnePassword => "op",
+            Self::Bitwarden => "bw",
+            Self::Pass => "pass",
+            Self::KeePass => "keepass",
+            Self::YubiKey => "yubikey",
+            Self::Command => "command",
+            Self::Prompt => "prompt",
+        })
+    }
+}
+
+/// The providers MFA codes are asked for, in order [default: keyring, op, bw, pass, keepass,
+/// yubikey, command, prompt].
+///
+/// Parsed from a comma-separated list. Each provider may be listed once, and `prompt` only last:
+/// a code entered by hand is final, so nothing after it would ever be asked. Without `prompt`,
+/// nobody is asked, for setups that must never wait for input.
+///
+/// ```
+/// use aws_mfa::token::{Provider, Providers};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let providers: Providers = "op, keyring".parse()?;
+/// assert_eq!(providers.to_string(), "op, keyring");
+/// assert!(providers.contains(Provider::Keyring) && !providers.contains(Provider::Prompt));
+/// assert_eq!(
+///     Providers::default().to_string(),
+///     "keyring, op, bw, pass, keepass, yubikey, command, prompt"
+/// );
+///
+/// for (invalid, expected) in [
+///     ("op, authy", "Unknown token provider \"authy\""),
+///     ("op, op", "op is listed twice"),
+///     ("prompt, op", "prompt must come last"),
+///     (" , ", "No token provider"),
+/// ] {
+///     let error = invalid.parse::<Providers>().unwrap_err().to_string();
+///     assert!(error.starts_with(expected), "{invalid}: {error}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Providers(Vec<Provider>);
+
+impl Default for Providers {
+    fn default() -> Self {
+        Self(vec![
+            Provider::Keyring,
+            Provider::OnePassword,
+            Provider::Bitwarden,
+            Provider::Pass,
+            Provider::KeePass,
+            Provider::YubiKey,
+            Provider::Command,
+            Provider::Prompt,
+        ])
+    }
+}
+
+impl Providers {
+    /// Returns whether `provider` is asked.
+    pub fn contains(&self, provider: Provider) -> bool {
+        self.0.contains(&provider)
+    }
+
+    /// Returns the providers in the order they are asked.
+    pub fn iter(&self) -> impl Iterator<Item = Provider> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl FromStr for Providers {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut providers = Vec::new();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let provider: Provider = name.parse()?;
+            ensure!(!providers.contains(&provider), "{provider} is listed twice");
+            ensure!(
+                !providers.contains(&Provider::Prompt),
+                "prompt must come last, as nothing after it would be asked"
+            );
+            providers.push(provider);
+        }
+        ensure!(!providers.is_empty(), "No token provider given");
+        Ok(Self(providers))
+    }
+}
+
+impl Display for Providers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.0.iter().map(Provider::to_string).collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+/// A password manager or secret that codes are fetched from, a link of the chain of
+/// [`Providers`] short of the prompt.
+trait TokenProvider {
+    /// Name in messages, e.g. `1Password`.
+    fn name(&self) -> &'static str;
+
+    /// Interval at which the codes change.
+    fn period(&self) -> Duration {
+        PERIOD
+    }
+
+    /// Time the codes are computed for.
+    fn time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Fetches the current code.
+    fn code(&self) -> Result<String>;
+}
+
+impl<T: TokenProvider> TokenProvider for &T {
+    fn name(&self) -> &'static str {
+        (*self).name()
+    }
+
+    fn period(&self) -> Duration {
+        (*self).period()
+    }
+
+    fn time(&self) -> SystemTime {
+        (*self).time()
+    }
+
+    fn code(&self) -> Result<String> {
+        (*self).code()
+    }
+}
+
+impl TokenProvider for Totp {
+    fn name(&self) -> &'static str {
+        "the keyring"
+    }
+
+    fn period(&self) -> Duration {
+        Totp::period(self)
+    }
+
+    fn time(&self) -> SystemTime {
+        Totp::time(self)
+    }
+
+    fn code(&self) -> Result<String> {
+        Ok(self.now())
+    }
+}
+
+/// 1Password, with the CLI configured in [`Sources`].
+struct OnePasswordProvider<'a> {
+    source: &'a OnePassword,
+    sources: &'a Sources,
+}
+
+impl TokenProvider for OnePasswordProvider<'_> {
+    fn name(&self) -> &'static str {
+        "1Password"
+    }
+
+    fn code(&self) -> Result<String> {
+        op_token(self.source, self.sources.op())
+    }
+}
+
+impl TokenProvider for Bitwarden {
+    fn name(&self) -> &'static str {
+        "Bitwarden"
+    }
+
+    fn code(&self) -> Result<String> {
+        self.token()
+    }
+}
+
+impl TokenProvider for Pass {
+    fn name(&self) -> &'static str {
+        "pass"
+    }
+
+    fn code(&self) -> Result<String> {
+        self.token()
+    }
+}
+
+impl TokenProvider for KeePass {
+    fn name(&self) -> &'static str {
+        "KeePassXC"
+    }
+
+    fn code(&self) -> Result<String> {
+        self.token()?
+            .with_context(|| format!("entry {} has no TOTP configured", self.entry))
+    }
+}
+
+impl TokenProvider for YubiKey {
+    fn name(&self) -> &'static str {
+        "the YubiKey"
+    }
+
+    fn code(&self) -> Result<String> {
+        self.token()
+    }
+}
+
+/// A command of the user's printing the current code, run with the shell.
+struct TokenCommand(String);
+
+impl TokenProvider for TokenCommand {
+    fn name(&self) -> &'static str {
+        "the token command"
+    }
+
+    fn code(&self) -> Result<String> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        debug!("Running the token command: {}", self.0);
+        // Its messages, e.g. asking to touch a security key, go straight to the terminal.
+        let output = Command::new(shell)
+            .arg(flag)
+            .arg(&self.0)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run {shell}"))?;
+        ensure!(output.status.success(), "{} exited with {}", self.0, output.status);
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let last = stdout.lines().map(str::trim).rfind(|line| !line.is_empty());
+        last.and_then(as_code)
+            .with_context(|| format!("{} printed no MFA code", self.0))
+    }
+}
+
+/// Bitwarden CLI executable.
+const BW: &str = if cfg!(windows) { "bw.exe" } else { "bw" };
+
+/// YubiKey Manager CLI executable.
+const YKMAN: &str = if cfg!(windows) { "ykman.exe" } else { "ykman" };
+
+/// Time a YubiKey is given to be touched by default.
+pub const DEFAULT_YUBIKEY_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Standard Unix password store executable.
+const PASS: &str = "pass";
+
+/// KeePassXC CLI executable.
+const KEEPASSXC_CLI: &str = if cfg!(windows) { "keepassxc-cli.exe" } else { "keepassxc-cli" };
+
+/// Prompts on `out` and reads lines via `read_line` until a code of `length` is entered.
+///
+/// `read_line` returns `None` at the end of input. Empty or malformed lines are re-prompted with
+/// a specific message, up to [`MAX_ATTEMPTS`] times.
+pub fn read_token(
+    mut read_line: impl FnMut() -> Result<Option<String>>,
+    length: TokenLength,
+    out: &mut impl Write,
+) -> Result<String> {
+    for _ in 0..MAX_ATTEMPTS {
+        // A hint only: the user can tell best whether the code shown will make it in time.
+        let left = remaining(SystemTime::now(), PERIOD).as_millis().div_ceil(1000);
+        write!(out, "Enter AWS MFA code for device (next code in {left}s): ")
+            .failure(Failure::Token)?;
+        out.flush().failure(Failure::Token)?;
+
+        let line = read_line().inspect_err(|_| {
+            let _ = writeln!(out);
+        })?;
+        let Some(line) = line else {
+            writeln!(out).failure(Failure::Token)?;
+            return Err(Failure::Token.wrap(anyhow!("No MFA code entered (end of input)")));
+        };
+
+        match parse_token(&line, length) {
+            Ok(token) => return Ok(token),
+            Err(problem) => writeln!(out, "Invalid code: {problem}").failure(Failure::Token)?,
+        }
+    }
+
+    Err(Failure::Token.wrap(anyhow!("No valid MFA code entered after {MAX_ATTEMPTS} attempts")))
+}
+
+/// Extracts the MFA code of `length` from a line of user input.
+///
+/// Surrounding whitespace is stripped, and pasted text around the code (e.g. `Your code is
+/// 123456`) is ignored. Returns a description of the problem if no plausible code is found.
+///
+/// ```
+/// use aws_mfa::token::{TokenLength, parse_token};
+///
+/// let six = TokenLength::default();
+/// assert_eq!(parse_token("Your code is 123456.", six).as_deref(), Ok("123456"));
+/// assert_eq!(parse_token("12345", six), Err("expected 6 digits, got 5".to_string()));
+/// assert_eq!(parse_token("12345678", six), Err("expected 6 digits, got 8".to_string()));
+///
+/// let eight = TokenLength::Exact(8);
+/// assert_eq!(parse_token("1234 5678", eight).as_deref(), Ok("12345678"));
+/// assert_eq!(parse_token("123456", eight), Err("expected 8 digits, got 6".to_string()));
+/// assert_eq!(parse_token("1234567", TokenLength::Auto).as_deref(), Ok("1234567"));
+/// ```
+pub fn parse_token(input: &str, length: TokenLength) -> Result<String, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("no code entered".to_string());
+    }
+
+    // Digits separated only by spaces are one code ("123 456"); otherwise take the longest run.
+    let token: String = if input.chars().all(|c| c.is_ascii_digit() || c.is_whitespace()) {
+        input.chars().filter(char::is_ascii_digit).collect()
+    } else {
+        input
+            .split(|c: char| !c.is_ascii_digit())
+            .max_by_key(|run| run.len())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    length.check(&token).map(|()| token)
+}
+
+/// Returns the receiving end of a background thread reading stdin line by line.
+///
+/// A single reader is shared for the whole process, so that a timed-out prompt doesn't leave a
+/// thread behind that swallows the next line.
+fn stdin_lines() -> &'static Mutex<Receiver<String>> {
+    static LINES: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let (sender, receiver) = channel();
+        spawn(move || {
+            for line in stdin().lines() {
+                if line.is_err() || sender.send(line.unwrap_or_default()).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+/// Retrieves an MFA token using either 1Password automation or the given prompt.
+///
+/// Shorthand for [`Sources::get_token`] with 1Password as the only source, if both `op_account`
+/// and `op_item_name` are given.
+pub fn get_mfa_token(
+    op_account: Option<&str>,
+    op_item_name: Option<&str>,
+    prompt: &impl Prompt,
+) -> Result<String> {
+    Sources::default()
+        .with_one_password(op_account.map(str::to_string), op_item_name.map(str::to_string))
+        .get_token(prompt)
+}
+
+/// Password managers that MFA codes are fetched from before falling back to the prompt.
+pub struct Sources {
+    totp: Option<Totp>,
+    one_password: Option<OnePassword>,
+    op_program: Option<PathBuf>,
+    op_sign_in: bool,
+    bitwarden: Option<Bitwarden>,
+    pass: Option<Pass>,
+    keepass: Option<KeePass>,
+    yubikey: Option<YubiKey>,
+    command: Option<TokenCommand>,
+    given: Option<String>,
+    providers: Providers,
+    token_length: TokenLength,
+    min_validity: Duration,
+    unattended: bool,
+    last_used: LastUsed,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            totp: None,
+            one_password: None,
+            op_program: None,
+            op_sign_in: false,
+            bitwarden: None,
+            pass: None,
+            keepass: None,
+            yubikey: None,
+            command: None,
+            given: None,
+            providers: Providers::default(),
+            token_length: TokenLength::default(),
+            min_validity: DEFAULT_MIN_VALIDITY,
+            unattended: false,
+            last_used: LastUsed::default(),
+        }
+    }
+}
+
+/// Where codes are read from in 1Password.
+enum OnePassword {
+    /// `op item get --account <account> <item> --otp`.
+    Item { account: String, item: String },
+    /// `op read <reference>`.
+    Reference(SecretReference),
+}
+
+impl Sources {
+    /// Fetches codes from the 1Password item `item` of `account`, if both are given and no
+    /// secret reference was set.
+    pub fn with_one_password(mut self, account: Option<String>, item: Option<String>) -> Self {
+        if !matches!(self.one_password, Some(OnePassword::Reference(_))) {
+            self.one_password = account
+                .zip(item)
+                .map(|(account, item)| OnePassword::Item { account, item });
+        }
+        self
+    }
+
+    /// Fetches codes from 1Password with `op read <reference>`, if given, instead of an account
+    /// and item.
+    pub fn with_op_reference(mut self, reference: Option<SecretReference>) -> Self {
+        if let Some(reference) = reference {
+            self.one_password = Some(OnePassword::Reference(reference));
+        }
+        self
+    }
+
+    /// Runs `program`, if given, instead of looking for the 1Password CLI on the `PATH`; see
+    /// [`Op::program`].
+    pub fn with_op_program(mut self, program: Option<PathBuf>) -> Self {
+        self.op_program = program;
+        self
+    }
+
+    /// Signs in to 1Password with the terminal if `op` has no session, instead of falling back;
+    /// see [`Op::with_sign_in`].
+    pub fn with_op_sign_in(mut self, sign_in: bool) -> Self {
+        self.op_sign_in = sign_in;
+        self
+    }
+
+    /// Accepts codes of `length` only, from the password managers and the prompt alike
+    /// [default: 6 digits].
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # fn main() -> anyhow::Result<()> {
+    /// use std::{fs, os::unix::fs::PermissionsExt};
+    ///
+    /// use aws_mfa::{
+    ///     error::Failure,
+    ///     token::{Sources, TokenLength},
+    /// };
+    ///
+    /// // A stub op handing out 8-digit codes.
+    /// let dir = std::env::temp_dir().join(format!("aws-mfa-token-length-{}", std::process::id()));
+    /// fs::create_dir_all(&dir)?;
+    /// let op = dir.join("op");
+    /// fs::write(&op, "#!/bin/sh\necho 87654321\n")?;
+    /// fs::set_permissions(&op, fs::Permissions::from_mode(0o755))?;
+    /// let sources = |length| {
+    ///     Sources::default()
+    ///         .with_one_password(Some("my.1password.com".into()), Some("AWS".into()))
+    ///         .with_op_program(Some(op.clone()))
+    ///         .with_token_length(length)
+    /// };
+    /// let typed = || Ok("123456".to_string());
+    ///
+    /// // Expecting 6 digits, the code of op is refused for the prompt.
+    /// assert_eq!(sources(TokenLength::default()).get_token(&typed)?, "123456");
+    /// assert_eq!(sources(TokenLength::Exact(8)).get_token(&typed)?, "87654321");
+    /// assert_eq!(sources(TokenLength::Auto).get_token(&typed)?, "87654321");
+    ///
+    /// // A typed code of another length fails.
+    /// let error = Sources::default().with_token_length(TokenLength::Exact(8)).get_token(&typed);
+    /// let error = error.unwrap_err();
+    /// assert_eq!(error.to_string(), "Invalid MFA code: expected 8 digits, got 6");
+    /// assert_eq!(Failure::of(&error), Some(Failure::Token));
+    /// # fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(unix))]
+    /// # fn main() {}
+    /// ```
+    pub fn with_token_length(mut self, length: TokenLength) -> Self {
+        self.token_length = length;
+        self
+    }
+
+    /// Generates codes from `totp`, a secret stored with `aws-mfa enroll`, before asking any
+    /// password manager.
+    ///
+    /// ```
+    /// # fn main() -> anyhow::Result<()> {
+    /// use aws_mfa::{token::Sources, totp::Totp};
+    ///
+    /// let totp = Totp::parse("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ")?;
+    /// let sources = Sources::default().with_totp(Some(totp.clone())).with_unattended(true);
+    /// assert!(sources.is_automated());
+    /// let before = totp.now();
+    /// let code = sources.get_token(&|| panic!("no prompt with a TOTP secret"))?;
+    /// // The window may have turned in between.
+    /// assert!(code == before || code == totp.now(), "{code}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_totp(mut self, totp: Option<Totp>) -> Self {
+        self.totp = totp;
+        self
+    }
+
+    /// Replaces a code from the keyring or a password manager that is valid for less than
+    /// `min_validity` by the next one, waiting for it with a countdown on a terminal, so that it
+    /// doesn't expire on the way to STS [default: [`DEFAULT_MIN_VALIDITY`]]. Codes of password
+    /// managers are taken to change every 30 seconds. `Duration::ZERO` takes any code.
+    pub fn with_min_validity(mut self, min_validity: Duration) -> Self {
+        self.min_validity = min_validity;
+        self
+    }
+
+    /// Fetches codes from a Bitwarden item, after 1Password if both are set.
+    pub fn with_bitwarden(mut self, bitwarden: Option<Bitwarden>) -> Self {
+        self.bitwarden = bitwarden;
+        self
+    }
+
+    /// Fetches codes from an entry of the password store, after 1Password and Bitwarden if set.
+    pub fn with_pass(mut self, pass: Option<Pass>) -> Self {
+        self.pass = pass;
+        self
+    }
+
+    /// Fetches codes from a KeePassXC database, after the other password managers if set.
+    pub fn with_keepass(mut self, keepass: KeePass) -> Self {
+        self.keepass = Some(keepass);
+        self
+    }
+
+    /// Fetches codes from `command`, run with the shell, which prints the current code as the
+    /// last line of its output. Failing or printing no code counts as a failure of the provider,
+    /// so the next one is asked.
+    ///
+    /// ```
+    /// use aws_mfa::token::Sources;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let sources = Sources::default().with_command(Some("exit 3".into()));
+    /// assert!(sources.is_automated());
+    /// assert_eq!(sources.get_token(&|| Ok("123456".to_string()))?, "123456");
+    ///
+    /// let sources = Sources::default()
+    ///     .with_command(Some("echo not a code".into()))
+    ///     .with_providers("command".parse()?);
+    /// let error = sources.get_token(&|| panic!("prompt isn't a provider")).unwrap_err();
+    /// assert!(error.to_string().contains("echo not a code printed no MFA code"), "{error}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_command(mut self, command: Option<String>) -> Self {
+        self.command = command.map(TokenCommand);
+        self
+    }
+
+    /// Uses `token`, given up front with `--token` or `AWS_MFA_TOKEN`, instead of asking any
+    /// token provider or the user. A code of the wrong format is an error rather than a reason
+    /// to ask for another one.
+    ///
+    /// ```
+    /// use aws_mfa::token::Sources;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let sources = Sources::default()
+    ///     .with_command(Some("echo 111111".into()))
+    ///     .with_token(Some("246810".into()))
+    ///     .with_unattended(true);
+    /// assert!(sources.is_automated());
+    /// assert_eq!(sources.get_token(&|| panic!("the code was given"))?, "246810");
+    ///
+    /// let sources = Sources::default().with_token(Some("24681".into()));
+    /// let error = sources.get_token(&|| panic!("the code was given")).unwrap_err();
+    /// assert!(error.to_string().contains("Invalid MFA code in --token"), "{error}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.given = token;
+        self
+    }
+
+    /// Fetches codes from an OATH account of a YubiKey, after the password managers if set.
+    pub fn with_yubikey(mut self, yubikey: Option<YubiKey>) -> Self {
+        self.yubikey = yubikey;
+        self
+    }
+
+    /// Never falls back to the prompt, for runs nobody is there to answer it; when no password
+    /// manager provides a code, fails with [`Failure::Unattended`] saying why each one didn't
+    /// [default: false].
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # fn main() -> anyhow::Result<()> {
+    /// use std::{
+    ///     fs,
+    ///     os::unix::fs::PermissionsExt,
+    ///     sync::atomic::{AtomicUsize, Ordering},
+    /// };
+    ///
+    /// use aws_mfa::{
+    ///     error::Failure,
+    ///     token::{KeePass, Sources},
+    /// };
+    ///
+    /// // Stub password managers that both fail.
+    /// let dir = std::env::temp_dir().join(format!("aws-mfa-unattended-{}", std::process::id()));
+    /// fs::create_dir_all(&dir)?;
+    /// let stub = |name: &str, error: &str| -> anyhow::Result<_> {
+    ///     let path = dir.join(name);
+    ///     fs::write(&path, format!("#!/bin/sh\ncat >&2 <<'EOF'\n{error}\nEOF\nexit 1\n"))?;
+    ///     fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    ///     Ok(path)
+    /// };
+    /// let op = stub("op", "[ERROR] \"AWS\" isn't an item.")?;
+    /// let keepass_cli = stub("keepassxc-cli", "Error reading the database: Invalid credentials")?;
+    /// let keepass = KeePass::new(dir.join("vault.kdbx"), "aws")
+    ///     .with_program(&keepass_cli)
+    ///     .with_password_prompt(|| Ok("hunter2".to_string()));
+    ///
+    /// // The prompt stands in for stdin, which must not be read.
+    /// let asked = AtomicUsize::new(0);
+    /// let prompt = || {
+    ///     asked.fetch_add(1, Ordering::Relaxed);
+    ///     Ok("123456".to_string())
+    /// };
+    /// let sources = Sources::default()
+    ///     .with_one_password(Some("my.1password.com".into()), Some("AWS".into()))
+    ///     .with_op_program(Some(op))
+    ///     .with_keepass(keepass)
+    ///     .with_unattended(true);
+    /// let error = sources.get_token(&prompt).unwrap_err();
+    /// assert_eq!(asked.load(Ordering::Relaxed), 0);
+    /// assert_eq!(Failure::of(&error), Some(Failure::Unattended));
+    /// assert_eq!(Failure::Unattended as u8, 8);
+    /// let error = error.to_string();
+    /// assert!(error.contains("1Password: [ERROR] \"AWS\" isn't an item."), "{error}");
+    /// assert!(error.contains("; KeePassXC: "), "{error}");
+    /// assert!(error.contains("Invalid credentials"), "{error}");
+    ///
+    /// // Without any password manager, there is nothing to try.
+    /// let error = Sources::default().with_unattended(true).get_token(&prompt).unwrap_err();
+    /// assert!(error.to_string().contains("no password manager is configured"), "{error}");
+    /// assert_eq!(asked.load(Ordering::Relaxed), 0);
+    /// # fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(unix))]
+    /// # fn main() {}
+    /// ```
+    pub fn with_unattended(mut self, unattended: bool) -> Self {
+        self.unattended = unattended;
+        self
+    }
+
+    /// Records the codes handed out in `last_used` rather than the user's cache directory.
+    pub fn with_last_used(mut self, last_used: LastUsed) -> Self {
+        self.last_used = last_used;
+        self
+    }
+
+    /// Asks `providers` only, in their order, rather than the keyring, 1Password, KeePassXC and
+    /// the prompt [default: [`Providers::default`]]. A provider listed but not configured, e.g.
+    /// `keepass` without a database, is skipped.
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # fn main() -> anyhow::Result<()> {
+    /// use std::{fs, os::unix::fs::PermissionsExt, time::Duration};
+    ///
+    /// use aws_mfa::{
+    ///     error::Failure,
+    ///     token::{KeePass, Sources},
+    /// };
+    ///
+    /// // Stub password managers with codes of their own; op hands out another one every second,
+    /// // so that it is never one just used.
+    /// let dir = std::env::temp_dir().join(format!("aws-mfa-providers-{}", std::process::id()));
+    /// fs::create_dir_all(&dir)?;
+    /// let stub = |name: &str, script: &str| -> anyhow::Result<_> {
+    ///     let path = dir.join(name);
+    ///     fs::write(&path, format!("#!/bin/sh\n{script}\n"))?;
+    ///     fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    ///     Ok(path)
+    /// };
+    /// let op = stub("op", "echo 1$(date +%M%S)0")?;
+    /// let keepass_cli = stub("keepassxc-cli", "cat >/dev/null; echo 22$(date +%M%S)")?;
+    /// let failing = stub("failing", "echo 'not signed in' >&2; exit 1")?;
+    /// let sources = |providers: &str, op: &std::path::Path| -> anyhow::Result<Sources> {
+    ///     let keepass = KeePass::new(dir.join("vault.kdbx"), "aws")
+    ///         .with_program(&keepass_cli)
+    ///         .with_password_prompt(|| Ok("hunter2".to_string()));
+    ///     Ok(Sources::default()
+    ///         .with_one_password(Some("my.1password.com".into()), Some("AWS".into()))
+    ///         .with_op_program(Some(op.to_path_buf()))
+    ///         .with_keepass(keepass)
+    ///         .with_min_validity(Duration::ZERO)
+    ///         .with_providers(providers.parse()?))
+    /// };
+    /// let typed = || Ok("123456".to_string());
+    ///
+    /// // The first provider that has a code wins.
+    /// assert!(sources("keepass, op", &op)?.get_token(&typed)?.starts_with("22"));
+    /// assert!(sources("op, keepass", &op)?.get_token(&typed)?.starts_with('1'));
+    /// assert_eq!(sources("keyring, prompt", &op)?.get_token(&typed)?, "123456");
+    ///
+    /// // Without the prompt, nobody is asked, and the error says what was tried.
+    /// let error = sources("keyring, op", &failing)?.get_token(&typed).unwrap_err();
+    /// assert_eq!(Failure::of(&error), Some(Failure::Token));
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "No token provider had an MFA code; tried 1Password: not signed in, \
+    ///      and skipped keyring, which isn't configured"
+    /// );
+    /// # fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(unix))]
+    /// # fn main() {}
+    /// ```
+    pub fn with_providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// Returns whether codes are fetched without asking the user for them.
+    pub fn is_automated(&self) -> bool {
+        self.given.is_some()
+            || self
+                .providers
+                .iter()
+                .any(|provider| self.provider(provider).is_some())
+    }
+
+    /// Returns the name and program of each configured password manager.
+    pub fn programs(&self) -> Vec<(&'static str, PathBuf)> {
+        let asked = |provider| self.providers.contains(provider);
+        let one_password = self.one_password.as_ref().filter(|_| asked(Provider::OnePassword));
+        let one_password = one_password.map(|_| {
+            let program = self.op().program().unwrap_or_else(|_| op::PROGRAM.into());
+            ("1Password CLI", program)
+        });
+        let bitwarden = self.bitwarden.as_ref().filter(|_| asked(Provider::Bitwarden));
+        let bitwarden = bitwarden.map(|b| ("Bitwarden CLI", b.program.clone()));
+        let pass = self.pass.as_ref().filter(|_| asked(Provider::Pass));
+        let pass = pass.map(|p| ("pass", p.program.clone()));
+        let keepass = self.keepass.as_ref().filter(|_| asked(Provider::KeePass));
+        let keepass = keepass.map(|k| ("KeePassXC CLI", k.program.clone()));
+        let yubikey = self.yubikey.as_ref().filter(|_| asked(Provider::YubiKey));
+        let yubikey = yubikey.map(|y| ("YubiKey Manager CLI", y.program.clone()));
+        let managers = one_password.into_iter().chain(bitwarden).chain(pass);
+        managers.chain(keepass).chain(yubikey).collect()
+    }
+
+    /// Returns `provider` if it is configured, short of the prompt.
+    fn provider(&self, provider: Provider) -> Option<Box<dyn TokenProvider + '_>> {
+        match provider {
+            Provider::Keyring => self.totp.as_ref().map(|t| Box::new(t) as Box<dyn TokenProvider>),
+            Provider::OnePassword => self.one_password.as_ref().map(|source| {
+                Box::new(OnePasswordProvider { source, sources: self }) as Box<dyn TokenProvider>
+            }),
+            Provider::Bitwarden => {
+                self.bitwarden.as_ref().map(|b| Box::new(b) as Box<dyn TokenProvider>)
+            }
+            Provider::Pass => self.pass.as_ref().map(|p| Box::new(p) as Box<dyn TokenProvider>),
+            Provider::KeePass => {
+                self.keepass.as_ref().map(|k| Box::new(k) as Box<dyn TokenProvider>)
+            }
+            Provider::YubiKey => {
+                self.yubikey.as_ref().map(|y| Box::new(y) as Box<dyn TokenProvider>)
+            }
+            Provider::Command => {
+                self.command.as_ref().map(|c| Box::new(c) as Box<dyn TokenProvider>)
+            }
+            Provider::Prompt => None,
+        }
+    }
+
+    /// Returns the 1Password CLI to fetch codes with.
+    fn op(&self) -> Op {
+        let op = Op::new().with_sign_in(self.op_sign_in);
+        match &self.op_program {
+            Some(program) => op.with_program(program),
+            None => op,
+        }
+    }
+
+    /// Retrieves an MFA token from the [TOTP secret](Self::with_totp), the configured password
+    /// managers, or from `prompt`.
+    ///
+    /// Falls back to the next source, and eventually the prompt unless
+    /// [unattended](Self::with_unattended), if one fails. A code identical to the last one
+    /// submitted within the same TOTP window is rejected by AWS, and one about to expire may be by
+    /// the time STS checks it, so in these cases the next window is awaited and the password
+    /// manager asked again; manually entered codes only get a warning or a hint. A code of another
+    /// length than set with [`Self::with_token_length`] counts as a failure of its password
+    /// manager, and fails the prompt with [`Failure::Token`].
+    pub fn get_token(&self, prompt: &impl Prompt) -> Result<String> {
+        let expected = |otp: String| match self.token_length.check(&otp) {
+            Ok(()) => Ok(otp),
+            Err(problem) => Err(anyhow!("Unexpected MFA code: {problem}; see --token-length")),
+        };
+        if let Some(token) = &self.given {
+            if let Err(problem) = self.token_length.check(token) {
+                let error = anyhow!("Invalid MFA code in --token or AWS_MFA_TOKEN: {problem}");
+                return Err(Failure::Token.wrap(error));
+            }
+            if self.last_used.is_reused(token, SystemTime::now()) {
+                warn!(
+                    "This code was already used in the current 30-second window; AWS may reject it"
+                );
+            }
+            debug!("Using the MFA code given with --token");
+            self.last_used.remember(token, SystemTime::now());
+            return Ok(token.clone());
+        }
+        debug!("Asking the token providers {} in turn", self.providers);
+
+        // Why each provider tried didn't provide a code, for the error when nothing is left to
+        // try.
+        let mut failures = Vec::new();
+        let manual = self.providers.contains(Provider::Prompt) && !self.unattended;
+        let (mut chain, mut skipped) = (Vec::new(), Vec::new());
+        for provider in self.providers.iter().filter(|&p| p != Provider::Prompt) {
+            match self.provider(provider) {
+                Some(source) => chain.push((provider, source)),
+                None => skipped.push(provider.to_string()),
+            }
+        }
+
+        for (i, (provider, source)) in chain.iter().enumerate() {
+            let name = source.name();
+            let next = match chain.get(i + 1) {
+                Some((_, next)) => Some(format!("trying {}", next.name())),
+                None => manual.then(|| "falling back to manual input".to_string()),
+            };
+            let fetch = || match source.code().and_then(expected) {
+                Ok(otp) => Some(otp),
+                Err(e) => {
+                    if let Some(next) = &next {
+                        warn!("Failed to get token from {name}: {}; {next}", Report(&e));
+                    }
+                    failures.push(format!("{name}: {}", Report(&e)));
+                    None
+                }
+            };
+            if let Some(otp) = self.fresh_token(name, source.period(), || source.time(), fetch) {
+                debug!("Token provider {provider} had the MFA code");
+                return Ok(otp);
+            }
+        }
+
+        if self.unattended {
+            let error = match failures.is_empty() {
+                true => anyhow!(
+                    "Can't ask for the MFA code in a non-interactive run, and no password manager \
+                     is configured; set up a password manager or --token-command, store the \
+                     TOTP secret with `aws-mfa enroll`, or run with --interactive"
+                ),
+                false => {
+                    anyhow!(
+                        "Can't ask for the MFA code in a non-interactive run; tried {}",
+                        failures.join("; ")
+                    )
+                }
+            };
+            return Err(Failure::Unattended.wrap(error));
+        }
+        if !manual {
+            let mut error = match failures.is_empty() {
+                true => format!(
+                    "No token provider had an MFA code, as none of {} is configured",
+                    self.providers
+                ),
+                false => {
+                    format!("No token provider had an MFA code; tried {}", failures.join("; "))
+                }
+            };
+            if !failures.is_empty() && !skipped.is_empty() {
+                let verb = if skipped.len() == 1 { "isn't" } else { "aren't" };
+                error += &format!(", and skipped {}, which {verb} configured", skipped.join(", "));
+            }
+            return Err(Failure::Token.wrap(anyhow!(error)));
+        }
+
+        let token = prompt.prompt()?;
+        if let Err(problem) = self.token_length.check(&token) {
+            return Err(Failure::Token.wrap(anyhow!("Invalid MFA code: {problem}")));
+        }
+        if self.last_used.is_reused(&token, SystemTime::now()) {
+            warn!("This code was already used in the current 30-second window; AWS may reject it");
+        }
+        self.last_used.remember(&token, SystemTime::now());
+        Ok(token)
+    }
+
+    /// Gets a code changing every `period` from `name` with `fetch`, waiting for the next TOTP
+    /// window, as told by `time`, if the code was just used or is about to expire.
+    fn fresh_token(
+        &self,
+        name: &str,
+        period: Duration,
+        time: impl Fn() -> SystemTime,
+        mut fetch: impl FnMut() -> Option<String>,
+    ) -> Option<String> {
+        let mut otp = fetch();
+        if let Some(code) = &otp {
+            let wait = wait_for_validity(time(), period, self.min_validity);
+            let reused = self.last_used.is_reused(code, SystemTime::now());
+            if reused {
+                warn!("The code from {name} was just used; waiting for the next one");
+            } else if !wait.is_zero() {
+                info!(
+                    "The code from {name} expires in {:.1}s; waiting for the next one",
+                    wait.as_secs_f64()
+                );
+            }
+            if reused || !wait.is_zero() {
+                wait_for_next_window(period, time, !self.unattended && stderr().is_terminal());
+                otp = fetch();
+            }
+        }
+
+        let otp = otp?;
+        info!("Retrieved MFA token from {name}");
+        self.last_used.remember(&otp, SystemTime::now());
+        Some(otp)
+    }
+}
+
+/// Fetches the current code from 1Password, signing in first if allowed to and needed, and
+/// showing progress since unlocking can take a while.
+///
+/// The progress line is finished before returning, so it never runs into the manual prompt.
+fn op_token(one_password: &OnePassword, op: Op) -> Result<String> {
+    let message = "Fetching code from 1Password…";
+    let output = match one_password {
+        OnePassword::Item { account, item } => op
+            .with_account(Some(account.clone()))
+            .run(&["item", "get", item, "--otp"], message)?,
+        OnePassword::Reference(reference) => op.run(&["read", reference.as_str()], message)?,
+    };
+
+    as_code(&output).context("op printed no MFA code")
+}
+
+/// A 1Password secret reference to a one-time password, `op://<vault>/<item>[/<section>]/<field>`.
+///
+/// `op read` returns the TOTP secret of a one-time password field unless asked for the current
+/// code with `?attribute=otp`, which is therefore added when missing.
+///
+/// ```
+/// use aws_mfa::token::SecretReference;
+///
+/// let parse = |s: &str| s.parse::<SecretReference>().map(|r| r.to_string());
+/// assert_eq!(
+///     parse("op://Private/AWS/one-time password")?,
+///     "op://Private/AWS/one-time password?attribute=otp"
+/// );
+/// let explicit = "op://Work/AWS/MFA/totp?attribute=OTP";
+/// assert_eq!(parse(explicit)?, explicit);
+/// assert_eq!(
+///     parse("op://Work/AWS/totp?ssh-format=openssh")?,
+///     "op://Work/AWS/totp?ssh-format=openssh&attribute=otp"
+/// );
+/// for malformed in [
+///     "Private/AWS/one-time password",
+///     "op://Private/AWS",
+///     "op://Private//totp",
+///     "op://a/b/c/d/e",
+///     "op://Private/AWS/totp?attribute=type",
+///     "op://Private/AWS/totp\n",
+/// ] {
+///     assert!(parse(malformed).is_err(), "{malformed}");
+/// }
+/// # anyhow::Ok(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct SecretReference(String);
+
+impl SecretReference {
+    /// Returns the reference as passed to `op read`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for SecretReference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expected = "expected op://<vault>/<item>[/<section>]/<field>";
+        let Some(path) = s.strip_prefix("op://") else {
+            bail!("Invalid secret reference {s:?}; {expected}");
+        };
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path, None),
+        };
+        let segments: Vec<_> = path.split('/').collect();
+        ensure!(
+            (3..=4).contains(&segments.len())
+                && segments.iter().all(|s| !s.trim().is_empty())
+                && !s.chars().any(char::is_control),
+            "Invalid secret reference {s:?}; {expected}"
+        );
+
+        let attribute = query
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .find_map(|param| param.strip_prefix("attribute="));
+        Ok(Self(match (attribute, query) {
+            (Some(attribute), _) => {
+                ensure!(
+                    attribute.eq_ignore_ascii_case("otp"),
+                    "Secret reference {s:?} reads the {attribute} attribute; MFA codes need \
+                     ?attribute=otp"
+                );
+                s.to_string()
+            }
+            (None, Some(_)) => format!("{s}&attribute=otp"),
+            (None, None) => format!("{s}?attribute=otp"),
+        }))
+    }
+}
+
+impl Display for SecretReference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Returns `output` if it is a code of plausible length.
+fn as_code(output: &str) -> Option<String> {
+    (TOKEN_LENGTHS.contains(&output.len()) && output.chars().all(|c| c.is_ascii_digit()))
+        .then(|| output.to_string())
+}
+
+/// An item of a Bitwarden vault whose TOTP is read with `bw get totp`.
+///
+/// The vault has to be unlocked already: `bw` runs with `--nointeraction` and takes the session
+/// from `BW_SESSION`, as printed by `bw unlock --raw`. Nothing is asked for, so that a locked vault
+/// falls back to the next provider instead of waiting for the master password.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{fs::write, os::unix::fs::PermissionsExt};
+///
+/// use aws_mfa::token::{Bitwarden, Sources};
+///
+/// // A stub bw with an item "aws", whose vault is locked for the item "locked".
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-bw-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+/// let stub = dir.join("bw");
+/// write(
+///     &stub,
+///     r#"#!/bin/sh
+/// if [ "$1 $2 $4" != "get totp --nointeraction" ]; then
+///     echo "Invalid command." >&2; exit 1
+/// fi
+/// case "$3" in
+///     aws) echo 123456;;
+///     locked) echo "Vault is locked." >&2; exit 1;;
+///     *) echo "Not found." >&2; exit 1;;
+/// esac
+/// "#,
+/// )?;
+/// std::fs::set_permissions(&stub, PermissionsExt::from_mode(0o755))?;
+/// let bitwarden = |item: &str| Bitwarden::new(item).with_program(&stub);
+///
+/// assert_eq!(bitwarden("aws").token()?, "123456");
+/// assert_eq!(bitwarden("github").token().unwrap_err().to_string(), "Not found.");
+/// let locked = bitwarden("locked").token().unwrap_err().to_string();
+/// assert!(locked.contains("export BW_SESSION=$(bw unlock --raw)"), "{locked}");
+///
+/// // A locked vault falls back to the prompt.
+/// let sources = Sources::default().with_bitwarden(Some(bitwarden("locked")));
+/// assert_eq!(sources.get_token(&|| Ok("654321".to_string()))?, "654321");
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub struct Bitwarden {
+    item: String,
+    program: PathBuf,
+}
+
+impl Bitwarden {
+    /// Reads the TOTP of `item`, a name or ID as `bw get` takes it.
+    pub fn new(item: impl Into<String>) -> Self {
+        Self { item: item.into(), program: BW.into() }
+    }
+
+    /// Runs `program` instead of `bw` from the `PATH`.
+    pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Returns the current code of the item.
+    pub fn token(&self) -> Result<String> {
+        let progress = Progress::start("Fetching code from Bitwarden…");
+        let result = self.run();
+        progress.finish(result.is_ok());
+        result
+    }
+
+    /// Runs `bw get totp`.
+    fn run(&self) -> Result<String> {
+        let args = ["get", "totp", &self.item, "--nointeraction"];
+        match printed_code("bw", &self.program, &args) {
+            Err(e)
+                if e.to_string().contains("locked") || e.to_string().contains("not logged in") =>
+            {
+                bail!("{e} Run `export BW_SESSION=$(bw unlock --raw)` and try again")
+            }
+            result => result,
+        }
+    }
+}
+
+/// An entry of the standard Unix password store whose one-time password is read with
+/// `pass otp`, from the pass-otp extension.
+///
+/// GnuPG asks for the passphrase of the store as usual, through its pinentry, unless its agent
+/// has it cached already.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{fs::write, os::unix::fs::PermissionsExt};
+///
+/// use aws_mfa::token::{Pass, Sources};
+///
+/// // A stub pass with an entry "aws/work", and "aws/legacy" without an otpauth URI.
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-pass-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+/// let stub = dir.join("pass");
+/// write(
+///     &stub,
+///     r#"#!/bin/sh
+/// [ "$1" = otp ] || { echo "Error: unknown command $1" >&2; exit 1; }
+/// case "$2" in
+///     aws/work) echo 123456;;
+///     aws/legacy) echo "Error: none or multiple otpauth:// URIs found in $2" >&2; exit 1;;
+///     *) echo "Error: $2 is not in the password store." >&2; exit 1;;
+/// esac
+/// "#,
+/// )?;
+/// std::fs::set_permissions(&stub, PermissionsExt::from_mode(0o755))?;
+/// let pass = |entry: &str| Pass::new(entry).with_program(&stub);
+///
+/// assert_eq!(pass("aws/work").token()?, "123456");
+/// let missing = pass("aws/home").token().unwrap_err();
+/// assert_eq!(missing.to_string(), "Error: aws/home is not in the password store.");
+///
+/// // Without a one-time password in the entry, the code is entered by hand.
+/// let sources = Sources::default().with_pass(Some(pass("aws/legacy")));
+/// assert_eq!(sources.get_token(&|| Ok("654321".to_string()))?, "654321");
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub struct Pass {
+    entry: String,
+    program: PathBuf,
+}
+
+impl Pass {
+    /// Reads the one-time password of `entry`, e.g. `aws/work`.
+    pub fn new(entry: impl Into<String>) -> Self {
+        Self { entry: entry.into(), program: PASS.into() }
+    }
+
+    /// Runs `program` instead of `pass` from the `PATH`.
+    pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Returns the current code of the entry.
+    pub fn token(&self) -> Result<String> {
+        let progress = Progress::start("Fetching code from pass…");
+        let result = printed_code("pass", &self.program, &["otp", &self.entry]);
+        progress.finish(result.is_ok());
+        result
+    }
+}
+
+/// An OATH account of a YubiKey whose code is read with `ykman oath accounts code`.
+///
+/// For an account that requires touch, `ykman` waits for the key to be touched; its request is
+/// passed on to the user, and `ykman` is stopped if nobody touches the key in time.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{fs::write, os::unix::fs::PermissionsExt, time::Duration};
+///
+/// use aws_mfa::token::{Sources, YubiKey};
+///
+/// // A stub ykman with the account "aws", and "aws-touch", which waits for a touch.
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-ykman-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+/// let stub = dir.join("ykman");
+/// write(
+///     &stub,
+///     r#"#!/bin/sh
+/// [ "$1 $2 $3 $4" = "oath accounts code --single" ] || { echo "Usage: ykman" >&2; exit 2; }
+/// case "$5" in
+///     aws) echo 123456;;
+///     aws-touch) echo "Touch your YubiKey..." >&2; exec sleep 10;;
+///     *) echo "ERROR: No matching account found." >&2; exit 1;;
+/// esac
+/// "#,
+/// )?;
+/// std::fs::set_permissions(&stub, PermissionsExt::from_mode(0o755))?;
+/// let yubikey = |account: &str| {
+///     YubiKey::new(account).with_program(&stub).with_timeout(Duration::from_millis(500))
+/// };
+///
+/// assert_eq!(yubikey("aws").token()?, "123456");
+/// let missing = yubikey("github").token().unwrap_err();
+/// assert_eq!(missing.to_string(), "ERROR: No matching account found.");
+/// let untouched = yubikey("aws-touch").token().unwrap_err();
+/// assert_eq!(untouched.to_string(), "The YubiKey wasn't touched within 0.5s");
+///
+/// // Without a touch, the code is entered by hand.
+/// let sources = Sources::default().with_yubikey(Some(yubikey("aws-touch")));
+/// assert_eq!(sources.get_token(&|| Ok("654321".to_string()))?, "654321");
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub struct YubiKey {
+    account: String,
+    program: PathBuf,
+    timeout: Duration,
+}
+
+impl YubiKey {
+    /// Reads the code of the OATH account `account`, or the only one matching it, as `ykman`
+    /// takes it.
+    pub fn new(account: impl Into<String>) -> Self {
+        Self {
+            account: account.into(),
+            program: YKMAN.into(),
+            timeout: DEFAULT_YUBIKEY_TIMEOUT,
+        }
+    }
+
+    /// Runs `program` instead of `ykman` from the `PATH`.
+    pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Stops `ykman` after `timeout` [default: [`DEFAULT_YUBIKEY_TIMEOUT`]].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the current code of the account.
+    pub fn token(&self) -> Result<String> {
+        let progress = Progress::start("Fetching code from the YubiKey…");
+        let result = self.run();
+        progress.finish(result.is_ok());
+        result
+    }
+
+    /// Runs `ykman oath accounts code`, passing on its request to touch the key.
+    fn run(&self) -> Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(["oath", "accounts", "code", "--single", &self.account])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program.display()))?;
+
+        let touch = Arc::new(AtomicBool::new(false));
+        let stderr = child.stderr.take().map(|stderr| {
+            let touch = touch.clone();
+            spawn(move || {
+                let mut lines = Vec::new();
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    if line.to_lowercase().contains("touch") {
+                        touch.store(true, Ordering::Relaxed);
+                        info!("Touch your YubiKey to get the MFA code");
+                    }
+                    lines.push(line);
+                }
+                lines
+            })
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait().context("ykman did not finish")? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                let waited = self.timeout.as_secs_f64();
+                match touch.load(Ordering::Relaxed) {
+                    true => bail!("The YubiKey wasn't touched within {waited}s"),
+                    false => bail!("ykman didn't finish within {waited}s"),
+                }
+            }
+            sleep(Duration::from_millis(50));
+        };
+
+        let stderr = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        if !status.success() {
+            match stderr.iter().map(|line| line.trim()).find(|line| !line.is_empty()) {
+                Some(message) => bail!("{message}"),
+                None => bail!("ykman exited with {status}"),
+            }
+        }
+
+        let mut stdout = String::new();
+        if let Some(mut out) = child.stdout.take() {
+            out.read_to_string(&mut stdout).context("Failed to read ykman")?;
+        }
+        let last = stdout.lines().map(str::trim).rfind(|line| !line.is_empty());
+        last.and_then(as_code).context("ykman printed no MFA code")
+    }
+}
+
+/// Runs `program`, the CLI of the password manager `name`, with `args` and returns the code it
+/// prints last. If it fails, the first line it printed on stderr is the error.
+fn printed_code(name: &str, program: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run {}", program.display()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+            Some(message) => bail!("{message}"),
+            None => bail!("{name} exited with {}", output.status),
+        }
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last = stdout.lines().map(str::trim).rfind(|line| !line.is_empty());
+    last.and_then(as_code)
+        .with_context(|| format!("{name} printed no MFA code"))
+}
+
+/// An entry of a KeePassXC database whose TOTP is read with `keepassxc-cli show --totp`.
+///
+/// The database password is asked for once, on first use, and handed to `keepassxc-cli` on its
+/// stdin, never on the command line. It is asked for again only if the database rejects it.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # fn main() -> anyhow::Result<()> {
+/// use std::{
+///     fs::write,
+///     os::unix::fs::PermissionsExt,
+///     sync::{
+///         Arc,
+///         atomic::{AtomicUsize, Ordering},
+///     },
+/// };
+///
+/// use aws_mfa::token::{KeePass, Sources};
+///
+/// // A stub keepassxc-cli that only knows the password "hunter2" and the entry "aws".
+/// let dir = std::env::temp_dir().join(format!("aws-mfa-keepass-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+/// let stub = dir.join("keepassxc-cli");
+/// write(
+///     &stub,
+///     r#"#!/bin/sh
+/// case "$*" in
+///     *hunter2*) echo "Password on the command line" >&2; exit 2;;
+///     *"--key-file "*" --no-password "*) ;;
+///     *) read -r password
+///        [ "$password" = hunter2 ] || { echo "Error while reading the database: Invalid credentials were provided, please try again." >&2; exit 1; };;
+/// esac
+/// for last; do :; done
+/// [ "$last" = aws ] || { echo "Entry with path $last has no TOTP set up." >&2; exit 1; }
+/// echo 123456
+/// "#,
+/// )?;
+/// std::fs::set_permissions(&stub, PermissionsExt::from_mode(0o755))?;
+///
+/// let asked = Arc::new(AtomicUsize::new(0));
+/// let keepass = |entry: &str, password: &'static str| {
+///     let asked = asked.clone();
+///     KeePass::new(dir.join("vault.kdbx"), entry).with_program(&stub).with_password_prompt(
+///         move || {
+///             asked.fetch_add(1, Ordering::Relaxed);
+///             Ok(password.to_string())
+///         },
+///     )
+/// };
+///
+/// // The password is asked for once.
+/// let aws = keepass("aws", "hunter2");
+/// assert_eq!(aws.token()?.as_deref(), Some("123456"));
+/// assert_eq!(aws.token()?.as_deref(), Some("123456"));
+/// assert_eq!(asked.load(Ordering::Relaxed), 1);
+///
+/// assert_eq!(keepass("github", "hunter2").token()?, None);
+/// let wrong = keepass("aws", "letmein").token().unwrap_err();
+/// assert!(wrong.to_string().contains("Invalid credentials"));
+///
+/// // A database protected by a key file alone.
+/// let key_file = keepass("aws", "").with_key_file(Some(dir.join("vault.keyx")));
+/// assert_eq!(key_file.token()?.as_deref(), Some("123456"));
+///
+/// // Without a TOTP on the entry, the code is entered by hand.
+/// let sources = Sources::default().with_keepass(keepass("github", "hunter2"));
+/// assert_eq!(sources.get_token(&|| Ok("654321".to_string()))?, "654321");
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub struct KeePass {
+    database: PathBuf,
+    entry: String,
+    key_file: Option<PathBuf>,
+    program: PathBuf,
+    ask_password: Box<dyn Fn() -> Result<String> + Send + Sync>,
+    password: Mutex<Option<Secret>>,
+}
+
+impl KeePass {
+    /// Reads the TOTP of `entry` in the database at `database`, asking for the password on the
+    /// terminal.
+    pub fn new(database: impl Into<PathBuf>, entry: impl Into<String>) -> Self {
+        let database = database.into();
+        let question = format!("Password of {} (empty for a key file alone): ", database.display());
+        Self {
+            database,
+            entry: entry.into(),
+            key_file: None,
+            program: KEEPASSXC_CLI.into(),
+            ask_password: Box::new(move || {
+                rpassword::prompt_password(&question).context("Failed to read the password")
+            }),
+            password: Mutex::new(None),
+        }
+    }
+
+    /// Unlocks the database with `key_file`, in addition to the password unless that is left
+    /// empty.
+    pub fn with_key_file(mut self, key_file: Option<PathBuf>) -> Self {
+        self.key_file = key_file;
+        self
+    }
+
+    /// Runs `program` instead of `keepassxc-cli` from the `PATH`.
+    pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Gets the database password from `ask` instead of the terminal.
+    pub fn with_password_prompt(
+        mut self,
+        ask: impl Fn() -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.ask_password = Box::new(ask);
+        self
+    }
+
+    /// Returns the current code of the entry, or `None` if the entry has no TOTP configured.
+    pub fn token(&self) -> Result<Option<String>> {
+        let password = self.password()?;
+
+        let progress = Progress::start("Fetching code from KeePassXC…");
+        let result = self.run(&password);
+        progress.finish(matches!(result, Ok(Some(_))));
+
+        if result
+            .as_ref()
+            .is_err_and(|e| e.to_string().contains("Invalid credentials"))
+            && let Ok(mut cached) = self.password.lock()
+        {
+            *cached = None;
+        }
+        result
+    }
+
+    /// Returns the database password, asking for it if it hasn't been yet.
+    fn password(&self) -> Result<Secret> {
+        let mut cached = self.password.lock().map_err(|_| anyhow!("Password cache poisoned"))?;
+        if let Some(password) = cached.as_ref() {
+            return Ok(password.clone());
+        }
+        let password = Secret::new((self.ask_password)()?);
+        *cached = Some(password.clone());
+        Ok(password)
+    }
+
+    /// Runs `keepassxc-cli show --totp`, writing `password` to its stdin.
+    fn run(&self, password: &Secret) -> Result<Option<String>> {
+        let mut command = Command::new(&self.program);
+        command.args(["show", "--quiet", "--totp"]);
+        if let Some(key_file) = &self.key_file {
+            command.arg("--key-file").arg(key_file);
+            if password.expose().is_empty() {
+                command.arg("--no-password");
+            }
+        }
+        command.arg(&self.database).arg(&self.entry);
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program.display()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A key-file-only database doesn't read stdin, which may then be closed already.
+            let _ = writeln!(stdin, "{}", password.expose());
+        }
+        let output = child.wait_with_output().context("keepassxc-cli did not finish")?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            if stderr.contains("has no TOTP") {
+                return Ok(None);
+            }
+            match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+                Some(message) => bail!("{message}"),
+                None => bail!("keepassxc-cli exited with {}", output.status),
+            }
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let last = stdout.lines().map(str::trim).rfind(|line| !line.is_empty());
+        match last.and_then(as_code) {
+            Some(otp) => Ok(Some(otp)),
+            None => bail!("keepassxc-cli printed no MFA code"),
+        }
+    }
+}
+
+/// Last code handed out in this process, with its TOTP window.
+static LAST_USED: Mutex<Option<(u64, String)>> = Mutex::new(None);
+
+/// The record of the last code handed out, so that it isn't used twice in its TOTP window, which
+/// AWS rejects.
+///
+/// The record is kept in this process and in a file shared between invocations, readable by the
+/// owner only, in a directory created private to them.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// use aws_mfa::token::LastUsed;
+///
+/// let dir = tempfile::tempdir()?;
+/// let last_used = LastUsed::at(Some(dir.path().join("last-token")));
+/// let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_010);
+/// last_used.remember("135791", start);
+/// assert!(last_used.is_reused("135791", start + Duration::from_secs(5)));
+/// assert!(!last_used.is_reused("135791", start + Duration::from_secs(30)));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct LastUsed {
+    path: Option<PathBuf>,
+}
+
+impl Default for LastUsed {
+    /// Shares the record in the user's cache directory.
+    fn default() -> Self {
+        Self::at(cache_dir().map(|d| d.join("aws-mfa/last-token")))
+    }
+}
+
+impl LastUsed {
+    /// Shares the record in the file `path`, or keeps it in this process only without one.
+    pub fn at(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    /// Returns whether `token` was already handed out in the TOTP window of `now`, by this
+    /// process or a previous invocation.
+    pub fn is_reused(&self, token: &str, now: SystemTime) -> bool {
+        let current = (window(now, PERIOD), token.to_string());
+
+        let in_memory = LAST_USED.lock().is_ok_and(|last| last.as_ref() == Some(&current));
+        let on_disk = self
+            .path
+            .as_ref()
+            .and_then(|path| read_to_string(path).ok())
+            .is_some_and(|content| content.trim() == format!("{} {}", current.0, current.1));
+
+        in_memory || on_disk
+    }
+
+    /// Records `token` as handed out in the TOTP window of `now`.
+    pub fn remember(&self, token: &str, now: SystemTime) {
+        let window = window(now, PERIOD);
+
+        if let Ok(mut last) = LAST_USED.lock() {
+            *last = Some((window, token.to_string()));
+        }
+
+        // The code is of no use once its window is over, but nobody else needs to see it before.
+        let Some(path) = &self.path else { return };
+        let result = path
+            .parent()
+            .map_or(Ok(()), create_private_dir)
+            .and_then(|()| create_private(path))
+            .and_then(|mut file| file.write_all(format!("{window} {token}").as_bytes()));
+        if let Err(e) = result {
+            debug!("Failed to record the last used token in {}: {e}", path.display());
+        }
+    }
+}
+
+/// Blocks until the next TOTP window of `period`, as told by `time`, starts, showing a countdown
+/// on stderr if `countdown` is set.
+fn wait_for_next_window(period: Duration, time: impl Fn() -> SystemTime, countdown: bool) {
+    let deadline = SystemTime::now() + remaining(time(), period);
+    while let Ok(left) = deadline.duration_since(SystemTime::now()) {
+        if countdown {
+            eprint!("\rNext code in {:>2}s", left.as_millis().div_ceil(1000));
+        }
+        sleep(left.min(Duration::from_secs(1)));
+        if left <= Duration::from_secs(1) {
+            break;
+        }
+    }
+    // Give the provider a moment past the boundary so it doesn't hand out the old code again.
+    sleep(Duration::from_millis(500));
+    if countdown {
+        eprintln!();
+    }
+}
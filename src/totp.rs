@@ -0,0 +1,329 @@
+//! TOTP code generation (RFC 6238).
+//!
+//! Codes are generated from secrets given as base32 or `otpauth://totp/…` URIs, as shown next to
+//! the QR code when an MFA device is set up, with the digits, period and algorithm of the URI.
+//! A local clock known to be off can be corrected for with [`Totp::with_skew`].
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::{
+    redact::Secret,
+    totp_window::{PERIOD, remaining},
+};
+
+/// HMAC algorithm codes are computed with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// HMAC-SHA1, the only one AWS MFA devices use.
+    #[default]
+    Sha1,
+    /// HMAC-SHA256.
+    Sha256,
+    /// HMAC-SHA512.
+    Sha512,
+}
+
+impl Algorithm {
+    /// Returns the name used in `otpauth://` URIs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+        }
+    }
+}
+
+/// A TOTP generator: the secret, and how codes are made from it.
+///
+/// The secret is never shown, neither by `Debug` nor in errors.
+///
+/// Codes match the test vectors of RFC 6238:
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+///
+/// use aws_mfa::totp::Totp;
+///
+/// // The ASCII secrets of the RFC, in base32.
+/// let sha1 = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+/// let sha256 = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA";
+/// let sha512 = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+///               GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA";
+/// let totp = |secret: &str, algorithm: &str| {
+///     let uri = format!("otpauth://totp/RFC?secret={secret}&digits=8&algorithm={algorithm}");
+///     Totp::parse(&uri).unwrap()
+/// };
+/// let (sha1, sha256, sha512) =
+///     (totp(sha1, "SHA1"), totp(sha256, "SHA256"), totp(sha512, "SHA512"));
+///
+/// for (secs, codes) in [
+///     (59, ["94287082", "46119246", "90693936"]),
+///     (1_111_111_109, ["07081804", "68084774", "25091201"]),
+///     (1_111_111_111, ["14050471", "67062674", "99943326"]),
+///     (1_234_567_890, ["89005924", "91819424", "93441116"]),
+///     (2_000_000_000, ["69279037", "90698825", "38618901"]),
+///     (20_000_000_000, ["65353130", "77737706", "47863826"]),
+/// ] {
+///     let t = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+///     assert_eq!([sha1.code_at(t), sha256.code_at(t), sha512.code_at(t)], codes, "{secs}");
+/// }
+///
+/// // AWS devices use the defaults: 6 digits of HMAC-SHA1 every 30 seconds.
+/// let aws = Totp::parse("GEZD GNBV GY3T QOJQ GEZD GNBV GY3T QOJQ").unwrap();
+/// let t = SystemTime::UNIX_EPOCH + Duration::from_secs(59);
+/// assert_eq!(aws.code_at(t), "287082");
+/// assert!(!format!("{aws:?}").contains("GEZD"));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Totp {
+    key: Vec<u8>,
+    digits: usize,
+    period: Duration,
+    algorithm: Algorithm,
+    skew: i64,
+}
+
+impl Totp {
+    /// Parses a base32 secret, in any case and with spaces or dashes between groups, or an
+    /// `otpauth://totp/…` URI with its `digits` (6 to 8), `period` and `algorithm` parameters.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use aws_mfa::totp::{Algorithm, Totp};
+    ///
+    /// let totp = Totp::parse(
+    ///     "otpauth://totp/Amazon%20Web%20Services:me@123456789012\
+    ///      ?secret=gezdgnbvgy3tqojq&issuer=Amazon%20Web%20Services",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     (totp.digits(), totp.period(), totp.algorithm()),
+    ///     (6, Duration::from_secs(30), Algorithm::Sha1)
+    /// );
+    /// assert_eq!(totp, Totp::parse("GEZDGNBV-GY3TQOJQ").unwrap());
+    ///
+    /// let totp = Totp::parse(
+    ///     "OTPAUTH://totp/Example?algorithm=sha256&digits=8&period=60&secret=GEZDGNBVGY3TQOJQ%3D",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     (totp.digits(), totp.period(), totp.algorithm()),
+    ///     (8, Duration::from_secs(60), Algorithm::Sha256)
+    /// );
+    ///
+    /// // Errors name the problem, never the secret.
+    /// let error = |input: &str| Totp::parse(input).unwrap_err().to_string();
+    /// assert_eq!(error("not base32!"), "The TOTP secret isn't valid base32");
+    /// assert_eq!(error(""), "The TOTP secret is empty");
+    /// assert_eq!(
+    ///     error("otpauth://hotp/Example?secret=GEZDGNBVGY3TQOJQ&counter=0"),
+    ///     "Only otpauth://totp/ URIs are supported, not HOTP"
+    /// );
+    /// assert_eq!(error("otpauth://totp/Example?digits=6"), "The otpauth:// URI has no secret");
+    /// assert_eq!(
+    ///     error("otpauth://totp/Example?secret=GEZDGNBVGY3TQOJQ&digits=10"),
+    ///     "Unsupported digits 10 in the otpauth:// URI; expected 6, 7 or 8"
+    /// );
+    /// assert_eq!(
+    ///     error("otpauth://totp/Example?secret=GEZDGNBVGY3TQOJQ&algorithm=MD5"),
+    ///     "Unsupported algorithm MD5 in the otpauth:// URI; expected SHA1, SHA256 or SHA512"
+    /// );
+    /// assert!(!error("otpauth://totp/Example?secret=GEZDGNBVGY3TQOJ!").contains("GEZD"));
+    /// ```
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let scheme = input.get(..10).filter(|s| s.eq_ignore_ascii_case("otpauth://"));
+        let Some(rest) = scheme.map(|s| &input[s.len()..]) else {
+            return Ok(Self::new(decode(input)?));
+        };
+
+        let is_totp = rest.get(..5).is_some_and(|t| t.eq_ignore_ascii_case("totp/"));
+        ensure!(is_totp, "Only otpauth://totp/ URIs are supported, not HOTP");
+        let query = rest.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        let mut key = None;
+        let (mut digits, mut period, mut algorithm) = (6, PERIOD, Algorithm::default());
+        for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let value = percent_decode_str(value).decode_utf8_lossy();
+            match name.to_ascii_lowercase().as_str() {
+                "secret" => key = Some(decode(&value)?),
+                "digits" => {
+                    let supported = value.parse().ok().filter(|d| (6..=8).contains(d));
+                    digits = supported.with_context(|| {
+                        format!(
+                            "Unsupported digits {value} in the otpauth:// URI; expected 6, 7 or 8"
+                        )
+                    })?;
+                }
+                "period" => {
+                    let secs = value.parse().ok().filter(|&s| s > 0).with_context(|| {
+                        format!("Invalid period {value} in the otpauth:// URI; expected seconds")
+                    })?;
+                    period = Duration::from_secs(secs);
+                }
+                "algorithm" => {
+                    algorithm = match value.to_ascii_uppercase().as_str() {
+                        "SHA1" => Algorithm::Sha1,
+                        "SHA256" => Algorithm::Sha256,
+                        "SHA512" => Algorithm::Sha512,
+                        _ => bail!(
+                            "Unsupported algorithm {value} in the otpauth:// URI; expected SHA1, \
+                             SHA256 or SHA512"
+                        ),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let key = key.context("The otpauth:// URI has no secret")?;
+        Ok(Self { key, digits, period, algorithm, skew: 0 })
+    }
+
+    /// Generates 6-digit codes of HMAC-SHA1 every 30 seconds from `key`.
+    fn new(key: Vec<u8>) -> Self {
+        Self {
+            key,
+            digits: 6,
+            period: PERIOD,
+            algorithm: Algorithm::default(),
+            skew: 0,
+        }
+    }
+
+    /// Generates the codes of [`Self::now`] for the local time less `secs`, the seconds the
+    /// local clock is ahead of AWS (negative if behind), as `aws-mfa doctor` reports them
+    /// [default: 0]. Without this, a clock off by more than a period yields codes AWS rejects.
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// use aws_mfa::totp::Totp;
+    ///
+    /// // A clock 30 seconds ahead gets the code of the window before.
+    /// let totp = Totp::parse("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+    /// let ahead = totp.clone().with_skew(30);
+    /// let before = || totp.code_at(SystemTime::now() - Duration::from_secs(30));
+    /// let expected = before();
+    /// let code = ahead.now();
+    /// // The window may have turned in between.
+    /// assert!(code == expected || code == before(), "{code}");
+    /// assert!(!ahead.valid_for().is_zero() && ahead.valid_for() <= totp.period());
+    /// ```
+    pub fn with_skew(mut self, secs: i64) -> Self {
+        self.skew = secs;
+        self
+    }
+
+    /// Returns the time codes are generated for: the local time corrected for the skew.
+    pub fn time(&self) -> SystemTime {
+        let now = SystemTime::now();
+        let skew = Duration::from_secs(self.skew.unsigned_abs());
+        match self.skew >= 0 {
+            true => now.checked_sub(skew).unwrap_or(now),
+            false => now + skew,
+        }
+    }
+
+    /// Returns how long the current code stays valid.
+    pub fn valid_for(&self) -> Duration {
+        remaining(self.time(), self.period)
+    }
+
+    /// Returns the number of digits of the codes.
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// Returns how long each code is valid.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the HMAC algorithm codes are computed with.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Returns the code for time `t`.
+    pub fn code_at(&self, t: SystemTime) -> String {
+        let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let counter = (secs / self.period.as_secs()).to_be_bytes();
+        let mac = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.key, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.key, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.key, &counter),
+        };
+
+        // Dynamic truncation, RFC 4226 section 5.3.
+        let offset = usize::from(mac[mac.len() - 1] & 0x0f);
+        let bytes = [mac[offset], mac[offset + 1], mac[offset + 2], mac[offset + 3]];
+        let binary = u32::from_be_bytes(bytes) & 0x7fff_ffff;
+        let code = u64::from(binary) % 10u64.pow(self.digits as u32);
+        format!("{code:0width$}", width = self.digits)
+    }
+
+    /// Returns the current code.
+    pub fn now(&self) -> String {
+        self.code_at(self.time())
+    }
+
+    /// Returns the `otpauth://totp/` URI of the generator, with the secret, labelled `label`.
+    pub fn to_uri(&self, label: &str) -> Secret {
+        let label: String = label
+            .bytes()
+            .map(|b| match b.is_ascii_alphanumeric() || b"-._@".contains(&b) {
+                true => char::from(b).to_string(),
+                false => format!("%{b:02X}"),
+            })
+            .collect();
+        Secret::new(format!(
+            "otpauth://totp/{label}?secret={}&digits={}&period={}&algorithm={}",
+            BASE32_NOPAD.encode(&self.key),
+            self.digits,
+            self.period.as_secs(),
+            self.algorithm.name()
+        ))
+    }
+}
+
+impl Debug for Totp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Totp")
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Decodes a base32 secret, ignoring case, padding and the spaces or dashes grouping it.
+fn decode(secret: &str) -> Result<Vec<u8>> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '='))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    ensure!(!normalized.is_empty(), "The TOTP secret is empty");
+    let key = BASE32_NOPAD.decode(normalized.as_bytes());
+    key.ok().context("The TOTP secret isn't valid base32")
+}
+
+/// Returns the HMAC of `message` with `key`.
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <M as hmac::digest::KeyInit>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,86 @@
+//! TOTP secrets kept in the system keyring, one entry per profile.
+//!
+//! `aws-mfa enroll` stores the secret of the MFA device as an `otpauth://totp/` URI, with its
+//! digits, period and algorithm, in the macOS Keychain, the Windows Credential Manager or the
+//! Secret Service on Linux, under the service `aws-mfa` and the profile name. Refreshes then
+//! generate codes from it with [`Totp`], without a prompt or a subprocess. The secret is only
+//! ever held in the keyring and in memory: it is never written to a file or logged.
+
+use anyhow::{Context, Result};
+use keyring::{Entry, Error};
+
+use crate::{redact::Secret, totp::Totp};
+
+/// Service the keyring entries are stored under.
+pub const SERVICE: &str = "aws-mfa";
+
+/// The keyring entry holding the TOTP secret of a profile.
+///
+/// ```
+/// # fn main() -> anyhow::Result<()> {
+/// use aws_mfa::{totp::Totp, totp_seed::SeedStore};
+///
+/// // The mock keyring keeps what is stored in the entry only.
+/// keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+/// let store = SeedStore::new("work")?;
+/// assert_eq!(store.load()?, None);
+///
+/// let totp = Totp::parse("otpauth://totp/AWS?secret=GEZDGNBVGY3TQOJQ&digits=8&period=60")?;
+/// store.save(&totp)?;
+/// assert_eq!(store.load()?, Some(totp));
+///
+/// assert!(store.delete()?);
+/// assert_eq!(store.load()?, None);
+/// assert!(!store.delete()?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct SeedStore {
+    profile: String,
+    entry: Entry,
+}
+
+impl SeedStore {
+    /// Opens the entry of `profile`, which needn't exist yet.
+    pub fn new(profile: &str) -> Result<Self> {
+        let entry = Entry::new(SERVICE, profile)
+            .with_context(|| format!("Failed to open the keyring entry of {profile}"))?;
+        Ok(Self { profile: profile.to_string(), entry })
+    }
+
+    /// Stores `totp`, replacing the secret stored before, if any.
+    pub fn save(&self, totp: &Totp) -> Result<()> {
+        let uri = totp.to_uri(&format!("{SERVICE}:{}", self.profile));
+        self.entry.set_password(uri.expose()).with_context(|| {
+            format!("Failed to store the TOTP secret of {} in the keyring", self.profile)
+        })
+    }
+
+    /// Returns the stored generator, or `None` if the profile isn't enrolled.
+    pub fn load(&self) -> Result<Option<Totp>> {
+        let uri = match self.entry.get_password() {
+            Ok(uri) => Secret::new(uri),
+            Err(Error::NoEntry) => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read the TOTP secret of {} from the keyring", self.profile)
+                });
+            }
+        };
+        let totp = Totp::parse(uri.expose()).with_context(|| {
+            format!("Invalid TOTP secret of {} in the keyring; run enroll again", self.profile)
+        })?;
+        Ok(Some(totp))
+    }
+
+    /// Deletes the stored secret. Returns whether there was one.
+    pub fn delete(&self) -> Result<bool> {
+        match self.entry.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(Error::NoEntry) => Ok(false),
+            Err(e) => Err(e).with_context(|| {
+                format!("Failed to delete the TOTP secret of {} from the keyring", self.profile)
+            }),
+        }
+    }
+}
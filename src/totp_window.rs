@@ -0,0 +1,104 @@
+//! TOTP time window arithmetic.
+//!
+//! A code is valid until the end of its time step, so one handed out just before the step rolls
+//! over may no longer be by the time STS checks it. The functions here take the time to compute
+//! with rather than reading the clock, so callers pass [`SystemTime::now`] and tests any instant.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of a TOTP time step, as used by AWS MFA devices and assumed for password managers.
+pub const PERIOD: Duration = Duration::from_secs(30);
+
+/// Default of how long a code must stay valid to be submitted, enough for the STS round trip.
+pub const DEFAULT_MIN_VALIDITY: Duration = Duration::from_secs(5);
+
+/// Returns the index of the time step of `period` containing `t`.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use aws_mfa::totp_window::{PERIOD, window};
+///
+/// assert_eq!(window(UNIX_EPOCH + Duration::from_millis(29_900), PERIOD), 0);
+/// assert_eq!(window(UNIX_EPOCH + Duration::from_secs(30), PERIOD), 1);
+/// assert_eq!(window(UNIX_EPOCH + Duration::from_millis(30_100), PERIOD), 1);
+/// ```
+pub fn window(t: SystemTime, period: Duration) -> u64 {
+    let elapsed = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (elapsed.as_nanos() / period.as_nanos().max(1)) as u64
+}
+
+/// Returns the time left in the time step of `period` containing `t`, which is the whole period
+/// right at its start.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use aws_mfa::totp_window::{PERIOD, remaining};
+///
+/// let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+/// assert_eq!(remaining(at(29_900), PERIOD), Duration::from_millis(100));
+/// assert_eq!(remaining(at(30_000), PERIOD), PERIOD);
+/// assert_eq!(remaining(at(30_100), PERIOD), Duration::from_millis(29_900));
+/// assert_eq!(remaining(at(59_900), Duration::from_secs(60)), Duration::from_millis(100));
+/// ```
+pub fn remaining(t: SystemTime, period: Duration) -> Duration {
+    let elapsed = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let period = period.as_nanos().max(1);
+    Duration::from_nanos((period - elapsed.as_nanos() % period) as u64)
+}
+
+/// Returns how long to wait at `t` for a code of the time step of `period` that is valid for at
+/// least `min_validity`: nothing while the current code is, and until the next step otherwise.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use aws_mfa::totp_window::{DEFAULT_MIN_VALIDITY, PERIOD, wait_for_validity};
+///
+/// let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+/// let wait = |millis| wait_for_validity(at(millis), PERIOD, DEFAULT_MIN_VALIDITY);
+///
+/// // 0.1s before the rollover, the next code is awaited.
+/// assert_eq!(wait(29_900), Duration::from_millis(100));
+/// assert_eq!(wait(25_100), Duration::from_millis(4_900));
+/// // Exactly the minimum left is enough, and so is a code just rolled over.
+/// assert_eq!(wait(25_000), Duration::ZERO);
+/// assert_eq!(wait(30_100), Duration::ZERO);
+/// assert_eq!(wait(100), Duration::ZERO);
+///
+/// // Without a minimum, any code goes.
+/// assert_eq!(wait_for_validity(at(29_900), PERIOD, Duration::ZERO), Duration::ZERO);
+/// ```
+pub fn wait_for_validity(t: SystemTime, period: Duration, min_validity: Duration) -> Duration {
+    match remaining(t, period) {
+        left if left < min_validity => left,
+        _ => Duration::ZERO,
+    }
+}
+
+/// Returns whether a code obtained at `obtained` may still be submitted at `now`: it is of the
+/// same time step of `period`, and that step has at least `min_validity` left.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use aws_mfa::totp_window::{DEFAULT_MIN_VALIDITY, PERIOD, still_valid};
+///
+/// let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+/// let valid = |obtained, now| still_valid(at(obtained), at(now), PERIOD, DEFAULT_MIN_VALIDITY);
+///
+/// assert!(valid(1_000, 20_000));
+/// assert!(valid(1_000, 25_000));
+/// // Too little left, or rolled over.
+/// assert!(!valid(1_000, 25_100));
+/// assert!(!valid(29_000, 31_000));
+/// ```
+pub fn still_valid(
+    obtained: SystemTime,
+    now: SystemTime,
+    period: Duration,
+    min_validity: Duration,
+) -> bool {
+    window(obtained, period) == window(now, period) && remaining(now, period) >= min_validity
+}
@@ -0,0 +1,284 @@
+//! Watch mode that keeps the session credentials fresh.
+//!
+//! Sleeps until shortly before the recorded expiration, then refreshes. The
+//! deadline is recomputed from the wall clock after every wake-up so that laptop sleep/resume
+//! doesn't leave the loop waiting on a stale monotonic timer. The [`Schedule`] is given the time
+//! rather than reading the clock, so tests can drive it through any instant.
+
+use std::{
+    cmp::min,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use log::{error, info, warn};
+use tokio::{select, signal::ctrl_c, task::spawn_blocking, time::sleep};
+
+use crate::{
+    error::{Failure, Report},
+    hook::{Hook, Kind},
+    notify,
+    updater::AwsMfaUpdater,
+};
+
+/// Upper bound for a single sleep, after which the deadline is checked against the wall clock.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Delay before the first retry after a failed refresh. Doubled on each consecutive failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Upper bound for the retry delay.
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+/// How long before a refresh that needs manual input the user is given a heads-up.
+const HEADS_UP: Duration = Duration::from_secs(5 * 60);
+
+/// What the refresh loop does next.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Sleep this long, then check the deadline against the wall clock again.
+    Sleep(Duration),
+    /// The deadline has passed; refresh now.
+    Refresh,
+}
+
+/// When to refresh the session, and how long to back off after failed refreshes.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use aws_mfa::watch::{Schedule, Step};
+///
+/// let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// let schedule = Schedule::new(Duration::from_secs(600));
+/// let deadline = schedule.deadline(Some(now + Duration::from_secs(3600)), now);
+/// assert_eq!(deadline, now + Duration::from_secs(3000));
+/// // Long sleeps are cut short to notice the wall clock jumping.
+/// assert_eq!(Schedule::step(deadline, now), Step::Sleep(Duration::from_secs(60)));
+/// assert_eq!(Schedule::step(deadline, deadline), Step::Refresh);
+/// ```
+#[derive(Debug)]
+pub struct Schedule {
+    lead_time: Duration,
+    backoff: Duration,
+    /// Expiration of the session the user was last told about, if any.
+    warned: Option<Option<SystemTime>>,
+}
+
+impl Schedule {
+    /// Refreshes `lead_time` before the session expires.
+    pub fn new(lead_time: Duration) -> Self {
+        Self { lead_time, backoff: INITIAL_BACKOFF, warned: None }
+    }
+
+    /// Returns whether to give the heads-up for the session expiring at `expiration`, which is
+    /// only the case once per session, however often its refresh is retried.
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// use aws_mfa::watch::Schedule;
+    ///
+    /// let expiration = Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    /// let mut schedule = Schedule::new(Duration::from_secs(600));
+    /// assert!(schedule.heads_up(expiration));
+    /// assert!(!schedule.heads_up(expiration));
+    /// ```
+    pub fn heads_up(&mut self, expiration: Option<SystemTime>) -> bool {
+        self.warned.replace(expiration) != Some(expiration)
+    }
+
+    /// Returns when to refresh a session expiring at `expiration`: `now` if none is recorded.
+    pub fn deadline(&self, expiration: Option<SystemTime>, now: SystemTime) -> SystemTime {
+        match expiration {
+            Some(expiration) => expiration.checked_sub(self.lead_time).unwrap_or(expiration),
+            None => now,
+        }
+    }
+
+    /// Returns what to do at `now` on the way to `deadline`, sleeping a minute at most.
+    pub fn step(deadline: SystemTime, now: SystemTime) -> Step {
+        match deadline.duration_since(now) {
+            Ok(remaining) if !remaining.is_zero() => Step::Sleep(min(remaining, MAX_SLEEP)),
+            _ => Step::Refresh,
+        }
+    }
+
+    /// Records a failed refresh and returns how long to wait before retrying, twice as long as
+    /// after the previous failure, up to half an hour.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use aws_mfa::watch::Schedule;
+    ///
+    /// let mut schedule = Schedule::new(Duration::from_secs(600));
+    /// let retries: Vec<_> = (0..8).map(|_| schedule.failed().as_secs()).collect();
+    /// assert_eq!(retries, [30, 60, 120, 240, 480, 960, 1800, 1800]);
+    /// schedule.succeeded();
+    /// assert_eq!(schedule.failed(), Duration::from_secs(30));
+    /// ```
+    pub fn failed(&mut self) -> Duration {
+        let backoff = self.backoff;
+        self.backoff = min(backoff * 2, MAX_BACKOFF);
+        backoff
+    }
+
+    /// Records a successful refresh, so the next failure is retried after 30s again.
+    pub fn succeeded(&mut self) {
+        self.backoff = INITIAL_BACKOFF;
+    }
+}
+
+/// Checks that refreshing `lead_time` before a session lasting `duration` seconds expires leaves
+/// some of it to use, rather than refreshing again as soon as it is obtained.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use aws_mfa::watch::check_lead_time;
+///
+/// assert!(check_lead_time(Duration::from_secs(600), 3600).is_ok());
+/// let error = check_lead_time(Duration::from_secs(3600), 3600).unwrap_err();
+/// assert_eq!(
+///     error.to_string(),
+///     "A lead time of 3600s leaves nothing of a 3600s session; use a shorter --lead-time"
+/// );
+/// ```
+pub fn check_lead_time(lead_time: Duration, duration: u32) -> Result<()> {
+    if lead_time >= Duration::from_secs(duration.into()) {
+        return Err(Failure::Config.wrap(anyhow!(
+            "A lead time of {}s leaves nothing of a {duration}s session; use a shorter --lead-time",
+            lead_time.as_secs()
+        )));
+    }
+    Ok(())
+}
+
+/// Refreshes the credentials `lead_time` before they expire until SIGINT/SIGTERM is received.
+/// Fails at once if the lead time isn't shorter than the session, see [`check_lead_time`].
+///
+/// `get_token` is called on a blocking thread, so it may prompt on stdin. With `notify`, every
+/// refresh result is shown as a desktop notification, and if `manual` (no automated token
+/// provider is configured) a heads-up is shown a few minutes before the prompt appears. The
+/// expiring hook of the updater, if any, runs at the same time, see
+/// [`AwsMfaUpdater::with_hooks`].
+pub async fn run<F>(
+    updater: &AwsMfaUpdater,
+    lead_time: Duration,
+    notify: bool,
+    manual: bool,
+    get_token: F,
+) -> Result<()>
+where
+    F: Fn() -> Result<String> + Clone + Send + 'static,
+{
+    check_lead_time(lead_time, updater.duration())?;
+    select! {
+        result = refresh_loop(updater, lead_time, notify, manual, get_token) => result,
+        result = shutdown_signal() => {
+            info!("Shutting down");
+            result
+        }
+    }
+}
+
+async fn refresh_loop<F>(
+    updater: &AwsMfaUpdater,
+    lead_time: Duration,
+    notify: bool,
+    manual: bool,
+    get_token: F,
+) -> Result<()>
+where
+    F: Fn() -> Result<String> + Clone + Send + 'static,
+{
+    let mut schedule = Schedule::new(lead_time);
+
+    loop {
+        let expiration = updater.expiration().unwrap_or_else(|e| {
+            error!("Failed to read the current expiration, refreshing now: {}", Report(&e));
+            None
+        });
+        let deadline = schedule.deadline(expiration, SystemTime::now());
+        // A retry after a failed refresh is for the same session, which was warned about.
+        let heads_up = schedule.heads_up(expiration);
+
+        if heads_up
+            && notify
+            && manual
+            && deadline.duration_since(SystemTime::now()).is_ok_and(|d| d > HEADS_UP)
+        {
+            sleep_until(deadline - HEADS_UP).await;
+            notify::expiring(HEADS_UP);
+        }
+        if let Some(hook) = updater.on_expiring_hook().filter(|_| heads_up) {
+            sleep_until(deadline.checked_sub(HEADS_UP).unwrap_or(deadline)).await;
+            expiring(updater, hook).await;
+        }
+        sleep_until(deadline).await;
+
+        let get_token = get_token.clone();
+        let token = async { spawn_blocking(get_token).await? };
+        let result = updater.update_credentials_with(token).await;
+
+        if notify {
+            match &result {
+                Ok(session) => {
+                    notify::refreshed(&updater.describe_expiration(session.expiration()))
+                }
+                Err(e) => notify::failed(e),
+            }
+        }
+
+        match result {
+            Ok(_) => schedule.succeeded(),
+            Err(e) => {
+                let backoff = schedule.failed();
+                error!("Refresh failed, retrying in {}s: {}", backoff.as_secs(), Report(&e));
+                sleep_until(SystemTime::now() + backoff).await;
+            }
+        }
+    }
+}
+
+/// Runs the `hook` for the session about to expire, if there is one.
+async fn expiring(updater: &AwsMfaUpdater, hook: &Hook) {
+    let session = match updater.session().and_then(|s| s.map(|s| s.credentials()).transpose()) {
+        Ok(Some(session)) => session,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Not running the expiring hook: {}", Report(&e));
+            return;
+        }
+    };
+    match updater.hook_event(Kind::Expiring, updater.mode(false), &session) {
+        Ok(event) => hook.fire(&event).await,
+        Err(e) => warn!("Not running the expiring hook: {}", Report(&e)),
+    }
+}
+
+/// Sleeps until the wall clock reaches `deadline`, waking up at least every [`MAX_SLEEP`].
+async fn sleep_until(deadline: SystemTime) {
+    while let Step::Sleep(duration) = Schedule::step(deadline, SystemTime::now()) {
+        sleep(duration).await;
+    }
+}
+
+/// Resolves on the first SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+#[cfg(unix)]
+pub async fn shutdown_signal() -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    select! {
+        result = ctrl_c() => Ok(result?),
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+/// Resolves on the first Ctrl-C.
+#[cfg(not(unix))]
+pub async fn shutdown_signal() -> Result<()> {
+    Ok(ctrl_c().await?)
+}
@@ -0,0 +1,186 @@
+//! The questions walking the user through the [`setup`](crate::setup) of a profile.
+//!
+//! [`init`] runs for the `init` command, [`wizard`] when a refresh finds something missing, and
+//! [`migrate_keys`] when the keys are in the profile's own section. Every question is asked on a
+//! [`Questions`], stdin and stderr for the binary, and every step can be skipped with an empty
+//! answer. Nothing is written until the user confirms what was gathered.
+
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, ensure};
+use log::info;
+
+use crate::{
+    credentials::{AwsCredentials, MfaDevice},
+    error::Report,
+    progress,
+    questions::Questions,
+    redact::Secret,
+    setup::{self, Setup},
+    updater::Profile,
+};
+
+/// Sets up `profile` for the `init` command: moves the keys `aws configure` left in its section,
+/// or runs the [`wizard`] for whatever is missing, then tightens the permissions of the
+/// credentials file at `path`. IAM calls time out after `timeout`.
+pub async fn init(
+    questions: &mut Questions<impl BufRead, impl Write>,
+    path: Option<PathBuf>,
+    profile: &Profile,
+    in_keyring: bool,
+    timeout: Duration,
+) -> Result<()> {
+    match setup::missing(path.clone(), profile)? {
+        Some(missing) if setup::stray_keys(path.clone(), profile)?.is_some() => {
+            let moved = questions.confirm(&format!("{missing}. Move them there?"))?;
+            ensure!(moved, "Setup cancelled; nothing was written");
+            migrate_keys(questions, path.clone(), profile, true, timeout).await?;
+        }
+        Some(missing) => {
+            wizard(questions, path.clone(), profile, &missing, in_keyring, timeout).await?
+        }
+        None => info!(
+            "Profile {} is set up already; `aws-mfa --fix` enters new long-term keys",
+            profile.name()
+        ),
+    }
+    setup::secure(path)?;
+    info!("Run `aws-mfa --profile {}` to get a session", profile.name());
+    Ok(())
+}
+
+/// Sets up `profile`, which is `missing` something, with the user's answers and writes them once
+/// confirmed, the keys to the keyring if `in_keyring`; see [`Setup`].
+pub async fn wizard(
+    questions: &mut Questions<impl BufRead, impl Write>,
+    path: Option<PathBuf>,
+    profile: &Profile,
+    missing: &str,
+    in_keyring: bool,
+    timeout: Duration,
+) -> Result<()> {
+    questions.say(format!(
+        "{missing}. Let's set up profile {}; press Enter to skip a step.",
+        profile.name()
+    ))?;
+    let mut setup = Setup { in_keyring, ..Default::default() };
+
+    // Keys already in the long-term section are kept, and serve to look up the MFA device.
+    let stored = setup::long_term_keys(path.clone(), profile)?;
+    let access_key_id = match stored {
+        Some(_) => None,
+        None => ask_access_key_id(questions)?,
+    };
+    if let Some(access_key_id) = access_key_id {
+        let secret = questions
+            .secret("Secret access key (hidden): ")
+            .context("Failed to read the secret access key from the terminal")?;
+        match secret.trim() {
+            "" => questions.say("Skipping the access key, which is no use without its secret")?,
+            secret => setup.keys = Some((access_key_id, Secret::new(secret))),
+        }
+    }
+
+    let keys = setup.keys.as_ref().or(stored.as_ref());
+    setup.mfa_device = ask_mfa_device(questions, keys, timeout).await?;
+
+    let account = questions.ask("1Password account for the MFA code, e.g. my.1password.com")?;
+    if !account.is_empty() {
+        match questions.ask("1Password item with the one-time password")? {
+            item if item.is_empty() => questions.say("Skipping 1Password, which needs an item")?,
+            item => setup.one_password = Some((account, item)),
+        }
+    }
+
+    let summary = setup.to_string();
+    questions.say(format!("\nProfile {}:\n{}", profile.name(), summary.trim_end()))?;
+    ensure!(questions.confirm("Write this?")?, "Setup cancelled; nothing was written");
+    setup.write(path, profile)
+}
+
+/// Moves the long-term keys `aws configure` left in the section of `profile` to its long-term
+/// section with [`setup::migrate`], then asks for the MFA device if it is still missing, or fails
+/// with [`setup::unattended`] if not `interactive`.
+pub async fn migrate_keys(
+    questions: &mut Questions<impl BufRead, impl Write>,
+    path: Option<PathBuf>,
+    profile: &Profile,
+    interactive: bool,
+    timeout: Duration,
+) -> Result<()> {
+    let moved = setup::migrate(path.clone(), profile)?;
+    let Some(missing) = setup::missing(path.clone(), profile)? else {
+        return Ok(());
+    };
+    if !interactive {
+        return Err(setup::unattended(&missing));
+    }
+    questions.say(format!("{missing}; press Enter to skip."))?;
+    match ask_mfa_device(questions, moved.keys.as_ref(), timeout).await? {
+        Some(device) => {
+            Setup { mfa_device: Some(device), ..Default::default() }.write(path, profile)
+        }
+        None => Err(setup::unattended(&missing)),
+    }
+}
+
+/// Asks for the MFA device, offering to look it up with IAM when the access `keys` are known.
+/// Returns `None` if the step is skipped.
+pub async fn ask_mfa_device(
+    questions: &mut Questions<impl BufRead, impl Write>,
+    keys: Option<&(String, Secret)>,
+    timeout: Duration,
+) -> Result<Option<String>> {
+    if let Some((access_key_id, secret)) = keys
+        && questions.confirm("Look up the MFA device with this access key?")?
+    {
+        let credentials =
+            AwsCredentials::new(access_key_id.clone(), secret.expose().to_string(), String::new());
+        let iam = credentials.iam_client(timeout).await;
+        match progress::track("Listing MFA devices…", setup::discover_mfa_devices(&iam)).await {
+            Ok(devices) => match devices.as_slice() {
+                [] => questions.say("This IAM user has no MFA device")?,
+                [device] => {
+                    questions.say(format!("Found the MFA device {device}"))?;
+                    return Ok(Some(device.clone()));
+                }
+                _ => {
+                    let names: Vec<_> = devices
+                        .iter()
+                        .map(|d| MfaDevice::parse(d).map_or(d.clone(), |d| d.to_string()))
+                        .collect();
+                    return Ok(Some(devices[questions.choose("MFA device", &names)?].clone()));
+                }
+            },
+            Err(e) => questions.say(Report(&e))?,
+        }
+    }
+    loop {
+        let value =
+            questions.ask("MFA device ARN (arn:aws:iam::<account>:mfa/<name>) or serial number")?;
+        match MfaDevice::parse(&value) {
+            _ if value.is_empty() => return Ok(None),
+            Ok(_) => return Ok(Some(value)),
+            Err(e) => questions.say(e)?,
+        }
+    }
+}
+
+/// Asks for a long-term access key ID until a valid one is entered. Returns `None` if the step is
+/// skipped.
+fn ask_access_key_id(
+    questions: &mut Questions<impl BufRead, impl Write>,
+) -> Result<Option<String>> {
+    loop {
+        let value = questions.ask("Access key ID (AKIA…)")?;
+        match setup::check_access_key_id(&value) {
+            _ if value.is_empty() => return Ok(None),
+            Ok(()) => return Ok(Some(value)),
+            Err(e) => questions.say(e)?,
+        }
+    }
+}
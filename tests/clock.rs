@@ -0,0 +1,92 @@
+//! Clock skew against the server time in a `Date` header.
+
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use aws_mfa::{
+    clock::{MAX_SKEW_SECS, skew_secs, skew_warning},
+    credentials::AwsCredentials,
+    error::{Failure, Report},
+};
+use aws_smithy_types::{DateTime, date_time::Format};
+use common::{ACCESS_KEY_ID, Reply, SECRET_ACCESS_KEY, Stub};
+
+/// Server time of the tests, as in a `Date` header.
+const DATE: &str = "Fri, 03 May 2024 19:21:04 GMT";
+
+/// Returns the local time `offset` seconds from [`DATE`].
+fn local(offset: i64) -> SystemTime {
+    let server = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_764_064);
+    match offset {
+        0.. => server + Duration::from_secs(offset.unsigned_abs()),
+        _ => server - Duration::from_secs(offset.unsigned_abs()),
+    }
+}
+
+#[test]
+fn local_clock_ahead() {
+    assert_eq!(skew_secs(DATE, local(300)), Some(300));
+    let warning = skew_warning(DATE, local(300)).unwrap();
+    assert!(warning.starts_with("Your local clock is 300 seconds ahead of AWS"), "{warning}");
+}
+
+#[test]
+fn local_clock_behind() {
+    assert_eq!(skew_secs(DATE, local(-90)), Some(-90));
+    let warning = skew_warning(DATE, local(-90)).unwrap();
+    assert!(warning.starts_with("Your local clock is 90 seconds behind AWS"), "{warning}");
+}
+
+#[test]
+fn local_clock_within_tolerance() {
+    for offset in [0, MAX_SKEW_SECS, -MAX_SKEW_SECS] {
+        assert_eq!(skew_secs(DATE, local(offset)), Some(offset));
+        assert_eq!(skew_warning(DATE, local(offset)), None, "{offset}");
+    }
+    assert!(skew_warning(DATE, local(MAX_SKEW_SECS + 1)).is_some());
+}
+
+#[test]
+fn unparseable_date_is_no_skew() {
+    assert_eq!(skew_secs("yesterday", local(0)), None);
+    assert_eq!(skew_warning("2024-05-03T19:21:04Z", local(3600)), None);
+}
+
+/// Returns the error of a GetSessionToken refused as `code` by a server whose clock is
+/// `offset` seconds ahead of the local one, as reported.
+async fn refused_by_server_off_by(code: &str, offset: i64) -> (Option<Failure>, String) {
+    let now = DateTime::from(SystemTime::now()).secs();
+    let date = DateTime::from_secs(now + offset).fmt(Format::HttpDate).unwrap();
+    let reply = Reply::error(403, code, "invalid MFA one time pass code").with_date(date);
+    let stub = Stub::start(move |_| reply.clone()).await;
+    let credentials = AwsCredentials::new(
+        ACCESS_KEY_ID.into(),
+        SECRET_ACCESS_KEY.into(),
+        "arn:aws:iam::123456789012:mfa/yubikey".into(),
+    );
+    let error = credentials
+        .get_session_token(&stub.sts(), "123456", 3600)
+        .await
+        .unwrap_err();
+    (Failure::of(&error), Report(&error).to_string())
+}
+
+#[tokio::test]
+async fn skewed_clock_is_named_before_the_rejected_code() {
+    let (failure, report) = refused_by_server_off_by("AccessDenied", -300).await;
+
+    assert_eq!(failure, Some(Failure::Rejected));
+    // The reply is dated before the request is sent, and a second may tick in between.
+    let ahead =
+        ["300", "301"].map(|secs| format!("Your local clock is {secs} seconds ahead of AWS"));
+    assert!(ahead.iter().any(|prefix| report.starts_with(prefix)), "{report}");
+}
+
+#[tokio::test]
+async fn clock_within_tolerance_leaves_the_rejected_code_to_blame() {
+    let (failure, report) = refused_by_server_off_by("AccessDenied", 5).await;
+
+    assert_eq!(failure, Some(Failure::Rejected));
+    assert!(report.starts_with("The MFA code was rejected"), "{report}");
+}
@@ -0,0 +1,45 @@
+//! `credential-process` as AWS tools run it: the JSON document alone on stdout, and the session
+//! stored so that the next call needs no MFA code.
+
+mod common;
+
+use std::{fs, path::Path, process::Output};
+
+use common::{EXPIRATION, Stub, WORK_LONG_TERM, aws_mfa, sessions};
+use serde_json::{Value, json};
+
+/// Runs `aws-mfa --profile work <args> credential-process` against `stub`.
+async fn credential_process(home: &Path, stub: &Stub, args: &[&str]) -> Output {
+    let mut command = aws_mfa(home);
+    command
+        .env("AWS_ENDPOINT_URL_STS", stub.endpoint())
+        .env("AWS_REGION", "eu-west-1")
+        .args(["--profile", "work"])
+        .args(args)
+        .arg("credential-process");
+    let output = tokio::process::Command::from(command).output().await.unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[tokio::test]
+async fn session_is_printed_as_json_and_reused_by_the_next_call() {
+    let stub = Stub::start(sessions).await;
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("credentials"), WORK_LONG_TERM).unwrap();
+    let expected = json!({
+        "Version": 1,
+        "AccessKeyId": "ASIASESSION",
+        "SecretAccessKey": "secret-of-ASIASESSION",
+        "SessionToken": "token-of-ASIASESSION",
+        "Expiration": EXPIRATION,
+    });
+
+    let first = credential_process(home.path(), &stub, &["--token", "123456"]).await;
+    assert_eq!(serde_json::from_slice::<Value>(&first.stdout).unwrap(), expected);
+
+    // Without a code to give, the second call can only succeed with the stored session.
+    let second = credential_process(home.path(), &stub, &[]).await;
+    assert_eq!(serde_json::from_slice::<Value>(&second.stdout).unwrap(), expected);
+    assert_eq!(stub.actions(), ["GetSessionToken"]);
+}
@@ -0,0 +1,131 @@
+//! How STS failures are reported, against a stub STS.
+
+mod common;
+
+use std::time::Duration;
+
+use aws_mfa::{
+    credentials::{AwsCredentials, caller_identity, timeout_config},
+    error::{Failure, Report},
+};
+use common::{ACCESS_KEY_ID, Reply, SECRET_ACCESS_KEY, Stub, sts_client};
+use tokio::net::TcpListener;
+
+/// Returns the long-term credentials of [`common::WORK_LONG_TERM`].
+fn credentials() -> AwsCredentials {
+    AwsCredentials::new(
+        ACCESS_KEY_ID.into(),
+        SECRET_ACCESS_KEY.into(),
+        "arn:aws:iam::123456789012:mfa/yubikey".into(),
+    )
+}
+
+/// Returns the error of a GetSessionToken answered with `reply`, as reported.
+async fn refusal(reply: Reply) -> (Option<Failure>, String) {
+    let stub = Stub::start(move |_| reply.clone()).await;
+    let error = credentials()
+        .get_session_token(&stub.sts(), "123456", 3600)
+        .await
+        .unwrap_err();
+    (Failure::of(&error), Report(&error).to_string())
+}
+
+#[tokio::test]
+async fn timeout_says_how_long_it_waited() {
+    let stub = Stub::start(|_| Reply::silence()).await;
+    let config = stub.sts().config().to_builder();
+    let config = config.timeout_config(timeout_config(Duration::from_millis(500)));
+    let client = aws_sdk_sts::Client::from_conf(config.build());
+
+    let error = caller_identity(&client).await.unwrap_err();
+
+    assert_eq!(Failure::of(&error), Some(Failure::Network));
+    // The endpoint named is the one of the environment, which clients are built from.
+    let message = error.to_string();
+    assert!(message.starts_with("Could not reach STS at "), "{message}");
+    assert!(
+        message.ends_with(
+            " within 0.5s; check your network and proxy settings, or raise --sts-timeout"
+        ),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn unreachable_endpoint_is_a_network_failure() {
+    // Nothing listens on a port that was just released.
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let client = sts_client(&format!("http://{closed}"));
+
+    let error = caller_identity(&client).await.unwrap_err();
+
+    assert_eq!(Failure::of(&error), Some(Failure::Network));
+    let message = error.to_string();
+    assert!(message.starts_with("Could not reach STS at "), "{message}");
+    assert!(
+        message.ends_with("; check your network and proxy settings, or raise --sts-timeout"),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn rejected_code_asks_for_a_fresh_one() {
+    let reply = Reply::error(
+        403,
+        "AccessDenied",
+        "MultiFactorAuthentication failed with invalid MFA one time pass code.",
+    );
+
+    let (failure, report) = refusal(reply).await;
+
+    assert_eq!(failure, Some(Failure::Rejected));
+    assert!(
+        report.starts_with(
+            "The MFA code was rejected; codes expire every 30 seconds, grab a fresh one: "
+        ),
+        "{report}"
+    );
+    assert!(report.contains("invalid MFA one time pass code"), "{report}");
+}
+
+#[tokio::test]
+async fn wrong_device_points_at_aws_mfa_device() {
+    let reply = Reply::error(
+        403,
+        "AccessDenied",
+        "MultiFactorAuthentication failed, unable to validate MFA code.",
+    );
+
+    let (failure, report) = refusal(reply).await;
+
+    assert_eq!(failure, Some(Failure::Rejected));
+    assert!(report.starts_with("MFA authentication failed. Check that aws_mfa_device"), "{report}");
+}
+
+#[tokio::test]
+async fn expired_token_blames_the_clock() {
+    let reply =
+        Reply::error(403, "ExpiredToken", "The security token included in the request is expired");
+
+    let (failure, report) = refusal(reply).await;
+
+    assert_eq!(failure, Some(Failure::Rejected));
+    assert!(
+        report.starts_with(
+            "The request was rejected as expired, which usually means the local clock is off; \
+             check your system time: "
+        ),
+        "{report}"
+    );
+}
+
+#[tokio::test]
+async fn unknown_service_error_is_passed_on() {
+    let reply = Reply::error(400, "InvalidParameterValue", "Something else");
+
+    let (failure, report) = refusal(reply).await;
+
+    assert_eq!(failure, Some(Failure::Rejected));
+    assert!(report.contains("Something else"), "{report}");
+    assert!(!report.contains("clock"), "{report}");
+}
@@ -0,0 +1,89 @@
+//! Saving the credentials file: through a private temporary file renamed into place.
+#![cfg(unix)]
+
+use std::{
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt, symlink},
+    path::Path,
+};
+
+use aws_mfa::credentials_file::CredentialsFile;
+
+const BEFORE: &str = "[default]\naws_access_key_id = ASIA1\n";
+const AFTER: &str = "[default]\naws_access_key_id = ASIA2\n";
+
+/// Returns the mode bits of `path`.
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn new_file_is_private_to_the_owner() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+
+    CredentialsFile::parse(AFTER).save(&path).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), AFTER);
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn existing_file_is_replaced_by_a_rename_keeping_its_mode() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, BEFORE).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let inode = fs::metadata(&path).unwrap().ino();
+
+    CredentialsFile::parse(AFTER).save(&path).unwrap();
+
+    // A new file took the place of the old one, which readers still holding it see whole.
+    assert_ne!(fs::metadata(&path).unwrap().ino(), inode);
+    assert_eq!(fs::read_to_string(&path).unwrap(), AFTER);
+    assert_eq!(mode(&path), 0o640);
+}
+
+#[test]
+fn temporary_file_left_by_a_crash_is_made_private_and_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    let stale = dir.path().join("credentials.tmp");
+    fs::write(&stale, "[default]\naws_access_key_id = ASIAHALFWRI").unwrap();
+    fs::set_permissions(&stale, fs::Permissions::from_mode(0o644)).unwrap();
+
+    CredentialsFile::parse(AFTER).save(&path).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), AFTER);
+    assert_eq!(mode(&path), 0o600);
+    assert!(!stale.exists());
+}
+
+#[test]
+fn failed_write_leaves_the_file_as_it_was() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, BEFORE).unwrap();
+    // The temporary file can't be created where a directory is.
+    fs::create_dir(dir.path().join("credentials.tmp")).unwrap();
+
+    let error = CredentialsFile::parse(AFTER).save(&path).unwrap_err();
+
+    assert!(error.to_string().starts_with("Failed to write"), "{error}");
+    assert_eq!(fs::read_to_string(&path).unwrap(), BEFORE);
+}
+
+#[test]
+fn symlinked_file_is_replaced_behind_the_link() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("dotfiles-credentials");
+    let link = dir.path().join("credentials");
+    fs::write(&target, BEFORE).unwrap();
+    symlink(&target, &link).unwrap();
+
+    CredentialsFile::parse(AFTER).save(&link).unwrap();
+
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), AFTER);
+}
@@ -0,0 +1,37 @@
+//! Checks of `aws-mfa doctor` against a stub STS.
+
+mod common;
+
+use std::time::SystemTime;
+
+use aws_mfa::doctor::{Status, sts};
+use aws_smithy_types::{DateTime, date_time::Format};
+use common::{Reply, Stub, sts_client};
+use tokio::net::TcpListener;
+
+/// Returns the time `offset` seconds from now, as in a `Date` header.
+fn date(offset: i64) -> String {
+    let now = DateTime::from(SystemTime::now()).secs();
+    DateTime::from_secs(now + offset).fmt(Format::HttpDate).unwrap()
+}
+
+#[tokio::test]
+async fn rejected_credentials_still_show_a_clock_behind() {
+    let stub =
+        Stub::start(|_| Reply::error(403, "InvalidClientTokenId", "").with_date(date(-300))).await;
+
+    let [reachable, clock] = sts(&stub.sts()).await;
+    assert_eq!(reachable.status, Status::Pass);
+    assert_eq!(clock.status, Status::Fail);
+    assert!(clock.detail.contains("ahead"), "{}", clock.detail);
+}
+
+#[tokio::test]
+async fn unreachable_sts_leaves_the_clock_unchecked() {
+    // Nothing listens on a port that was just released.
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+    let [reachable, clock] = sts(&sts_client(&format!("http://{closed}"))).await;
+    assert_eq!(reachable.status, Status::Fail);
+    assert_eq!(clock.status, Status::Warn);
+}
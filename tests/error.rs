@@ -0,0 +1,183 @@
+//! Exit codes of the failure classes, and how errors are classified.
+
+mod common;
+
+use std::{
+    fs,
+    io::{self, ErrorKind, Write},
+    process::{ExitCode, Stdio},
+};
+
+use anyhow::{Context, anyhow};
+use aws_mfa::error::{Failure, Report, ResultExt, is_transient};
+use aws_sdk_sts::{
+    config::http::HttpResponse,
+    error::{ConnectorError, ErrorMetadata, SdkError},
+    operation::get_caller_identity::GetCallerIdentityError,
+};
+use aws_smithy_types::body::SdkBody;
+use common::{WORK_LONG_TERM, aws_mfa};
+
+#[test]
+fn bad_token_exits_with_3() {
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("credentials"), WORK_LONG_TERM).unwrap();
+
+    let output = aws_mfa(home.path())
+        .args(["--profile", "work", "--token", "12345"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid MFA code in --token"), "{stderr}");
+}
+
+#[test]
+fn bad_typed_token_exits_with_3() {
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("credentials"), WORK_LONG_TERM).unwrap();
+
+    let mut child = aws_mfa(home.path())
+        .args(["--profile", "work", "--providers", "prompt", "--interactive"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"12345\nabc\n1234567\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No valid MFA code entered after 3 attempts"), "{stderr}");
+}
+
+#[test]
+fn fix_without_a_terminal_exits_with_8() {
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("credentials"), WORK_LONG_TERM).unwrap();
+
+    // Neither stdin nor stderr is a terminal, so nothing could be asked.
+    let output = aws_mfa(home.path())
+        .args(["--profile", "work", "--fix"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--fix asks for the long-term keys"), "{stderr}");
+    let content = fs::read_to_string(home.path().join("credentials")).unwrap();
+    assert_eq!(content, WORK_LONG_TERM);
+}
+
+#[test]
+fn prompt_without_a_terminal_exits_with_8() {
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("credentials"), WORK_LONG_TERM).unwrap();
+
+    let output = aws_mfa(home.path())
+        .args(["--profile", "work", "--providers", "prompt"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Can't ask for the MFA code in a non-interactive run"), "{stderr}");
+}
+
+#[test]
+fn missing_credentials_file_exits_with_2() {
+    let home = tempfile::tempdir().unwrap();
+
+    let output = aws_mfa(home.path()).args(["--profile", "work"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Credentials file not found"), "{stderr}");
+}
+
+#[test]
+fn exit_codes_follow_the_class() {
+    let cases = [
+        (Failure::Config, 2),
+        (Failure::Token, 3),
+        (Failure::Rejected, 4),
+        (Failure::Network, 5),
+        (Failure::Write, 6),
+        (Failure::Cancelled, 7),
+        (Failure::Unattended, 8),
+    ];
+    for (failure, code) in cases {
+        let error = failure.wrap(anyhow!("failed"));
+        assert_eq!(Failure::of(&error), Some(failure));
+        assert_eq!(Failure::exit_code(&error), ExitCode::from(code));
+    }
+    assert_eq!(Failure::exit_code(&anyhow!("unclassified")), ExitCode::from(1));
+}
+
+#[test]
+fn first_class_attached_wins() {
+    let error = Err::<(), _>(anyhow!("bad code"))
+        .failure(Failure::Token)
+        .context("Failed to refresh")
+        .failure(Failure::Network)
+        .unwrap_err();
+    assert_eq!(Failure::of(&error), Some(Failure::Token));
+}
+
+/// Returns an error response of STS with `code`.
+fn service_error(code: &str) -> SdkError<GetCallerIdentityError, HttpResponse> {
+    let error = GetCallerIdentityError::generic(ErrorMetadata::builder().code(code).build());
+    let response = HttpResponse::new(403.try_into().unwrap(), SdkBody::empty());
+    SdkError::service_error(error, response)
+}
+
+#[test]
+fn sdk_errors_are_rejections_or_network_failures() {
+    let rejected = Failure::sdk(service_error("AccessDenied"));
+    assert_eq!(Failure::of(&rejected), Some(Failure::Rejected));
+
+    let timeout = SdkError::<GetCallerIdentityError, HttpResponse>::timeout_error("timed out");
+    assert_eq!(Failure::of(&Failure::sdk(timeout)), Some(Failure::Network));
+
+    let refused = ConnectorError::io(io::Error::from(ErrorKind::ConnectionRefused).into());
+    let dispatch = SdkError::<GetCallerIdentityError, HttpResponse>::dispatch_failure(refused);
+    assert_eq!(Failure::of(&Failure::sdk(dispatch)), Some(Failure::Network));
+
+    let response = HttpResponse::new(200.try_into().unwrap(), SdkBody::from("<garbage"));
+    let garbled = SdkError::<GetCallerIdentityError, HttpResponse>::response_error(
+        "unparseable response",
+        response,
+    );
+    assert_eq!(Failure::of(&Failure::sdk(garbled)), Some(Failure::Network));
+}
+
+#[test]
+fn classified_errors_keep_the_wrapped_error_in_the_chain() {
+    let error = Failure::Write.wrap(io::Error::from(ErrorKind::Interrupted));
+    let source = error.source().expect("the wrapped error is the source");
+    assert_eq!(
+        source.downcast_ref::<io::Error>().map(io::Error::kind),
+        Some(ErrorKind::Interrupted)
+    );
+    assert!(is_transient(&error));
+
+    let error = Failure::Write
+        .wrap(anyhow!(io::Error::from(ErrorKind::TimedOut)).context("Failed to write"))
+        .context("Failed to save the session");
+    assert!(is_transient(&error));
+    assert_eq!(
+        Report(&error).to_string(),
+        "Failed to save the session: Failed to write: timed out"
+    );
+}
+
+#[test]
+fn sdk_errors_keep_their_source() {
+    let error = Failure::sdk(service_error("ExpiredToken"));
+    let sdk = error
+        .chain()
+        .find_map(|e| e.downcast_ref::<SdkError<GetCallerIdentityError, HttpResponse>>());
+    assert!(sdk.is_some_and(|e| e.as_service_error().is_some()));
+    assert!(!is_transient(&error));
+}
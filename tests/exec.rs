@@ -0,0 +1,56 @@
+//! `exec` handing a session to a command, run as the binary against a stub STS endpoint.
+#![cfg(unix)]
+
+mod common;
+
+use std::{fs, path::Path, process::Output};
+
+use common::{Stub, WORK_LONG_TERM, aws_mfa, sessions};
+
+/// Runs `aws-mfa --profile work <args> exec <exec_args> -- sh -c ...` against `stub`, printing
+/// the access key ID the command gets.
+async fn exec(home: &Path, stub: &Stub, exec_args: &[&str]) -> Output {
+    let mut command = aws_mfa(home);
+    command
+        .env("AWS_ENDPOINT_URL_STS", stub.endpoint())
+        .env("AWS_REGION", "eu-west-1")
+        .args(["--profile", "work", "--token", "123456", "exec"])
+        .args(exec_args)
+        .args(["--", "sh", "-c", "printf %s \"$AWS_ACCESS_KEY_ID\""]);
+    tokio::process::Command::from(command).output().await.unwrap()
+}
+
+#[tokio::test]
+async fn new_session_is_handed_to_the_command_only() {
+    let stub = Stub::start(sessions).await;
+    let home = tempfile::tempdir().unwrap();
+    let credentials = home.path().join("credentials");
+    fs::write(&credentials, WORK_LONG_TERM).unwrap();
+
+    for args in [&[][..], &["--no-write"]] {
+        let output = exec(home.path(), &stub, args).await;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ASIASESSION");
+        assert_eq!(fs::read_to_string(&credentials).unwrap(), WORK_LONG_TERM, "{args:?}");
+    }
+    // Nothing stored, so each run gets a session of its own.
+    assert_eq!(stub.actions(), ["GetSessionToken", "GetSessionToken"]);
+}
+
+#[tokio::test]
+async fn write_stores_the_session_for_the_next_run() {
+    let stub = Stub::start(sessions).await;
+    let home = tempfile::tempdir().unwrap();
+    let credentials = home.path().join("credentials");
+    fs::write(&credentials, WORK_LONG_TERM).unwrap();
+
+    let output = exec(home.path(), &stub, &["--write"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let written = fs::read_to_string(&credentials).unwrap();
+    assert!(written.contains("[work]\naws_access_key_id=ASIASESSION\n"), "{written}");
+
+    // The stored session is reused without asking STS again.
+    let output = exec(home.path(), &stub, &[]).await;
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ASIASESSION");
+    assert_eq!(stub.actions(), ["GetSessionToken"]);
+}
@@ -0,0 +1,55 @@
+//! Refreshes of hand-edited credentials files, compared byte for byte with golden files.
+//!
+//! Each `tests/fixtures/refresh/<name>.before.ini` is refreshed with the session of the stub STS
+//! and must then equal `<name>.after.ini`: only the managed keys of the session section change.
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use aws_mfa::{
+    permissions::Policy,
+    updater::{AwsMfaUpdater, Profile},
+};
+use common::{Stub, sessions};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/refresh")
+        .join(name)
+}
+
+/// Refreshes the profile `work` in a copy of the `name` fixture and compares it with the golden
+/// file.
+async fn refresh(name: &str) {
+    let stub = Stub::start(sessions).await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::copy(fixture(&format!("{name}.before.ini")), &path).unwrap();
+
+    AwsMfaUpdater::new(Some(path.clone()), Profile::new("work"), None, Policy::Warn)
+        .unwrap()
+        .with_sts_client(stub.sts())
+        .update_credentials("123456")
+        .await
+        .unwrap();
+
+    let golden = fs::read_to_string(fixture(&format!("{name}.after.ini"))).unwrap();
+    let refreshed = fs::read_to_string(&path).unwrap();
+    assert_eq!(refreshed, golden, "{name}");
+}
+
+#[tokio::test]
+async fn comments_blank_lines_and_other_sections_survive_a_refresh() {
+    refresh("commented").await;
+}
+
+#[tokio::test]
+async fn crlf_line_endings_are_kept_for_new_lines_too() {
+    refresh("crlf").await;
+}
+
+#[tokio::test]
+async fn missing_session_section_is_appended_after_the_rest() {
+    refresh("new-session").await;
+}
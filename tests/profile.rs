@@ -0,0 +1,86 @@
+//! Which profile a run works on: `--profile`, then `AWS_PROFILE`, then the only one there is.
+
+mod common;
+
+use std::{fs, path::Path, process::Output};
+
+use common::{ACCESS_KEY_ID, Stub, WORK_LONG_TERM, aws_mfa, sessions};
+use tempfile::TempDir;
+
+/// Returns a home directory with the long-term sections of profiles `work` and `other`.
+fn home() -> TempDir {
+    let home = tempfile::tempdir().unwrap();
+    let other = WORK_LONG_TERM.replace("[work-long-term]", "[other-long-term]");
+    fs::write(home.path().join("credentials"), format!("{WORK_LONG_TERM}{other}")).unwrap();
+    home
+}
+
+/// Runs a dry run with `AWS_PROFILE` set to `env` and `args`, and returns the section the
+/// session would be written to, the log and the output.
+fn planned(home: &Path, env: Option<&str>, args: &[&str]) -> (Option<String>, String, Output) {
+    let mut command = aws_mfa(home);
+    command.arg("--dry-run").args(args);
+    if let Some(profile) = env {
+        command.env("AWS_PROFILE", profile);
+    }
+    let output = command.output().unwrap();
+    let section = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| Some(line.strip_prefix("+[")?.strip_suffix(']')?.to_string()));
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    (section, stderr, output)
+}
+
+#[test]
+fn aws_profile_selects_the_sections() {
+    let home = home();
+
+    let (section, stderr, _) = planned(home.path(), Some("work"), &[]);
+
+    assert_eq!(section.as_deref(), Some("work"));
+    assert!(stderr.contains("Using profile work (from AWS_PROFILE)"), "{stderr}");
+}
+
+#[test]
+fn profile_flag_beats_aws_profile() {
+    let home = home();
+
+    let (section, stderr, _) = planned(home.path(), Some("work"), &["--profile", "other"]);
+
+    assert_eq!(section.as_deref(), Some("other"));
+    assert!(stderr.contains("Using profile other (from --profile)"), "{stderr}");
+}
+
+#[test]
+fn empty_aws_profile_is_no_choice() {
+    let home = home();
+
+    let (section, stderr, output) = planned(home.path(), Some(""), &[]);
+
+    assert_eq!(section, None);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr.contains("pick one with --profile or AWS_PROFILE"), "{stderr}");
+}
+
+#[tokio::test]
+async fn aws_profile_is_not_resolved_by_the_sdk() {
+    let stub = Stub::start(sessions).await;
+    let home = home();
+    // Were the SDK to resolve profile work, it would run this instead of signing with the keys.
+    fs::write(home.path().join("config"), "[profile work]\ncredential_process = false\n").unwrap();
+    let mut command = aws_mfa(home.path());
+    command
+        .env("AWS_PROFILE", "work")
+        .env("AWS_ENDPOINT_URL_STS", stub.endpoint())
+        .env("AWS_REGION", "eu-west-1")
+        .args(["--token", "123456"]);
+
+    let output = tokio::process::Command::from(command).output().await.unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let requests = stub.requests();
+    assert_eq!(requests[0].action(), "GetSessionToken");
+    assert_eq!(requests[0].signed_by, ACCESS_KEY_ID);
+    let written = fs::read_to_string(home.path().join("credentials")).unwrap();
+    assert!(written.contains("[work]\naws_access_key_id=ASIASESSION\n"), "{written}");
+}
@@ -0,0 +1,15 @@
+//! When the progress spinner is drawn.
+
+use aws_mfa::progress::visible;
+
+#[test]
+fn spinner_is_only_drawn_for_people_watching_a_terminal() {
+    let term = Some("xterm-256color");
+    assert!(visible(true, term, false, false));
+
+    // JSON is read by another program, which may capture stderr too.
+    assert!(!visible(true, term, true, false));
+    assert!(!visible(true, term, false, true));
+    assert!(!visible(false, term, false, false));
+    assert!(!visible(true, Some("dumb"), false, false));
+}
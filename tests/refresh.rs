@@ -0,0 +1,81 @@
+//! Refreshes skipped while the session in the credentials file is valid long enough, run as the
+//! binary against a stub STS endpoint.
+
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    process::Output,
+    time::{Duration, SystemTime},
+};
+
+use aws_smithy_types::{DateTime, date_time::Format};
+use common::{EXPIRATION, Stub, WORK_LONG_TERM, aws_mfa, sessions};
+use tempfile::TempDir;
+
+/// Returns a home directory with a session of profile `work` expiring at `expiration`.
+fn home(expiration: &str) -> TempDir {
+    let home = tempfile::tempdir().unwrap();
+    let session = format!(
+        "\n[work]\naws_access_key_id=ASIAOLDSESSION\naws_secret_access_key=secret\n\
+         aws_session_token=token\nexpiration={expiration}\n"
+    );
+    fs::write(home.path().join("credentials"), format!("{WORK_LONG_TERM}{session}")).unwrap();
+    home
+}
+
+/// Refreshes profile `work` with `args` against `stub`.
+async fn refresh(home: &Path, stub: &Stub, args: &[&str]) -> Output {
+    let mut command = aws_mfa(home);
+    command
+        .env("AWS_ENDPOINT_URL_STS", stub.endpoint())
+        .env("AWS_REGION", "eu-west-1")
+        .args(["--profile", "work", "--token", "123456"])
+        .args(args);
+    let output = tokio::process::Command::from(command).output().await.unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[tokio::test]
+async fn valid_session_is_kept_without_asking_sts() {
+    let stub = Stub::start(sessions).await;
+    let home = home(EXPIRATION);
+    let before = fs::read_to_string(home.path().join("credentials")).unwrap();
+
+    let output = refresh(home.path(), &stub, &[]).await;
+
+    assert!(stub.requests().is_empty());
+    assert_eq!(fs::read_to_string(home.path().join("credentials")).unwrap(), before);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("skipping the refresh"), "{stderr}");
+}
+
+#[tokio::test]
+async fn session_about_to_expire_is_refreshed() {
+    let stub = Stub::start(sessions).await;
+    let soon = SystemTime::now() + Duration::from_secs(5 * 60);
+    let home = home(&DateTime::from(soon).fmt(Format::DateTime).unwrap());
+
+    // Valid for 5 more minutes, short of the 10 minutes asked for by default, but not of 1.
+    refresh(home.path(), &stub, &["--min-remaining", "1m"]).await;
+    assert!(stub.requests().is_empty());
+    refresh(home.path(), &stub, &[]).await;
+
+    assert_eq!(stub.actions(), ["GetSessionToken"]);
+    let written = fs::read_to_string(home.path().join("credentials")).unwrap();
+    assert!(written.contains("aws_access_key_id=ASIASESSION\n"), "{written}");
+}
+
+#[tokio::test]
+async fn force_refreshes_a_valid_session() {
+    let stub = Stub::start(sessions).await;
+    let home = home(EXPIRATION);
+
+    refresh(home.path(), &stub, &["--force"]).await;
+
+    assert_eq!(stub.actions(), ["GetSessionToken"]);
+    let written = fs::read_to_string(home.path().join("credentials")).unwrap();
+    assert!(written.contains("aws_access_key_id=ASIASESSION\n"), "{written}");
+}
@@ -0,0 +1,237 @@
+//! Rotation of the long-term access key against a stub of IAM and STS, failing at each step.
+
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use aws_mfa::{
+    error::{Failure, Report},
+    permissions::Policy,
+    rotate::rotate_key,
+    updater::{AwsMfaUpdater, Profile},
+};
+use common::{ACCESS_KEY_ID, Reply, Request, Stub, WORK_LONG_TERM, sessions};
+use tempfile::TempDir;
+
+const NEW_KEY_ID: &str = "AKIANEWKEYEXAMPLE000";
+const NEW_SECRET: &str = "new-secret-access-key";
+
+fn credentials_file() -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, WORK_LONG_TERM).unwrap();
+    (dir, path)
+}
+
+fn updater(path: &Path, stub: &Stub) -> AwsMfaUpdater {
+    AwsMfaUpdater::new(Some(path.to_path_buf()), Profile::new("work"), None, Policy::Warn)
+        .unwrap()
+        .with_sts_client(stub.sts())
+}
+
+/// Answers like IAM and STS letting the key be rotated.
+fn rotation(request: &Request) -> Reply {
+    let action = request.action();
+    match action {
+        "CreateAccessKey" => Reply::result(
+            action,
+            &format!(
+                "<AccessKey><UserName>alice</UserName><AccessKeyId>{NEW_KEY_ID}</AccessKeyId>\
+                 <Status>Active</Status><SecretAccessKey>{NEW_SECRET}</SecretAccessKey>\
+                 </AccessKey>"
+            ),
+        ),
+        "UpdateAccessKey" | "DeleteAccessKey" => Reply::ok(format!("<{action}Response/>")),
+        _ => sessions(request),
+    }
+}
+
+/// Answers like [`rotation`], but `action` fails with an IAM or STS error `code`.
+fn failing(action: &'static str, code: &'static str) -> impl Fn(&Request) -> Reply {
+    move |request| match request.action() {
+        failed if failed == action => Reply::error(403, code, "Not today"),
+        _ => rotation(request),
+    }
+}
+
+/// Returns each action requested of the stub with the access key it concerns, if any.
+fn steps(stub: &Stub) -> Vec<String> {
+    let step = |r: &Request| match r.param("AccessKeyId") {
+        Some(key) => format!("{} {key}", r.action()),
+        None => r.action().to_string(),
+    };
+    stub.requests().iter().map(step).collect()
+}
+
+#[tokio::test]
+async fn new_key_is_written_and_verified_before_the_old_one_is_deleted() {
+    let stub = Stub::start(rotation).await;
+    let (_dir, path) = credentials_file();
+
+    rotate_key(&updater(&path, &stub), &stub.iam(), false, |_| Ok(true))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        steps(&stub),
+        [
+            "CreateAccessKey".to_string(),
+            "GetCallerIdentity".to_string(),
+            format!("UpdateAccessKey {ACCESS_KEY_ID}"),
+            format!("DeleteAccessKey {ACCESS_KEY_ID}"),
+        ]
+    );
+    let requests = stub.requests();
+    assert_eq!(requests[1].signed_by, NEW_KEY_ID);
+    assert_eq!(requests[2].param("Status"), Some("Inactive"));
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(written.contains(&format!("aws_access_key_id = {NEW_KEY_ID}")), "{written}");
+    assert!(written.contains(&format!("aws_secret_access_key = {NEW_SECRET}")));
+}
+
+#[tokio::test]
+async fn failed_creation_changes_nothing() {
+    let stub = Stub::start(failing("CreateAccessKey", "AccessDenied")).await;
+    let (_dir, path) = credentials_file();
+
+    let error = rotate_key(&updater(&path, &stub), &stub.iam(), false, |_| Ok(true))
+        .await
+        .unwrap_err();
+
+    assert_eq!(Failure::of(&error), Some(Failure::Rejected));
+    assert!(
+        Report(&error)
+            .to_string()
+            .starts_with("Failed to create a new access key")
+    );
+    assert_eq!(steps(&stub), ["CreateAccessKey"]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), WORK_LONG_TERM);
+}
+
+#[tokio::test]
+async fn second_key_over_the_limit_names_the_one_to_delete() {
+    let stub = Stub::start(|request: &Request| match request.action() {
+        "CreateAccessKey" => Reply::error(409, "LimitExceeded", "Cannot exceed quota"),
+        "ListAccessKeys" => Reply::result(
+            "ListAccessKeys",
+            &format!(
+                "<AccessKeyMetadata>\
+                 <member><AccessKeyId>{ACCESS_KEY_ID}</AccessKeyId></member>\
+                 <member><AccessKeyId>AKIAOTHERKEYEXAMPLE0</AccessKeyId></member>\
+                 </AccessKeyMetadata><IsTruncated>false</IsTruncated>"
+            ),
+        ),
+        _ => rotation(request),
+    })
+    .await;
+    let (_dir, path) = credentials_file();
+
+    let error = rotate_key(&updater(&path, &stub), &stub.iam(), false, |_| Ok(true))
+        .await
+        .unwrap_err();
+
+    assert!(
+        error
+            .to_string()
+            .ends_with("aws iam delete-access-key --access-key-id AKIAOTHERKEYEXAMPLE0"),
+        "{error}"
+    );
+    assert_eq!(steps(&stub), ["CreateAccessKey", "ListAccessKeys"]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), WORK_LONG_TERM);
+}
+
+#[tokio::test]
+async fn failed_write_never_deletes_the_old_key() {
+    let stub = Stub::start(rotation).await;
+    let (_dir, path) = credentials_file();
+    let updater = updater(&path, &stub);
+    // Writing the new key, and restoring the old one, fail once the file can't be read.
+    fs::remove_file(&path).unwrap();
+    fs::create_dir(&path).unwrap();
+
+    let error = rotate_key(&updater, &stub.iam(), false, |_| Ok(true))
+        .await
+        .unwrap_err();
+
+    let report = Report(&error).to_string();
+    assert!(report.starts_with("Failed to restore the old access key"), "{report}");
+    assert!(report.contains("`aws-mfa --fix`"));
+    assert_eq!(steps(&stub), ["CreateAccessKey"]);
+}
+
+#[tokio::test]
+async fn unusable_new_key_is_deleted_and_the_old_one_restored() {
+    let stub = Stub::start(failing("GetCallerIdentity", "AccessDenied")).await;
+    let (_dir, path) = credentials_file();
+
+    let error = rotate_key(&updater(&path, &stub), &stub.iam(), false, |_| Ok(true))
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.to_string(), "The new access key didn't work");
+    assert_eq!(
+        steps(&stub),
+        [
+            "CreateAccessKey".to_string(),
+            "GetCallerIdentity".to_string(),
+            format!("DeleteAccessKey {NEW_KEY_ID}")
+        ]
+    );
+    let restored = fs::read_to_string(&path).unwrap();
+    assert!(restored.contains(&format!("aws_access_key_id = {ACCESS_KEY_ID}")), "{restored}");
+    assert!(!restored.contains(NEW_KEY_ID));
+}
+
+#[tokio::test]
+async fn new_key_unknown_to_sts_at_first_is_tried_again() {
+    let unknown = AtomicBool::new(true);
+    let stub = Stub::start(move |request: &Request| match request.action() {
+        "GetCallerIdentity" if unknown.swap(false, Ordering::SeqCst) => {
+            Reply::error(403, "InvalidClientTokenId", "The security token is invalid")
+        }
+        _ => rotation(request),
+    })
+    .await;
+    let (_dir, path) = credentials_file();
+
+    rotate_key(&updater(&path, &stub), &stub.iam(), true, |_| Ok(true))
+        .await
+        .unwrap();
+
+    assert_eq!(steps(&stub), ["CreateAccessKey", "GetCallerIdentity", "GetCallerIdentity"]);
+}
+
+#[tokio::test]
+async fn failed_deletion_of_the_old_key_keeps_the_new_one() {
+    let stub = Stub::start(failing("DeleteAccessKey", "AccessDenied")).await;
+    let (_dir, path) = credentials_file();
+
+    let error = rotate_key(&updater(&path, &stub), &stub.iam(), false, |_| Ok(true))
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        format!("Deactivated but failed to delete the old access key {ACCESS_KEY_ID}")
+    );
+    assert_eq!(Failure::of(&error), Some(Failure::Rejected));
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(written.contains(&format!("aws_access_key_id = {NEW_KEY_ID}")), "{written}");
+}
+
+#[tokio::test]
+async fn declined_deletion_keeps_both_keys() {
+    let stub = Stub::start(rotation).await;
+    let (_dir, path) = credentials_file();
+
+    rotate_key(&updater(&path, &stub), &stub.iam(), false, |_| Ok(false))
+        .await
+        .unwrap();
+
+    assert_eq!(steps(&stub), ["CreateAccessKey", "GetCallerIdentity"]);
+    assert!(fs::read_to_string(&path).unwrap().contains(NEW_KEY_ID));
+}
@@ -0,0 +1,191 @@
+//! The metadata server endpoints, requested over HTTP like an SDK would.
+
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use aws_mfa::{
+    permissions::Policy,
+    server::Server,
+    updater::{AwsMfaUpdater, Profile},
+};
+use common::{EXPIRATION, Reply, Stub, WORK_LONG_TERM, sessions};
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+use tempfile::TempDir;
+
+const CREDENTIALS: &str = "/latest/meta-data/iam/security-credentials/";
+
+/// Sessions with less than this left are refreshed before being served.
+const MIN_REMAINING: Duration = Duration::from_secs(600);
+
+/// Returns a directory with a credentials file holding [`WORK_LONG_TERM`] and `session`, and its
+/// path.
+fn credentials_file(session: &str) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, format!("{WORK_LONG_TERM}\n{session}")).unwrap();
+    (dir, path)
+}
+
+fn session(expiration: &str) -> String {
+    format!(
+        "[work]\naws_access_key_id=ASIAOLD\naws_secret_access_key=secret-of-ASIAOLD\n\
+         aws_session_token=token-of-ASIAOLD\nexpiration={expiration}\n"
+    )
+}
+
+fn updater(path: &Path, stub: &Stub) -> AwsMfaUpdater {
+    AwsMfaUpdater::new(Some(path.to_path_buf()), Profile::new("work"), None, Policy::Warn)
+        .unwrap()
+        .with_sts_client(stub.sts())
+}
+
+/// A token provider that counts the codes it gave out.
+fn counted() -> (Arc<AtomicUsize>, impl Fn() -> anyhow::Result<String> + Clone + Send) {
+    let asked = Arc::new(AtomicUsize::new(0));
+    let counter = asked.clone();
+    (asked, move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok("123456".to_string())
+    })
+}
+
+/// Sends a `method` request for `path` below the endpoint of `server`, serving it meanwhile.
+async fn request(
+    server: &Server,
+    updater: &AwsMfaUpdater,
+    get_token: impl Fn() -> anyhow::Result<String> + Clone + Send + 'static,
+    method: Method,
+    path: &str,
+) -> (StatusCode, String) {
+    let url = format!("{}{path}", server.endpoint().unwrap());
+    let response = reqwest::Client::new().request(method, url).send();
+    tokio::select! {
+        result = server.serve(updater, MIN_REMAINING, get_token) => panic!("stopped: {result:?}"),
+        response = response => {
+            let response = response.unwrap();
+            (response.status(), response.text().await.unwrap())
+        }
+    }
+}
+
+#[tokio::test]
+async fn token_and_role_name_are_served_below_the_secret() {
+    let stub = Stub::start(sessions).await;
+    let (_dir, path) = credentials_file(&session(EXPIRATION));
+    let updater = updater(&path, &stub);
+    let server = Server::bind(0, Some("s3cret".into())).await.unwrap();
+    let (asked, get_token) = counted();
+
+    let (status, token) =
+        request(&server, &updater, get_token.clone(), Method::PUT, "/latest/api/token").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(token.len(), 32);
+    assert!(token.chars().all(|c| c.is_ascii_hexdigit()), "{token}");
+
+    let (status, roles) = request(&server, &updater, get_token, Method::GET, CREDENTIALS).await;
+    assert_eq!((status, roles.as_str()), (StatusCode::OK, "aws-mfa"));
+    assert_eq!(asked.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn paths_outside_the_secret_are_not_found() {
+    let stub = Stub::start(sessions).await;
+    let (_dir, path) = credentials_file(&session(EXPIRATION));
+    let updater = updater(&path, &stub);
+    let server = Server::bind(0, Some("s3cret".into())).await.unwrap();
+    let (_, get_token) = counted();
+    let endpoint = server.endpoint().unwrap();
+    let url = format!("{}{CREDENTIALS}aws-mfa", endpoint.strip_suffix("/s3cret").unwrap());
+    let response = reqwest::get(url);
+    let status = tokio::select! {
+        result = server.serve(&updater, MIN_REMAINING, get_token) => panic!("stopped: {result:?}"),
+        response = response => response.unwrap().status(),
+    };
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(stub.requests().is_empty());
+}
+
+#[tokio::test]
+async fn fresh_session_is_served_in_the_metadata_format() {
+    let stub = Stub::start(sessions).await;
+    let (_dir, path) = credentials_file(&session(EXPIRATION));
+    let updater = updater(&path, &stub);
+    let server = Server::bind(0, None).await.unwrap();
+    let (asked, get_token) = counted();
+
+    let role = format!("{CREDENTIALS}aws-mfa");
+    let (status, body) = request(&server, &updater, get_token, Method::GET, &role).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let document: Value = serde_json::from_str(&body).unwrap();
+    let mut keys: Vec<_> = document.as_object().unwrap().keys().cloned().collect();
+    keys.sort();
+    let expected =
+        ["AccessKeyId", "Code", "Expiration", "LastUpdated", "SecretAccessKey", "Token", "Type"];
+    assert_eq!(keys, expected);
+    assert_eq!(document["Code"], "Success");
+    assert_eq!(document["Type"], "AWS-HMAC");
+    assert_eq!(document["AccessKeyId"], "ASIAOLD");
+    assert_eq!(document["SecretAccessKey"], "secret-of-ASIAOLD");
+    assert_eq!(document["Token"], "token-of-ASIAOLD");
+    assert_eq!(document["Expiration"], EXPIRATION);
+    assert_eq!(asked.load(Ordering::SeqCst), 0);
+    assert!(stub.requests().is_empty());
+}
+
+#[tokio::test]
+async fn expiring_session_is_refreshed_before_being_served() {
+    let stub = Stub::start(sessions).await;
+    let (_dir, path) = credentials_file(&session("2001-05-03T19:21:04Z"));
+    let updater = updater(&path, &stub);
+    let server = Server::bind(0, None).await.unwrap();
+    let (asked, get_token) = counted();
+
+    let role = format!("{CREDENTIALS}aws-mfa");
+    let (status, body) = request(&server, &updater, get_token.clone(), Method::GET, &role).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let document: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(document["AccessKeyId"], "ASIASESSION");
+    assert_eq!(document["Expiration"], EXPIRATION);
+    assert_eq!(stub.actions(), ["GetSessionToken"]);
+    assert_eq!(stub.requests()[0].param("TokenCode"), Some("123456"));
+    assert!(
+        fs::read_to_string(&path)
+            .unwrap()
+            .contains("aws_access_key_id=ASIASESSION")
+    );
+
+    // The refreshed session is served as is from then on.
+    request(&server, &updater, get_token, Method::GET, &role).await;
+    assert_eq!(asked.load(Ordering::SeqCst), 1);
+    assert_eq!(stub.actions().len(), 1);
+}
+
+#[tokio::test]
+async fn failed_refresh_is_a_server_error() {
+    let stub =
+        Stub::start(|_| Reply::error(403, "AccessDenied", "MultiFactorAuthentication failed"))
+            .await;
+    let (_dir, path) = credentials_file("");
+    let updater = updater(&path, &stub);
+    let server = Server::bind(0, None).await.unwrap();
+    let (_, get_token) = counted();
+
+    let role = format!("{CREDENTIALS}aws-mfa");
+    let (status, body) = request(&server, &updater, get_token, Method::GET, &role).await;
+
+    assert_eq!((status, body.as_str()), (StatusCode::INTERNAL_SERVER_ERROR, ""));
+    assert_eq!(fs::read_to_string(&path).unwrap(), format!("{WORK_LONG_TERM}\n"));
+}
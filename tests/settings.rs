@@ -0,0 +1,164 @@
+//! Where the settings of a refresh come from: flags, then the environment, then the long-term
+//! section, then the aws-mfa config file, then the defaults. Seen in the plan of a dry run.
+
+mod common;
+
+use std::{fs, path::Path, process::Command, time::SystemTime};
+
+use aws_smithy_types::{DateTime, date_time::Format};
+use common::{WORK_LONG_TERM, aws_mfa};
+use tempfile::TempDir;
+
+const CONFIG: &str = "[profiles.work]\nduration = 3600\ntarget_profile = \"work-config\"\n";
+
+/// Returns a home directory with [`WORK_LONG_TERM`] plus `long_term` in the credentials file,
+/// and `config` as the aws-mfa config file.
+fn home(long_term: &str, config: Option<&str>) -> TempDir {
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("credentials"), format!("{WORK_LONG_TERM}{long_term}")).unwrap();
+    if let Some(config) = config {
+        let dir = home.path().join(".config/aws-mfa");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.toml"), config).unwrap();
+    }
+    home
+}
+
+/// Returns the dry run of refreshing profile `work`.
+fn dry_run(home: &Path) -> Command {
+    let mut command = aws_mfa(home);
+    command.args(["--profile", "work", "--dry-run"]);
+    command
+}
+
+/// Runs `command` and returns the section the session would be written to and its duration.
+fn planned(command: &mut Command) -> (String, i64) {
+    let started = DateTime::from(SystemTime::now()).secs();
+    let output = command.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let section = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("+[")?.strip_suffix(']'))
+        .unwrap_or_else(|| panic!("no session section planned: {stdout}"));
+    let expiration = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("+expiration="))
+        .unwrap_or_else(|| panic!("no expiration planned: {stdout}"));
+    let expiration = DateTime::from_str(expiration, Format::DateTime).unwrap().secs();
+    // Round off the seconds the run took.
+    let duration = (expiration - started + 30) / 60 * 60;
+    (section.to_string(), duration)
+}
+
+#[test]
+fn defaults_apply_without_settings() {
+    let home = home("", None);
+
+    assert_eq!(planned(&mut dry_run(home.path())), ("work".into(), 43200));
+}
+
+#[test]
+fn config_file_beats_the_defaults() {
+    let home = home("", Some(CONFIG));
+
+    assert_eq!(planned(&mut dry_run(home.path())), ("work-config".into(), 3600));
+}
+
+#[test]
+fn long_term_section_beats_the_config_file() {
+    let home = home("aws_mfa_duration = 7200\n", Some(CONFIG));
+
+    assert_eq!(planned(&mut dry_run(home.path())), ("work-config".into(), 7200));
+}
+
+#[test]
+fn environment_beats_the_config_file() {
+    let home = home("", Some(CONFIG));
+    let mut command = dry_run(home.path());
+    command
+        .env("AWS_SESSION_DURATION", "14400")
+        .env("AWS_MFA_TARGET_PROFILE", "work-env");
+
+    assert_eq!(planned(&mut command), ("work-env".into(), 14400));
+}
+
+#[test]
+fn flags_beat_the_environment() {
+    let home = home("", Some(CONFIG));
+    let mut command = dry_run(home.path());
+    command
+        .env("AWS_SESSION_DURATION", "14400")
+        .env("AWS_MFA_TARGET_PROFILE", "work-env")
+        .args(["--duration", "900", "--target-profile", "work-flag"]);
+
+    assert_eq!(planned(&mut command), ("work-flag".into(), 900));
+}
+
+#[test]
+fn config_file_names_the_mfa_device_the_credentials_file_lacks() {
+    let without_device = WORK_LONG_TERM.replace("aws_mfa_device", "; aws_mfa_device");
+    let config = "[profiles.work]\nmfa_serial = \"arn:aws:iam::123456789012:mfa/yubikey\"\n";
+    let home = home("", Some(config));
+    fs::write(home.path().join("credentials"), &without_device).unwrap();
+
+    assert_eq!(planned(&mut dry_run(home.path())).0, "work");
+
+    fs::remove_file(home.path().join(".config/aws-mfa/config.toml")).unwrap();
+    let output = dry_run(home.path()).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Missing aws_mfa_device in [work-long-term]"), "{stderr}");
+}
+
+#[test]
+fn misspelled_setting_fails_naming_the_file() {
+    let home = home("", Some("[profiles.work]\ndurration = 3600\n"));
+
+    let output = dry_run(home.path()).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("config.toml"), "{stderr}");
+    assert!(stderr.contains("unknown field `durration`"), "{stderr}");
+}
+
+#[test]
+fn config_file_can_be_named_in_the_environment() {
+    let home = home("", None);
+    let path = home.path().join("aws-mfa.toml");
+    fs::write(&path, CONFIG).unwrap();
+    let mut command = dry_run(home.path());
+    command.env("AWS_MFA_CONFIG_FILE", &path);
+
+    assert_eq!(planned(&mut command), ("work-config".into(), 3600));
+}
+
+#[test]
+fn session_policy_applies_to_the_role_of_the_profile() {
+    let role = "role_arn = arn:aws:iam::123456789012:role/admin\n";
+    let home = home(role, None);
+    let mut command = dry_run(home.path());
+    command.args(["--session-policy-arn", "arn:aws:iam::aws:policy/ReadOnlyAccess"]);
+
+    assert_eq!(planned(&mut command).0, "work");
+}
+
+#[test]
+fn session_policy_without_a_role_fails_naming_get_session_token() {
+    let home = home("", None);
+    let mut command = dry_run(home.path());
+    command.args(["--session-policy-arn", "arn:aws:iam::aws:policy/ReadOnlyAccess"]);
+
+    let output = command.output().unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "--session-policy-arn only applies to assuming a role, not to GetSessionToken"
+        ),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,139 @@
+//! Where MFA codes come from, with a stub `op` and token commands, and the guard against using a
+//! code twice.
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use aws_mfa::{
+    error::Failure,
+    token::{LastUsed, Sources},
+};
+
+/// Writes an executable script `body` to `path`.
+fn script(path: &Path, body: &str) {
+    fs::write(path, format!("#!/bin/sh\n{body}")).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn op_reads_the_code_of_a_secret_reference() {
+    let dir = tempfile::tempdir().unwrap();
+    let op = dir.path().join("op");
+    // Only knows the reference with the attribute. The code changes every second, so that it
+    // is never one already used.
+    script(
+        &op,
+        "[ \"$1 $2\" = 'read op://Private/AWS/one-time password?attribute=otp' ] || exit 1\n\
+         date +%H%M%S\n",
+    );
+    let sources = |reference: &str| {
+        Sources::default()
+            .with_op_reference(Some(reference.parse().unwrap()))
+            // The account and item are ignored once a reference is set.
+            .with_one_password(Some("my.1password.com".into()), Some("AWS".into()))
+            .with_op_program(Some(op.clone()))
+            .with_last_used(LastUsed::at(None))
+    };
+    let typed = || Ok("654321".to_string());
+
+    let code = sources("op://Private/AWS/one-time password")
+        .get_token(&typed)
+        .unwrap();
+    assert!(code.len() == 6 && code != "654321", "{code}");
+    // Codes op can't read are entered by hand.
+    let code = sources("op://Private/GitHub/one-time password")
+        .get_token(&typed)
+        .unwrap();
+    assert_eq!(code, "654321");
+}
+
+/// Returns sources asking `command` for the code, then the prompt unless `unattended`.
+fn command(command: &str, unattended: bool) -> Sources {
+    Sources::default()
+        .with_providers("command,prompt".parse().unwrap())
+        .with_command(Some(command.to_string()))
+        .with_unattended(unattended)
+        // Takes the code whenever it is printed, rather than waiting for a fresh window.
+        .with_min_validity(Duration::ZERO)
+        .with_last_used(LastUsed::at(None))
+}
+
+#[test]
+fn token_command_provides_the_code() {
+    let typed = || -> anyhow::Result<String> { panic!("the prompt isn't needed") };
+
+    let code = command("echo 314159", true).get_token(&typed).unwrap();
+    assert_eq!(code, "314159");
+}
+
+#[test]
+fn failing_token_command_falls_back_to_the_prompt() {
+    let typed = || Ok("271828".to_string());
+
+    let code = command("exit 3", false).get_token(&typed).unwrap();
+    assert_eq!(code, "271828");
+    // Printing something other than a code is a failure too.
+    let code = command("echo locked", false).get_token(&typed).unwrap();
+    assert_eq!(code, "271828");
+}
+
+#[test]
+fn failing_token_command_is_reported_when_nothing_is_left_to_try() {
+    let typed = || -> anyhow::Result<String> { panic!("unattended runs never prompt") };
+
+    let error = command("exit 3", true).get_token(&typed).unwrap_err();
+    assert_eq!(Failure::of(&error), Some(Failure::Unattended));
+    let message = format!("{error:#}");
+    assert!(message.contains("Can't ask for the MFA code in a non-interactive run; tried"));
+}
+
+/// Returns the time `secs` seconds into the TOTP window starting at 1,700,000,010.
+fn into_window(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_010 + secs)
+}
+
+#[test]
+fn code_is_refused_again_within_its_window() {
+    let dir = tempfile::tempdir().unwrap();
+    let last_used = LastUsed::at(Some(dir.path().join("last-token")));
+
+    last_used.remember("112233", into_window(10));
+
+    assert!(last_used.is_reused("112233", into_window(10)));
+    assert!(last_used.is_reused("112233", into_window(29)));
+    assert!(!last_used.is_reused("445566", into_window(29)));
+    // The next invocation knows it too.
+    let next = LastUsed::at(Some(dir.path().join("last-token")));
+    assert!(next.is_reused("112233", into_window(20)));
+}
+
+#[test]
+fn code_is_allowed_once_its_window_is_over() {
+    let dir = tempfile::tempdir().unwrap();
+    let last_used = LastUsed::at(Some(dir.path().join("last-token")));
+
+    last_used.remember("778899", into_window(29));
+
+    assert!(!last_used.is_reused("778899", into_window(30)));
+    assert!(!last_used.is_reused("778899", into_window(95)));
+    // Nor is it reused in an earlier window, for a clock set back.
+    assert!(!last_used.is_reused("778899", into_window(0) - Duration::from_secs(1)));
+}
+
+#[test]
+fn used_code_is_recorded_privately() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("aws-mfa/last-token");
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    LastUsed::at(Some(path.clone())).remember("112233", into_window(10));
+
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(mode(path.parent().unwrap()), 0o700);
+}
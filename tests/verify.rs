@@ -0,0 +1,62 @@
+//! `--verify`: the new session calls STS GetCallerIdentity before it is relied on.
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use aws_mfa::{
+    error::Failure,
+    permissions::Policy,
+    updater::{AwsMfaUpdater, Profile},
+};
+use common::{Reply, Request, Stub, WORK_LONG_TERM, sessions};
+use tempfile::TempDir;
+
+/// Returns a directory with a credentials file holding [`WORK_LONG_TERM`], and its path.
+fn credentials_file() -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, WORK_LONG_TERM).unwrap();
+    (dir, path)
+}
+
+#[tokio::test]
+async fn verify_signs_with_the_new_session() {
+    let stub = Stub::start(sessions).await;
+    let (_dir, path) = credentials_file();
+    let updater = AwsMfaUpdater::new(Some(path), Profile::new("work"), None, Policy::Warn)
+        .unwrap()
+        .with_sts_client(stub.sts());
+
+    let session = updater.fetch_credentials("123456").await.unwrap();
+    let identity = updater.verify(&session).await.unwrap();
+
+    assert_eq!(identity.account, "123456789012");
+    assert_eq!(identity.arn, "arn:aws:iam::123456789012:user/alice");
+    let requests = stub.requests();
+    assert_eq!(stub.actions(), ["GetSessionToken", "GetCallerIdentity"]);
+    assert_eq!(requests[1].signed_by, "ASIASESSION");
+}
+
+#[tokio::test]
+async fn unusable_session_fails_verification() {
+    let stub = Stub::start(|request: &Request| match request.action() {
+        "GetCallerIdentity" => Reply::error(
+            403,
+            "InvalidClientTokenId",
+            "The security token included in the request is invalid.",
+        ),
+        _ => sessions(request),
+    })
+    .await;
+    let (_dir, path) = credentials_file();
+    let updater = AwsMfaUpdater::new(Some(path), Profile::new("work"), None, Policy::Warn)
+        .unwrap()
+        .with_sts_client(stub.sts());
+
+    let session = updater.fetch_credentials("123456").await.unwrap();
+    let error = updater.verify(&session).await.unwrap_err();
+
+    assert_eq!(Failure::of(&error), Some(Failure::Rejected));
+    assert!(format!("{error:#}").contains("The security token included in the request is invalid"));
+}
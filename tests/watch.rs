@@ -0,0 +1,107 @@
+//! The watch schedule, driven through a session's lifetime without waiting for it.
+
+mod common;
+
+use std::{
+    fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aws_mfa::{
+    error::Failure,
+    permissions::Policy,
+    updater::{AwsMfaUpdater, Profile},
+    watch::{self, Schedule, Step},
+};
+use common::WORK_LONG_TERM;
+
+const LEAD_TIME: Duration = Duration::from_secs(600);
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+}
+
+#[test]
+fn sleeps_until_the_lead_time_before_expiration_then_refreshes() {
+    let schedule = Schedule::new(LEAD_TIME);
+    let deadline = schedule.deadline(Some(at(3600)), at(0));
+    assert_eq!(deadline, at(3000));
+
+    // Follow the loop through the session: it sleeps in steps until the deadline.
+    let mut now = at(0);
+    let mut sleeps = 0;
+    while let Step::Sleep(duration) = Schedule::step(deadline, now) {
+        assert!(duration <= Duration::from_secs(60), "{duration:?}");
+        now += duration;
+        sleeps += 1;
+    }
+    assert_eq!((now, sleeps), (at(3000), 50));
+    assert_eq!(Schedule::step(deadline, at(2990)), Step::Sleep(Duration::from_secs(10)));
+}
+
+#[test]
+fn missed_deadline_refreshes_at_once() {
+    let schedule = Schedule::new(LEAD_TIME);
+
+    // Woken up from laptop sleep well past the deadline, or after the session expired.
+    let deadline = schedule.deadline(Some(at(3600)), at(0));
+    assert_eq!(Schedule::step(deadline, at(3300)), Step::Refresh);
+    assert_eq!(Schedule::step(deadline, at(7200)), Step::Refresh);
+    // Without a recorded session there is nothing to wait for.
+    assert_eq!(Schedule::step(schedule.deadline(None, at(0)), at(0)), Step::Refresh);
+}
+
+#[test]
+fn failed_refreshes_back_off_until_one_succeeds() {
+    let mut schedule = Schedule::new(LEAD_TIME);
+    let backoff = |schedule: &mut Schedule| schedule.failed().as_secs();
+
+    assert_eq!([30, 60, 120].map(|_| backoff(&mut schedule)), [30, 60, 120]);
+    schedule.succeeded();
+    assert_eq!(backoff(&mut schedule), 30);
+    let capped: Vec<_> = (0..10).map(|_| backoff(&mut schedule)).collect();
+    assert_eq!(capped.last(), Some(&1800));
+}
+
+#[tokio::test]
+async fn lead_time_as_long_as_the_session_is_rejected_before_refreshing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, WORK_LONG_TERM).unwrap();
+    let updater =
+        AwsMfaUpdater::new(Some(path.clone()), Profile::new("work"), Some(900), Policy::Warn)
+            .unwrap();
+
+    let get_token = || -> anyhow::Result<String> { panic!("no code should be asked for") };
+    let error = watch::run(&updater, Duration::from_secs(900), false, true, get_token)
+        .await
+        .unwrap_err();
+
+    assert_eq!(Failure::of(&error), Some(Failure::Config));
+    assert_eq!(
+        error.to_string(),
+        "A lead time of 900s leaves nothing of a 900s session; use a shorter --lead-time"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), WORK_LONG_TERM);
+}
+
+#[test]
+fn failed_refreshes_give_one_heads_up_per_session() {
+    let mut schedule = Schedule::new(LEAD_TIME);
+    let expiring = Some(at(3600));
+
+    // Two passes of the loop for the same session, the second after a failed refresh.
+    let passes: Vec<_> = (0..2)
+        .map(|_| {
+            let heads_up = schedule.heads_up(expiring);
+            schedule.failed();
+            heads_up
+        })
+        .collect();
+    assert_eq!(passes, [true, false]);
+
+    // The next session gets one of its own.
+    schedule.succeeded();
+    assert!(schedule.heads_up(Some(at(7200))));
+    assert!(!schedule.heads_up(Some(at(7200))));
+}
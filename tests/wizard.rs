@@ -0,0 +1,173 @@
+//! The setup questions, answered from a reader.
+
+mod common;
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use aws_mfa::{error::Failure, questions::Questions, updater::Profile, wizard};
+use common::{ACCESS_KEY_ID, SECRET_ACCESS_KEY};
+use tempfile::TempDir;
+
+/// IAM isn't called unless a lookup is confirmed.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns a directory and the path of a credentials file in it, not created yet.
+fn credentials_path() -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    (dir, path)
+}
+
+#[tokio::test]
+async fn wizard_writes_the_answers_once_confirmed() {
+    let (_dir, path) = credentials_path();
+    let answers = format!(
+        "{ACCESS_KEY_ID}\n{SECRET_ACCESS_KEY}\nn\narn:aws:iam::123456789012:mfa/phone\n\ny\n"
+    );
+    let mut output = Vec::new();
+    let mut questions = Questions::new(answers.as_bytes(), &mut output);
+    let missing = "Credentials file not found";
+
+    wizard::wizard(
+        &mut questions,
+        Some(path.clone()),
+        &Profile::new("work"),
+        missing,
+        false,
+        TIMEOUT,
+    )
+    .await
+    .unwrap();
+
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(written.contains(&format!("aws_access_key_id={ACCESS_KEY_ID}")), "{written}");
+    assert!(written.contains(&format!("aws_secret_access_key={SECRET_ACCESS_KEY}")));
+    assert!(written.contains("aws_mfa_device=arn:aws:iam::123456789012:mfa/phone"));
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("Credentials file not found. Let's set up profile work;"));
+    // The summary shows what will be written, but never the secret.
+    assert!(output.contains("  MFA device  phone (account 123456789012)"), "{output}");
+    assert!(output.contains("  1Password   (skipped)"));
+    assert!(!output.contains(SECRET_ACCESS_KEY));
+}
+
+#[tokio::test]
+async fn invalid_answers_are_asked_again_and_nothing_is_written_unconfirmed() {
+    let (_dir, path) = credentials_path();
+    let answers = "AKIA-nope\n\nnot-a-device\nGAHT12345678\n\nn\n";
+    let mut output = Vec::new();
+    let mut questions = Questions::new(answers.as_bytes(), &mut output);
+    let missing = "Credentials file not found";
+
+    let error = wizard::wizard(
+        &mut questions,
+        Some(path.clone()),
+        &Profile::new("work"),
+        missing,
+        false,
+        TIMEOUT,
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(error.to_string(), "Setup cancelled; nothing was written");
+    assert!(!path.exists());
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("Access key ID (AKIA…): ").count(), 2, "{output}");
+    assert_eq!(output.matches("MFA device ARN").count(), 2);
+    assert!(output.contains("  Access key  (skipped)\n  MFA device  hardware token GAHT12345678"));
+}
+
+#[tokio::test]
+async fn keys_left_by_aws_configure_are_moved_and_the_device_asked_for() {
+    let (_dir, path) = credentials_path();
+    let configured = format!(
+        "[work]\naws_access_key_id = {ACCESS_KEY_ID}\naws_secret_access_key = {SECRET_ACCESS_KEY}\n"
+    );
+    fs::write(&path, &configured).unwrap();
+    let mut questions = Questions::new("n\nGAHT12345678\n".as_bytes(), Vec::new());
+
+    wizard::migrate_keys(&mut questions, Some(path.clone()), &Profile::new("work"), true, TIMEOUT)
+        .await
+        .unwrap();
+
+    let written = fs::read_to_string(&path).unwrap();
+    let long_term = &written[written.find("[work-long-term]").unwrap()..];
+    assert!(long_term.contains(&format!("aws_access_key_id={ACCESS_KEY_ID}")), "{written}");
+    assert!(long_term.contains("aws_mfa_device=GAHT12345678"));
+}
+
+#[tokio::test]
+async fn unattended_migration_moves_the_keys_but_leaves_the_device_missing() {
+    let (_dir, path) = credentials_path();
+    let configured = format!(
+        "[work]\naws_access_key_id = {ACCESS_KEY_ID}\naws_secret_access_key = {SECRET_ACCESS_KEY}\n"
+    );
+    fs::write(&path, &configured).unwrap();
+    let mut questions = Questions::new("".as_bytes(), Vec::new());
+
+    let error = wizard::migrate_keys(
+        &mut questions,
+        Some(path.clone()),
+        &Profile::new("work"),
+        false,
+        TIMEOUT,
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(Failure::of(&error), Some(Failure::Config));
+    assert!(format!("{error:#}").contains("`aws-mfa --fix`"));
+    assert!(fs::read_to_string(&path).unwrap().contains("[work-long-term]"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn init_of_a_set_up_profile_only_tightens_the_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (_dir, path) = credentials_path();
+    fs::write(&path, common::WORK_LONG_TERM).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    // Nothing is asked, so the closed input isn't read.
+    let mut questions = Questions::new("".as_bytes(), Vec::new());
+
+    wizard::init(&mut questions, Some(path.clone()), &Profile::new("work"), false, TIMEOUT)
+        .await
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), common::WORK_LONG_TERM);
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn init_moves_the_default_keys_to_the_long_term_section_privately() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (_dir, path) = credentials_path();
+    let configured = format!(
+        "[default]\naws_access_key_id = {ACCESS_KEY_ID}\n\
+         aws_secret_access_key = {SECRET_ACCESS_KEY}\nregion = eu-west-1\n"
+    );
+    fs::write(&path, &configured).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    // Move the keys, skip the IAM lookup and enter the device.
+    let mut output = Vec::new();
+    let mut questions = Questions::new("y\nn\nGAHT12345678\n".as_bytes(), &mut output);
+
+    wizard::init(&mut questions, Some(path.clone()), &Profile::default(), false, TIMEOUT)
+        .await
+        .unwrap();
+
+    let written = fs::read_to_string(&path).unwrap();
+    let (session, long_term) = written.split_at(written.find("[default-long-term]").unwrap());
+    // Other settings of the section stay where aws configure put them.
+    assert_eq!(session, "[default]\nregion = eu-west-1\n\n");
+    assert!(long_term.contains(&format!("aws_access_key_id={ACCESS_KEY_ID}")), "{written}");
+    assert!(long_term.contains(&format!("aws_secret_access_key={SECRET_ACCESS_KEY}")));
+    assert!(long_term.contains("aws_mfa_device=GAHT12345678"));
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Move them there?"), "{output}");
+}